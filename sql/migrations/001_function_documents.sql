@@ -0,0 +1,2 @@
+-- the original and updated rows are stored as json documents
+ALTER TABLE function MODIFY original LONGTEXT, MODIFY updated LONGTEXT;
@@ -0,0 +1,4 @@
+-- every applied disguise gets a stable public id
+ALTER TABLE disguise ADD COLUMN public_id CHAR(36) NULL AFTER disguise_id;
+UPDATE disguise SET public_id = UUID() WHERE public_id IS NULL;
+ALTER TABLE disguise MODIFY public_id CHAR(36) NOT NULL, ADD UNIQUE (public_id);
@@ -0,0 +1,13 @@
+-- the named and versioned disguise specifications
+CREATE TABLE IF NOT EXISTS specification (
+    specification_id INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    name VARCHAR(64) NOT NULL,
+    version INT NOT NULL,
+    transformations LONGTEXT NOT NULL,
+    reversible BOOLEAN NOT NULL DEFAULT TRUE,
+    time VARCHAR(64),
+    UNIQUE (name, version)
+);
+
+-- the functions of the irreversible disguises have no original state
+ALTER TABLE function MODIFY original LONGTEXT NULL;
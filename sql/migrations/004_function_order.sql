@@ -0,0 +1,2 @@
+-- the functions are recovered in the reverse order of their insertion
+ALTER TABLE function ADD COLUMN function_id INT NOT NULL AUTO_INCREMENT PRIMARY KEY FIRST;
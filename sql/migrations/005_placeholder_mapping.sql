@@ -0,0 +1,10 @@
+-- the placeholders generated for every decorrelated row of a disguise
+-- the vaults generated before keep using their shared placeholder
+CREATE TABLE IF NOT EXISTS placeholder_mapping (
+    disguise_id INT NOT NULL,
+    table_name VARCHAR(64),
+    predicate TEXT,
+    placeholder_table VARCHAR(64),
+    placeholder_predicate TEXT,
+    INDEX (disguise_id)
+);
@@ -0,0 +1,24 @@
+-- the placeholders owned by the vaults, which are collected when no row refers to them
+CREATE TABLE IF NOT EXISTS owned_placeholder (
+    vault_id VARCHAR(64),
+    disguise_id INT NULL,
+    placeholder_table VARCHAR(64),
+    placeholder_predicate TEXT,
+    time VARCHAR(64),
+    INDEX (disguise_id)
+);
+
+-- the shared placeholders of the existing vaults
+INSERT INTO owned_placeholder (vault_id, disguise_id, placeholder_table, placeholder_predicate, time)
+SELECT vault_id, NULL,
+    JSON_UNQUOTE(JSON_EXTRACT(placeholder_info, '$.table')),
+    JSON_UNQUOTE(JSON_EXTRACT(placeholder_info, '$.pred')),
+    NOW()
+FROM vault
+WHERE placeholder_info IS NOT NULL;
+
+-- the placeholders generated for the decorrelated rows
+INSERT INTO owned_placeholder (vault_id, disguise_id, placeholder_table, placeholder_predicate, time)
+SELECT disguise.vault_id, placeholder_mapping.disguise_id,
+    placeholder_mapping.placeholder_table, placeholder_mapping.placeholder_predicate, disguise.time
+FROM placeholder_mapping JOIN disguise ON disguise.disguise_id = placeholder_mapping.disguise_id;
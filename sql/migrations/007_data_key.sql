@@ -0,0 +1,21 @@
+-- the data keys of the disguises, wrapped by the keys of their vaults
+-- the originals of a disguise could not be decrypted once its data key is destroyed
+CREATE TABLE IF NOT EXISTS data_key (
+    disguise_id INT NOT NULL PRIMARY KEY,
+    vault_id VARCHAR(64),
+    wrapped_key TEXT NOT NULL,
+    time VARCHAR(64)
+);
+
+-- the proof of the cleared disguises and their destroyed data keys
+CREATE TABLE IF NOT EXISTS tombstone (
+    tombstone_id INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    public_id CHAR(36) NOT NULL,
+    vault_id VARCHAR(64),
+    disguise_type VARCHAR(64),
+    functions INT,
+    key_fingerprint CHAR(64) NULL,
+    method VARCHAR(32),
+    time VARCHAR(64),
+    INDEX (vault_id)
+);
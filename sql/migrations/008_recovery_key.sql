@@ -0,0 +1,6 @@
+-- the recovery key pairs of the vaults which are only recovered with the user's secret
+-- the private keys are encrypted by the keys derived from the secrets
+ALTER TABLE vault
+    ADD COLUMN recovery_public_key TEXT NULL,
+    ADD COLUMN recovery_private_key TEXT NULL,
+    ADD COLUMN recovery_salt VARCHAR(64) NULL;
@@ -0,0 +1,9 @@
+-- the api keys of the server, only the hashes of their secrets are stored
+CREATE TABLE IF NOT EXISTS api_key (
+    key_id CHAR(36) NOT NULL PRIMARY KEY,
+    name VARCHAR(64),
+    key_hash CHAR(64) NOT NULL,
+    role VARCHAR(16) NOT NULL,
+    time VARCHAR(64),
+    revoked BOOLEAN NOT NULL DEFAULT FALSE
+);
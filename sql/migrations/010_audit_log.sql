@@ -0,0 +1,26 @@
+-- the append-only audit log of the disguise operations
+-- every entry is chained to the one before it by "prev_hash"
+CREATE TABLE IF NOT EXISTS audit_log (
+    audit_id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    actor VARCHAR(128),
+    operation VARCHAR(32) NOT NULL,
+    vault_id VARCHAR(64),
+    disguise_ids TEXT,
+    summary TEXT,
+    row_count BIGINT,
+    outcome VARCHAR(16) NOT NULL,
+    message TEXT,
+    time VARCHAR(64) NOT NULL,
+    prev_hash CHAR(64) NOT NULL,
+    hash CHAR(64) NOT NULL,
+    -- only one entry could follow each entry, so the concurrent entries could not fork the chain
+    UNIQUE (prev_hash),
+    INDEX (vault_id),
+    INDEX (time)
+);
+
+-- the entries could not be changed or deleted by the server
+CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log
+    FOR EACH ROW SIGNAL SQLSTATE '45000' SET MESSAGE_TEXT = 'The audit log is append-only.';
+CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+    FOR EACH ROW SIGNAL SQLSTATE '45000' SET MESSAGE_TEXT = 'The audit log is append-only.';
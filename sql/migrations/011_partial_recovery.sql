@@ -0,0 +1,3 @@
+-- the functions could be recovered one by one,
+-- and the disguise is marked when a part of it has been recovered
+ALTER TABLE disguise ADD COLUMN status VARCHAR(32) NOT NULL DEFAULT 'applied';
@@ -0,0 +1,13 @@
+-- the disguises are kept after they are recovered or cleared,
+-- the recovered functions are marked without their original and updated state,
+-- so that a recovery stopped halfway is resumed from the unmarked functions
+ALTER TABLE function ADD COLUMN recovered_time VARCHAR(64) NULL;
+
+-- the states of the disguises with the time they were entered
+CREATE TABLE IF NOT EXISTS disguise_history (
+    history_id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    disguise_id INT NOT NULL,
+    status VARCHAR(32) NOT NULL,
+    time VARCHAR(64) NOT NULL,
+    INDEX (disguise_id)
+);
@@ -0,0 +1,2 @@
+-- the rows changed by more than one disguise of the vault are found by their tables and predicates
+ALTER TABLE function ADD INDEX function_row (table_name, predicate(255));
@@ -0,0 +1,3 @@
+-- the data keys could be kept in the key store outside the backed-up vault database,
+-- then only the fingerprints of the wrapped keys are kept here
+ALTER TABLE data_key MODIFY wrapped_key TEXT NULL, ADD COLUMN key_fingerprint CHAR(64) NULL;
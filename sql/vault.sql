@@ -0,0 +1,124 @@
+-- the schema of the vault database (VAULT_DATABASE_URL)
+-- the existing databases are upgraded by the scripts in "migrations" in order
+
+CREATE TABLE IF NOT EXISTS vault (
+    vault_id VARCHAR(64) NOT NULL PRIMARY KEY,
+    email VARCHAR(255),
+    placeholder_info TEXT,
+    recovery_public_key TEXT NULL,
+    recovery_private_key TEXT NULL,
+    recovery_salt VARCHAR(64) NULL
+);
+
+CREATE TABLE IF NOT EXISTS disguise (
+    disguise_id INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    public_id CHAR(36) NOT NULL UNIQUE,
+    time VARCHAR(64),
+    vault_id VARCHAR(64),
+    disguise_type VARCHAR(64),
+    status VARCHAR(32) NOT NULL DEFAULT 'applied',
+    INDEX (vault_id, disguise_type)
+);
+
+CREATE TABLE IF NOT EXISTS function (
+    function_id INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    disguise_id INT NOT NULL,
+    function_type VARCHAR(32),
+    table_name VARCHAR(64),
+    predicate TEXT,
+    original LONGTEXT,
+    updated LONGTEXT,
+    recovered_time VARCHAR(64) NULL,
+    INDEX (disguise_id),
+    INDEX function_row (table_name, predicate(255))
+);
+
+CREATE TABLE IF NOT EXISTS disguise_history (
+    history_id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    disguise_id INT NOT NULL,
+    status VARCHAR(32) NOT NULL,
+    time VARCHAR(64) NOT NULL,
+    INDEX (disguise_id)
+);
+
+CREATE TABLE IF NOT EXISTS specification (
+    specification_id INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    name VARCHAR(64) NOT NULL,
+    version INT NOT NULL,
+    transformations LONGTEXT NOT NULL,
+    reversible BOOLEAN NOT NULL DEFAULT TRUE,
+    time VARCHAR(64),
+    UNIQUE (name, version)
+);
+
+CREATE TABLE IF NOT EXISTS placeholder_mapping (
+    disguise_id INT NOT NULL,
+    table_name VARCHAR(64),
+    predicate TEXT,
+    placeholder_table VARCHAR(64),
+    placeholder_predicate TEXT,
+    INDEX (disguise_id)
+);
+
+CREATE TABLE IF NOT EXISTS owned_placeholder (
+    vault_id VARCHAR(64),
+    disguise_id INT NULL,
+    placeholder_table VARCHAR(64),
+    placeholder_predicate TEXT,
+    time VARCHAR(64),
+    INDEX (disguise_id)
+);
+
+CREATE TABLE IF NOT EXISTS data_key (
+    disguise_id INT NOT NULL PRIMARY KEY,
+    vault_id VARCHAR(64),
+    wrapped_key TEXT NULL,
+    key_fingerprint CHAR(64) NULL,
+    time VARCHAR(64)
+);
+
+CREATE TABLE IF NOT EXISTS tombstone (
+    tombstone_id INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    public_id CHAR(36) NOT NULL,
+    vault_id VARCHAR(64),
+    disguise_type VARCHAR(64),
+    functions INT,
+    key_fingerprint CHAR(64) NULL,
+    method VARCHAR(32),
+    time VARCHAR(64),
+    INDEX (vault_id)
+);
+
+CREATE TABLE IF NOT EXISTS api_key (
+    key_id CHAR(36) NOT NULL PRIMARY KEY,
+    name VARCHAR(64),
+    key_hash CHAR(64) NOT NULL,
+    role VARCHAR(16) NOT NULL,
+    time VARCHAR(64),
+    revoked BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE TABLE IF NOT EXISTS audit_log (
+    audit_id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    actor VARCHAR(128),
+    operation VARCHAR(32) NOT NULL,
+    vault_id VARCHAR(64),
+    disguise_ids TEXT,
+    summary TEXT,
+    row_count BIGINT,
+    outcome VARCHAR(16) NOT NULL,
+    message TEXT,
+    time VARCHAR(64) NOT NULL,
+    prev_hash CHAR(64) NOT NULL,
+    hash CHAR(64) NOT NULL,
+    -- only one entry could follow each entry, so the concurrent entries could not fork the chain
+    UNIQUE (prev_hash),
+    INDEX (vault_id),
+    INDEX (time)
+);
+
+-- the entries could not be changed or deleted by the server
+CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log
+    FOR EACH ROW SIGNAL SQLSTATE '45000' SET MESSAGE_TEXT = 'The audit log is append-only.';
+CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+    FOR EACH ROW SIGNAL SQLSTATE '45000' SET MESSAGE_TEXT = 'The audit log is append-only.';
@@ -0,0 +1,70 @@
+use std::{env, io};
+use chrono::Local;
+use sqlx::mysql::MySqlPoolOptions;
+use dotenv::dotenv;
+use crate::dbaccess::api_key::{create_api_key_db, get_api_keys_db, revoke_api_key_db};
+use crate::models::api_key::{ApiKey, Role};
+
+//the api keys only use a part of the shared modules
+#[allow(dead_code)]
+#[path = "../models/mod.rs"]
+mod models;
+#[allow(dead_code)]
+#[path = "../dbaccess/mod.rs"]
+mod dbaccess;
+#[allow(dead_code)]
+#[path = "../errors.rs"]
+mod error;
+#[allow(dead_code)]
+#[path = "../state.rs"]
+mod state;
+
+const USAGE: &str = "Usage: api_key (create <name> <support|app|admin> | list | revoke <key id>)";
+
+///
+/// manage the api keys of the server in the vault database,
+/// the token of the new key is only shown once
+///
+#[actix_rt::main]
+async fn main() -> io::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    if !matches!(args.as_slice(), ["create", _, _] | ["list"] | ["revoke", _]) {
+        eprintln!("{}", USAGE);
+        std::process::exit(2);
+    }
+    //load the env variables and connect the vault database like the server
+    dotenv().ok();
+    let vault_database_url = env::var("VAULT_DATABASE_URL").expect("VAULT_DATABASE_URL is not set yet.");
+    let vault_db = MySqlPoolOptions::new().connect(&vault_database_url).await.unwrap();
+
+    let result = async {
+        match args.as_slice() {
+            ["create", name, role] => {
+                let (api_key, token) = ApiKey::generate(name, Role::parse(role)?, Local::now().to_string());
+                create_api_key_db(&vault_db, &api_key).await?;
+                println!("The api key \"{}\" has been created with the role \"{}\".", api_key.key_id.unwrap(), role);
+                println!("Token (it will not be shown again): {}", token);
+            }
+            ["revoke", key_id] => println!("{}", revoke_api_key_db(&vault_db, key_id).await?),
+            _ => {
+                for api_key in get_api_keys_db(&vault_db).await? {
+                    println!(
+                        "{}\t{}\t{}\t{}{}",
+                        api_key.key_id.unwrap_or_default(),
+                        api_key.name.unwrap_or_default(),
+                        api_key.role.unwrap_or_default(),
+                        api_key.time.unwrap_or_default(),
+                        if api_key.revoked.unwrap_or(false) { "\trevoked" } else { "" }
+                    );
+                }
+            }
+        }
+        Ok::<_, error::MyError>(())
+    }.await;
+    if let Err(err) = result {
+        eprintln!("The api keys could not be managed: {}", err);
+        std::process::exit(1);
+    }
+    Ok(())
+}
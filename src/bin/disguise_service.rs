@@ -4,7 +4,8 @@ use actix_web::*;
 use sqlx::mysql::MySqlPoolOptions;
 use dotenv::dotenv;
 use crate::error::MyError::InvalidInput;
-use crate::routers::{disguise_routes, vault_routes};
+use crate::models::crypto::{KeyStore, MasterKey};
+use crate::routers::{audit_routes, disguise_routes, placeholder_routes, schema_routes, specification_routes, vault_routes};
 use crate::state::AppState;
 
 
@@ -20,6 +21,8 @@ mod models;
 mod dbaccess;
 #[path = "../errors.rs"]
 mod error;
+#[path = "../middleware.rs"]
+mod middleware;
 
 #[actix_rt::main]
 async fn main() -> io::Result<()> {
@@ -32,10 +35,36 @@ async fn main() -> io::Result<()> {
     //then build the vault database
     let vault_database_url = env::var("VAULT_DATABASE_URL").expect("VAULT_DATABASE_URL is not set yet.");
     let vault_db = MySqlPoolOptions::new().connect(&vault_database_url).await.unwrap();
+    //the raw sql predicates are refused unless they are allowed explicitly
+    let allow_raw_sql = env::var("ALLOW_RAW_SQL").map(|value| value == "true").unwrap_or(false);
+    //the original rows in the vaults are encrypted by the keys derived from the master key
+    let master_key = MasterKey::load().expect("The master key of the vaults is not correct.");
+    //the vaults are only stored as plaintext if it is allowed explicitly
+    let allow_plaintext_vault = env::var("ALLOW_PLAINTEXT_VAULT").map(|value| value == "true").unwrap_or(false);
+    if master_key.is_none() {
+        if !allow_plaintext_vault {
+            panic!("VAULT_MASTER_KEY is not set yet, or set ALLOW_PLAINTEXT_VAULT=true to store the vaults as plaintext.");
+        }
+        println!("VAULT_MASTER_KEY is not set, the vaults will be stored as plaintext.");
+    }
+    //the data keys could only be shredded from the key store outside the vault database
+    let key_store = KeyStore::load().expect("The key store of the data keys could not be opened.");
+    if key_store.is_none() {
+        println!("VAULT_KEY_STORE is not set, the data keys will be kept in the vault database.");
+    }
+    //the requests require the api keys unless the authentication is turned off explicitly
+    let api_auth = env::var("API_AUTH").map(|value| value != "false").unwrap_or(true);
+    if !api_auth {
+        println!("API_AUTH is false, the requests will not be authenticated.");
+    }
     //put the target and vault database pool in the state
     let shared_data = web::Data::new(AppState {
         vault_db,
         target_db,
+        allow_raw_sql,
+        master_key,
+        key_store,
+        api_auth,
     });
     //set the app state and the invalid input
     let app = move || {
@@ -47,6 +76,10 @@ async fn main() -> io::Result<()> {
             }))
             .configure(disguise_routes)
             .configure(vault_routes)
+            .configure(specification_routes)
+            .configure(schema_routes)
+            .configure(placeholder_routes)
+            .configure(audit_routes)
     };
     println!("The server has been started.");
     HttpServer::new(app).bind("127.0.0.1:3000")?.run().await
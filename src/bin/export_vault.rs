@@ -0,0 +1,88 @@
+use std::{env, fs, io};
+use std::path::PathBuf;
+use sqlx::mysql::MySqlPoolOptions;
+use dotenv::dotenv;
+use crate::dbaccess::export::{export_vault_db, find_export_vault_db};
+use crate::models::crypto::{KeyStore, MasterKey, VaultKeys};
+use crate::models::export::ExportQuery;
+
+//the export only uses a part of the shared modules
+#[allow(dead_code)]
+#[path = "../state.rs"]
+mod state;
+#[allow(dead_code)]
+#[path = "../models/mod.rs"]
+mod models;
+#[allow(dead_code)]
+#[path = "../dbaccess/mod.rs"]
+mod dbaccess;
+#[allow(dead_code)]
+#[path = "../errors.rs"]
+mod error;
+
+const USAGE: &str = "Usage: export_vault (--vault-id <id> | --email <email>) [--secret <recovery secret>] [--output <directory>]";
+
+///
+/// export all the original rows kept in one vault into a directory,
+/// "vault.json" has the whole export and every table has its own csv file
+///
+#[actix_rt::main]
+async fn main() -> io::Result<()> {
+    //read the vault and the output directory from the arguments
+    let mut query = ExportQuery { vault_id: None, email: None };
+    let mut output = None;
+    let mut secret = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next();
+        match (arg.as_str(), value) {
+            ("--vault-id", Some(value)) => query.vault_id = Some(value),
+            ("--email", Some(value)) => query.email = Some(value),
+            ("--output", Some(value)) => output = Some(PathBuf::from(value)),
+            ("--secret", Some(value)) => secret = Some(value),
+            _ => {
+                eprintln!("{}", USAGE);
+                std::process::exit(2);
+            }
+        }
+    }
+    if query.vault_id.is_none() && query.email.is_none() {
+        eprintln!("{}", USAGE);
+        std::process::exit(2);
+    }
+    //load the env variables and connect the databases like the server
+    dotenv().ok();
+    let target_database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set yet.");
+    let target_db = MySqlPoolOptions::new().connect(&target_database_url).await.unwrap();
+    let vault_database_url = env::var("VAULT_DATABASE_URL").expect("VAULT_DATABASE_URL is not set yet.");
+    let vault_db = MySqlPoolOptions::new().connect(&vault_database_url).await.unwrap();
+    let master_key = MasterKey::load().expect("The master key of the vaults is not correct.");
+    let key_store = KeyStore::load().expect("The key store of the data keys could not be opened.");
+
+    let export = async {
+        let vault = find_export_vault_db(&vault_db, &query).await?;
+        let keys = VaultKeys::opening(master_key.as_ref(), key_store.as_ref(), &vault, secret.as_deref())?;
+        let mut target_tx = target_db.begin().await?;
+        let export = export_vault_db(&mut target_tx, &vault_db, vault, &keys).await?;
+        target_tx.rollback().await?;
+        Ok::<_, error::MyError>(export)
+    }.await;
+    let export = match export {
+        Ok(export) => export,
+        Err(err) => {
+            eprintln!("The vault could not be exported: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let vault_id = export.vault.as_ref().and_then(|vault| vault.vault_id.clone()).unwrap_or_default();
+    let output = output.unwrap_or_else(|| PathBuf::from(format!("export_{}", vault_id)));
+    fs::create_dir_all(&output)?;
+    fs::write(output.join("vault.json"), serde_json::to_string_pretty(&export).unwrap())?;
+    for table in export.tables.as_ref().unwrap() {
+        //the table name could not leave the output directory
+        let file_name = format!("{}.csv", table.table_name.as_ref().unwrap().replace(['/', '\\'], "_"));
+        fs::write(output.join(file_name), table.to_csv())?;
+    }
+    println!("The vault \"{}\" has been exported to {}.", vault_id, output.display());
+    Ok(())
+}
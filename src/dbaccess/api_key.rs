@@ -0,0 +1,99 @@
+use sqlx::MySqlPool;
+use crate::error::MyError;
+use crate::models::api_key::ApiKey;
+
+///
+/// store the new api key with the hash of its secret
+///
+/// # Arguments
+///
+/// * `vault_pool`: the server's database
+/// * `api_key`: the api key to store
+///
+/// returns: Result<String, MyError>
+///
+//only the api_key tool manages the keys
+#[allow(dead_code)]
+pub async fn create_api_key_db(
+    vault_pool: &MySqlPool,
+    api_key: &ApiKey,
+) -> Result<String, MyError> {
+    let sql = "INSERT INTO api_key (key_id, name, key_hash, role, time, revoked) VALUES (?, ?, ?, ?, ?, ?)";
+    sqlx::query(sql)
+        .bind(api_key.key_id.as_deref())
+        .bind(api_key.name.as_deref())
+        .bind(api_key.key_hash.as_deref())
+        .bind(api_key.role.as_deref())
+        .bind(api_key.time.as_deref())
+        .bind(api_key.revoked.unwrap_or(false))
+        .execute(vault_pool)
+        .await?;
+    Ok("The api key has been created.".into())
+}
+
+///
+/// get the api key by its id
+///
+/// # Arguments
+///
+/// * `vault_pool`: the server's database
+/// * `key_id`: the id of the api key
+///
+/// returns: Result<Option<ApiKey>, MyError>
+///
+pub async fn get_api_key_db(
+    vault_pool: &MySqlPool,
+    key_id: &str,
+) -> Result<Option<ApiKey>, MyError> {
+    let sql = "SELECT key_id, name, key_hash, role, time, revoked FROM api_key WHERE key_id=?";
+    let api_key: Option<ApiKey> = sqlx::query_as(sql)
+        .bind(key_id)
+        .fetch_optional(vault_pool)
+        .await?;
+    Ok(api_key)
+}
+
+///
+/// get all the api keys
+///
+/// # Arguments
+///
+/// * `vault_pool`: the server's database
+///
+/// returns: Result<Vec<ApiKey, Global>, MyError>
+///
+//only the api_key tool manages the keys
+#[allow(dead_code)]
+pub async fn get_api_keys_db(vault_pool: &MySqlPool) -> Result<Vec<ApiKey>, MyError> {
+    let sql = "SELECT key_id, name, key_hash, role, time, revoked FROM api_key ORDER BY time";
+    let api_keys: Vec<ApiKey> = sqlx::query_as(sql)
+        .fetch_all(vault_pool)
+        .await?;
+    Ok(api_keys)
+}
+
+///
+/// revoke the api key, it is kept to show who had the access
+///
+/// # Arguments
+///
+/// * `vault_pool`: the server's database
+/// * `key_id`: the id of the api key
+///
+/// returns: Result<String, MyError>
+///
+//only the api_key tool manages the keys
+#[allow(dead_code)]
+pub async fn revoke_api_key_db(
+    vault_pool: &MySqlPool,
+    key_id: &str,
+) -> Result<String, MyError> {
+    let res = sqlx::query("UPDATE api_key SET revoked=TRUE WHERE key_id=?")
+        .bind(key_id)
+        .execute(vault_pool)
+        .await?;
+    if res.rows_affected() == 0 {
+        return Err(MyError::NotFound("The api key is not found.".into()));
+    }
+    Ok("The api key has been revoked.".into())
+}
@@ -0,0 +1,132 @@
+use sqlx::{MySql, MySqlPool, Row, Transaction};
+use crate::error::MyError;
+use crate::models::audit::{AuditEntry, AuditQuery};
+
+/// the entries returned by one query at most
+const MAX_AUDIT_ENTRIES: i64 = 1000;
+/// the times to append the entry after the others have been chained to the same entry
+const APPEND_ATTEMPTS: usize = 5;
+
+///
+/// append the entry to the end of the audit log
+/// only one entry could be chained to each entry by the unique "prev_hash",
+/// so the entry appended at the same time as another one is chained again to the new last entry
+///
+/// # Arguments
+///
+/// * `vault_tx`: the transaction opened on the server's database
+/// * `entry`: the entry of the operation, which is sealed by the last hash
+///
+/// returns: Result<u64, MyError>
+///
+pub async fn append_audit_db(
+    vault_tx: &mut Transaction<'_, MySql>,
+    entry: &mut AuditEntry,
+) -> Result<u64, MyError> {
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        //the locking read gets the last committed entry without blocking the other appends
+        let prev_hash: Option<String> = sqlx::query("SELECT hash FROM audit_log ORDER BY audit_id DESC LIMIT 1 LOCK IN SHARE MODE")
+            .fetch_optional(&mut *vault_tx)
+            .await?
+            .map(|row| row.get("hash"));
+        entry.seal(prev_hash);
+        let sql = "INSERT INTO audit_log (actor, operation, vault_id, disguise_ids, summary, row_count, outcome, message, time, prev_hash, hash) \
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
+        let inserted = sqlx::query(sql)
+            .bind(entry.actor.as_deref())
+            .bind(entry.operation.as_deref())
+            .bind(entry.vault_id.as_deref())
+            .bind(entry.disguise_ids.as_deref())
+            .bind(entry.summary.as_deref())
+            .bind(entry.row_count)
+            .bind(entry.outcome.as_deref())
+            .bind(entry.message.as_deref())
+            .bind(entry.time.as_deref())
+            .bind(entry.prev_hash.as_deref())
+            .bind(entry.hash.as_deref())
+            .execute(&mut *vault_tx)
+            .await;
+        match inserted {
+            Ok(result) => {
+                let audit_id = result.last_insert_id();
+                entry.audit_id = Some(audit_id as i64);
+                return Ok(audit_id);
+            }
+            //another entry has been chained to the same entry
+            Err(sqlx::Error::Database(err)) if attempts < APPEND_ATTEMPTS && err.code().as_deref() == Some("23000") => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+///
+/// append the entry in its own transaction,
+/// which records the failed operations whose transactions were rolled back
+///
+/// # Arguments
+///
+/// * `vault_pool`: the server's database
+/// * `entry`: the entry of the operation
+///
+/// returns: Result<u64, MyError>
+///
+pub async fn record_audit_db(
+    vault_pool: &MySqlPool,
+    entry: &mut AuditEntry,
+) -> Result<u64, MyError> {
+    let mut vault_tx = vault_pool.begin().await?;
+    let audit_id = append_audit_db(&mut vault_tx, entry).await?;
+    vault_tx.commit().await?;
+    Ok(audit_id)
+}
+
+///
+/// get the audit entries of the vault and in the time range,
+/// the earliest first
+///
+/// # Arguments
+///
+/// * `vault_pool`: the server's database
+/// * `query`: the vault, the time range and the limit of the entries
+///
+/// returns: Result<Vec<AuditEntry, Global>, MyError>
+///
+pub async fn get_audit_entries_db(
+    vault_pool: &MySqlPool,
+    query: &AuditQuery,
+) -> Result<Vec<AuditEntry>, MyError> {
+    let sql = "SELECT audit_id, actor, operation, vault_id, disguise_ids, summary, row_count, outcome, message, time, prev_hash, hash \
+        FROM audit_log WHERE (? IS NULL OR vault_id=?) AND (? IS NULL OR time>=?) AND (? IS NULL OR time<=?) \
+        ORDER BY audit_id LIMIT ?";
+    let entries: Vec<AuditEntry> = sqlx::query_as(sql)
+        .bind(query.vault_id.as_deref())
+        .bind(query.vault_id.as_deref())
+        .bind(query.from.as_deref())
+        .bind(query.from.as_deref())
+        .bind(query.to.as_deref())
+        .bind(query.to.as_deref())
+        .bind(query.limit.unwrap_or(MAX_AUDIT_ENTRIES).clamp(1, MAX_AUDIT_ENTRIES))
+        .fetch_all(vault_pool)
+        .await?;
+    Ok(entries)
+}
+
+///
+/// get the whole audit log to check its chain
+///
+/// # Arguments
+///
+/// * `vault_pool`: the server's database
+///
+/// returns: Result<Vec<AuditEntry, Global>, MyError>
+///
+pub async fn get_audit_log_db(vault_pool: &MySqlPool) -> Result<Vec<AuditEntry>, MyError> {
+    let sql = "SELECT audit_id, actor, operation, vault_id, disguise_ids, summary, row_count, outcome, message, time, prev_hash, hash \
+        FROM audit_log ORDER BY audit_id";
+    let entries: Vec<AuditEntry> = sqlx::query_as(sql)
+        .fetch_all(vault_pool)
+        .await?;
+    Ok(entries)
+}
@@ -0,0 +1,92 @@
+use chrono::Local;
+use sqlx::{MySql, MySqlPool, Transaction};
+use crate::dbaccess::target::decode_original_db;
+use crate::dbaccess::vault::{count_functions_db, download_disguise_db, get_data_key_db, get_vault_by_email_db, get_vault_by_id_db, list_disguises_db};
+use crate::error::MyError;
+use crate::models::crypto::VaultKeys;
+use crate::models::export::{ExportQuery, VaultExport};
+use crate::models::vault::{DisguiseSummary, Vault};
+
+///
+/// find the vault to export by its id, or by its owner's email
+///
+/// # Arguments
+///
+/// * `vault_pool`: the server's database
+/// * `query`: the id or the email of the vault
+///
+/// returns: Result<Vault, MyError>
+///
+pub async fn find_export_vault_db(
+    vault_pool: &MySqlPool,
+    query: &ExportQuery,
+) -> Result<Vault, MyError> {
+    match (&query.vault_id, &query.email) {
+        (Some(vault_id), _) => get_vault_by_id_db(vault_pool, vault_id).await,
+        (None, Some(email)) => get_vault_by_email_db(vault_pool, email).await,
+        (None, None) => Err(MyError::InvalidInput("The vault id or the email is required.".into())),
+    }
+}
+
+///
+/// export all the original rows kept in the vault
+/// with the metadata of all its disguises
+/// the original rows are decoded as they are recovered,
+/// so they are decrypted by the data keys of the disguises
+/// and the old format is read with the fields of the table
+///
+/// # Arguments
+///
+/// * `target_tx`: the transaction opened on the application's database
+/// * `vault_pool`: the server's database
+/// * `vault`: the vault to export
+/// * `keys`: the keys to unwrap the data keys of the disguises
+///
+/// returns: Result<VaultExport, MyError>
+///
+pub async fn export_vault_db(
+    target_tx: &mut Transaction<'_, MySql>,
+    vault_pool: &MySqlPool,
+    vault: Vault,
+    keys: &VaultKeys,
+) -> Result<VaultExport, MyError> {
+    let vault_id = vault.vault_id.clone().unwrap();
+    let mut export = VaultExport::new(vault.into(), Local::now().to_string());
+    let mut unavailable = 0;
+    //the earliest disguise is exported first
+    let mut disguises = list_disguises_db(vault_pool, vault_id.as_str()).await?;
+    disguises.reverse();
+    for disguise in disguises {
+        let public_id = disguise.public_id.clone().unwrap();
+        let functions = count_functions_db(vault_pool, disguise.disguise_id.unwrap()).await?;
+        export.disguises.get_or_insert_with(Vec::new).push(DisguiseSummary {
+            disguise_id: disguise.public_id,
+            disguise_type: disguise.disguise_type,
+            time: disguise.time,
+            status: disguise.status,
+            functions: Some(functions)
+        });
+        let disguise = download_disguise_db(vault_pool, public_id.as_str()).await?;
+        let data_key = get_data_key_db(vault_pool, disguise.disguise_id.unwrap(), keys).await?;
+        for function in disguise.functions.unwrap() {
+            //the recovered rows are back in the application
+            if function.recovered_time.is_some() {
+                continue;
+            }
+            //the irreversible disguises do not keep the original rows
+            if function.original.is_none() {
+                unavailable += 1;
+                continue;
+            }
+            let original = decode_original_db(target_tx, &function, data_key.as_ref()).await?;
+            export.add_row(
+                function.table_name.as_ref().unwrap(),
+                Some(public_id.clone()),
+                function.function_type.clone(),
+                &original
+            );
+        }
+    }
+    export.unavailable = Some(unavailable);
+    Ok(export)
+}
@@ -1,2 +1,8 @@
 pub mod vault;
-pub mod target;
\ No newline at end of file
+pub mod target;
+pub mod specification;
+pub mod schema;
+pub mod placeholder;
+pub mod export;
+pub mod api_key;
+pub mod audit;
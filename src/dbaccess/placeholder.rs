@@ -0,0 +1,333 @@
+use std::collections::HashSet;
+use serde_json::Value;
+use sqlx::{MySql, Row, Transaction};
+use crate::dbaccess::schema::get_foreign_keys_db;
+use crate::dbaccess::target::bind_values;
+use crate::error::MyError;
+use crate::models::placeholder::{GeneratePlaceHolder, Generator, OwnedPlaceholder, PlaceholderMapping, PlaceholderReport};
+use crate::models::predicate::{quote_identifier, Predicate, SqlFragment};
+use crate::models::schema::find_dependents;
+use crate::models::target::Target;
+
+/// the times to generate another value for the unique column
+const UNIQUE_ATTEMPTS: usize = 10;
+
+///
+/// insert a new placeholder by the template of the vault
+/// the values of the template's columns are generated,
+/// and the generated values of the unique columns are not used yet
+/// the literal "fields" and "field_values" of the old template
+/// are only accepted when the server allows the raw sql
+///
+/// # Arguments
+///
+/// * `target_tx`: the transaction opened on the application's database
+/// * `placeholder`: the template of the placeholder
+/// * `owner`: the original owner of the decorrelated row
+/// * `allow_raw`: if the literal template is accepted
+///
+/// returns: Result<u64, MyError>
+///
+pub async fn insert_placeholder_db(
+    target_tx: &mut Transaction<'_, MySql>,
+    placeholder: &GeneratePlaceHolder,
+    owner: Option<&Target>,
+    allow_raw: bool,
+) -> Result<u64, MyError> {
+    let table_name = placeholder.table.as_ref()
+        .ok_or_else(|| MyError::InvalidInput("The table of the placeholder is required.".into()))?;
+    let columns = match &placeholder.columns {
+        Some(columns) => columns,
+        //the literal fields and values of the old template
+        None => {
+            if !allow_raw {
+                return Err(MyError::InvalidInput(
+                    "The literal fields of the placeholder are not allowed by the server, the columns are required.".into()
+                ));
+            }
+            let (names, values) = placeholder.literal_values()?;
+            let placeholders = vec!["?"; values.len()].join(", ");
+            let sql = "INSERT INTO ".to_string() + quote_identifier(table_name)?.as_str()
+                + " (" + names.join(", ").as_str() + ") VALUES (" + placeholders.as_str() + ")";
+            let id = bind_values(sqlx::query(sql.as_str()), &values)
+                .execute(&mut *target_tx)
+                .await?
+                .last_insert_id();
+            return Ok(id);
+        }
+    };
+    let unique_columns = get_unique_columns_db(target_tx, table_name).await?;
+    let owner = owner.map(|owner| owner.to_map());
+
+    let mut names = vec![];
+    let mut values = vec![];
+    for column in columns {
+        let name = column.column.as_ref()
+            .ok_or_else(|| MyError::InvalidInput("The column of the placeholder is required.".into()))?;
+        let generator = column.generator.as_ref().unwrap_or(&Generator::Null);
+        let mut value = match generator {
+            Generator::Sequence { start } => next_sequence_db(target_tx, table_name, name, start.unwrap_or(1)).await?,
+            _ => generator.generate(name, owner.as_ref()),
+        };
+        //the unique column gets another value until it is not used
+        if unique_columns.contains(name) && !value.is_null() {
+            let mut attempts = 0;
+            while value_exists_db(target_tx, table_name, name, &value).await? {
+                attempts += 1;
+                value = match generator.regenerate(name, owner.as_ref(), &value) {
+                    Some(value) if attempts < UNIQUE_ATTEMPTS => value,
+                    _ => return Err(MyError::OperationError(
+                        format!("The unique value of the placeholder's column \"{}\" could not be generated.", name)
+                    )),
+                };
+            }
+        }
+        names.push(quote_identifier(name)?);
+        values.push(value);
+    }
+    let placeholders = vec!["?"; values.len()].join(", ");
+    let sql = "INSERT INTO ".to_string() + quote_identifier(table_name)?.as_str()
+        + " (" + names.join(", ").as_str() + ") VALUES (" + placeholders.as_str() + ")";
+    let id = bind_values(sqlx::query(sql.as_str()), &values)
+        .execute(&mut *target_tx)
+        .await?
+        .last_insert_id();
+    Ok(id)
+}
+
+///
+/// get the columns of the table with their own unique index
+///
+/// # Arguments
+///
+/// * `target_tx`: the transaction opened on the application's database
+/// * `table_name`: the name of the table
+///
+/// returns: Result<HashSet<String, RandomState>, MyError>
+///
+pub async fn get_unique_columns_db(
+    target_tx: &mut Transaction<'_, MySql>,
+    table_name: &str,
+) -> Result<HashSet<String>, MyError> {
+    let sql = "SELECT CAST(MAX(COLUMN_NAME) AS CHAR) AS column_name \
+        FROM information_schema.STATISTICS \
+        WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = ? AND NON_UNIQUE = 0 \
+        GROUP BY INDEX_NAME HAVING COUNT(*) = 1";
+    let rows = sqlx::query(sql)
+        .bind(table_name)
+        .fetch_all(&mut *target_tx)
+        .await?;
+    Ok(rows.iter().map(|row| row.get("column_name")).collect())
+}
+
+///
+/// get the next number of the column
+/// which is larger than all the numbers in it
+///
+/// # Arguments
+///
+/// * `target_tx`: the transaction opened on the application's database
+/// * `table_name`: the name of the table
+/// * `column`: the name of the column
+/// * `start`: the first number of the sequence
+///
+/// returns: Result<Value, MyError>
+///
+async fn next_sequence_db(
+    target_tx: &mut Transaction<'_, MySql>,
+    table_name: &str,
+    column: &str,
+    start: i64,
+) -> Result<Value, MyError> {
+    let sql = "SELECT CAST(MAX(".to_string() + quote_identifier(column)?.as_str() + ") AS SIGNED) FROM "
+        + quote_identifier(table_name)?.as_str() + " FOR UPDATE";
+    let row = sqlx::query(sql.as_str())
+        .fetch_one(&mut *target_tx)
+        .await?;
+    let largest: Option<i64> = row.get(0);
+    Ok(Value::from(largest.map_or(start, |largest| start.max(largest + 1))))
+}
+
+///
+/// check if the value has been used in the column
+///
+/// # Arguments
+///
+/// * `target_tx`: the transaction opened on the application's database
+/// * `table_name`: the name of the table
+/// * `column`: the name of the column
+/// * `value`: the value to check
+///
+/// returns: Result<bool, MyError>
+///
+async fn value_exists_db(
+    target_tx: &mut Transaction<'_, MySql>,
+    table_name: &str,
+    column: &str,
+    value: &Value,
+) -> Result<bool, MyError> {
+    let predicate = Predicate::eq(column, value.clone()).render(false)?;
+    Ok(count_rows_db(target_tx, table_name, &predicate).await? > 0)
+}
+
+///
+/// count the rows of the table selected by the predicate
+///
+/// # Arguments
+///
+/// * `target_tx`: the transaction opened on the application's database
+/// * `table_name`: the name of the table
+/// * `predicate`: the rendered predicate of the rows
+///
+/// returns: Result<i64, MyError>
+///
+pub async fn count_rows_db(
+    target_tx: &mut Transaction<'_, MySql>,
+    table_name: &str,
+    predicate: &SqlFragment,
+) -> Result<i64, MyError> {
+    let sql = "SELECT COUNT(*) FROM ".to_string() + quote_identifier(table_name)?.as_str()
+        + " WHERE " + predicate.sql.as_str();
+    let row = bind_values(sqlx::query(sql.as_str()), &predicate.values)
+        .fetch_one(&mut *target_tx)
+        .await?;
+    Ok(row.get(0))
+}
+
+///
+/// register the placeholder generated for the vault
+/// so that it could be collected when it is not used
+///
+/// # Arguments
+///
+/// * `vault_tx`: the transaction opened on the server's database
+/// * `placeholder`: the placeholder owned by the vault
+///
+/// returns: Result<String, MyError>
+///
+pub async fn register_placeholder_db(
+    vault_tx: &mut Transaction<'_, MySql>,
+    placeholder: &OwnedPlaceholder,
+) -> Result<String, MyError> {
+    let sql = "INSERT INTO owned_placeholder (vault_id, disguise_id, placeholder_table, placeholder_predicate, time) VALUES (?, ?, ?, ?, ?)";
+    sqlx::query(sql)
+        .bind(placeholder.vault_id.as_deref())
+        .bind(placeholder.disguise_id)
+        .bind(placeholder.placeholder_table.as_deref())
+        .bind(placeholder.placeholder_predicate.as_deref())
+        .bind(placeholder.time.as_deref())
+        .execute(&mut *vault_tx)
+        .await?;
+    Ok("The placeholder has been registered.".into())
+}
+
+///
+/// count the references to every placeholder owned by the vaults
+/// and remove the orphaned and missing ones if it is required
+/// the placeholder is orphaned when no row refers to it,
+/// and it is neither the shared placeholder of an existing vault
+/// the rows refer to it by the declared foreign keys,
+/// or by the placeholder mappings of the disguises in the vaults
+///
+/// # Arguments
+///
+/// * `target_tx`: the transaction opened on the application's database
+/// * `vault_tx`: the transaction opened on the server's database
+/// * `remove`: if the orphaned and missing placeholders are removed
+///
+/// returns: Result<Vec<PlaceholderReport, Global>, MyError>
+///
+pub async fn collect_placeholders_db(
+    target_tx: &mut Transaction<'_, MySql>,
+    vault_tx: &mut Transaction<'_, MySql>,
+    remove: bool,
+) -> Result<Vec<PlaceholderReport>, MyError> {
+    let sql = "SELECT vault_id, disguise_id, placeholder_table, placeholder_predicate, time FROM owned_placeholder ORDER BY placeholder_table, time";
+    let placeholders: Vec<OwnedPlaceholder> = sqlx::query_as(sql)
+        .fetch_all(&mut *vault_tx)
+        .await?;
+    let vault_ids: HashSet<String> = sqlx::query("SELECT vault_id FROM vault")
+        .fetch_all(&mut *vault_tx)
+        .await?
+        .iter()
+        .map(|row| row.get("vault_id"))
+        .collect();
+    let sql = "SELECT table_name, predicate, placeholder_table, placeholder_predicate FROM placeholder_mapping";
+    let mappings: Vec<PlaceholderMapping> = sqlx::query_as(sql)
+        .fetch_all(&mut *vault_tx)
+        .await?;
+    let foreign_keys = get_foreign_keys_db(target_tx).await?;
+
+    let mut reports = vec![];
+    for placeholder in placeholders {
+        let table_name = placeholder.placeholder_table.as_ref().unwrap();
+        let (column, value) = match Predicate::from_vault(placeholder.placeholder_predicate.as_ref().unwrap())? {
+            Predicate::Condition { column, value, .. } => (column, value),
+            _ => return Err(MyError::OperationError("The placeholder is not correct.".to_string())),
+        };
+        let predicate = Predicate::eq(&column, value.clone()).render(false)?;
+        //count the rows referring to the placeholder
+        let mut references = None;
+        if count_rows_db(target_tx, table_name, &predicate).await? > 0 {
+            let mut count = 0;
+            let mut counted_tables = HashSet::new();
+            for dependent in find_dependents(&foreign_keys, table_name, &column) {
+                let foreign_key = dependent.foreign_key.unwrap();
+                if dependent.depth != Some(1) {
+                    continue;
+                }
+                let referring = Predicate::eq(foreign_key.column_name.as_ref().unwrap(), value.clone()).render(false)?;
+                count += count_rows_db(target_tx, foreign_key.table_name.as_ref().unwrap(), &referring).await?;
+                counted_tables.insert(foreign_key.table_name.clone().unwrap());
+            }
+            //the rows decorrelated by the disguises refer to the placeholder
+            //even if their foreign keys are not declared
+            for mapping in mappings.iter().filter(|mapping| {
+                mapping.placeholder_table == placeholder.placeholder_table
+                    && mapping.placeholder_predicate == placeholder.placeholder_predicate
+            }) {
+                let mapped_table = mapping.table_name.as_ref().unwrap();
+                if counted_tables.contains(mapped_table) {
+                    continue;
+                }
+                let mapped = Predicate::from_vault(mapping.predicate.as_ref().unwrap())?.render(false)?;
+                count += count_rows_db(target_tx, mapped_table, &mapped).await?;
+            }
+            references = Some(count);
+        }
+        let reserved = placeholder.disguise_id.is_none()
+            && placeholder.vault_id.as_ref().is_some_and(|vault_id| vault_ids.contains(vault_id));
+        let status = match references {
+            None => "missing",
+            Some(count) if count > 0 => "in_use",
+            Some(_) if reserved => "reserved",
+            Some(_) => "orphaned",
+        };
+        let removed = remove && (status == "missing" || status == "orphaned");
+        if removed {
+            if status == "orphaned" {
+                let sql = "DELETE FROM ".to_string() + quote_identifier(table_name)?.as_str() + " WHERE " + predicate.sql.as_str();
+                bind_values(sqlx::query(sql.as_str()), &predicate.values)
+                    .execute(&mut *target_tx)
+                    .await?;
+            }
+            for sql in [
+                "DELETE FROM owned_placeholder WHERE placeholder_table=? and placeholder_predicate=?",
+                "DELETE FROM placeholder_mapping WHERE placeholder_table=? and placeholder_predicate=?",
+            ] {
+                sqlx::query(sql)
+                    .bind(table_name)
+                    .bind(placeholder.placeholder_predicate.as_deref())
+                    .execute(&mut *vault_tx)
+                    .await?;
+            }
+        }
+        reports.push(PlaceholderReport {
+            placeholder: Some(placeholder),
+            references,
+            status: Some(status.to_string()),
+            removed: Some(removed),
+        });
+    }
+    Ok(reports)
+}
@@ -0,0 +1,73 @@
+use sqlx::{MySql, Transaction};
+use crate::dbaccess::placeholder::count_rows_db;
+use crate::dbaccess::target::describe_table_db;
+use crate::error::MyError;
+use crate::models::predicate::Predicate;
+use crate::models::schema::{find_dependents, Dependent, DiscoverRoot, ForeignKey};
+
+///
+/// get all the foreign keys of the application's database
+///
+/// # Arguments
+///
+/// * `target_tx`: the transaction opened on the application's database
+///
+/// returns: Result<Vec<ForeignKey, Global>, MyError>
+///
+pub async fn get_foreign_keys_db(
+    target_tx: &mut Transaction<'_, MySql>,
+) -> Result<Vec<ForeignKey>, MyError> {
+    let sql = "SELECT CAST(TABLE_NAME AS CHAR) AS table_name, \
+        CAST(COLUMN_NAME AS CHAR) AS column_name, \
+        CAST(REFERENCED_TABLE_NAME AS CHAR) AS referenced_table_name, \
+        CAST(REFERENCED_COLUMN_NAME AS CHAR) AS referenced_column_name, \
+        CAST(CONSTRAINT_NAME AS CHAR) AS constraint_name \
+        FROM information_schema.KEY_COLUMN_USAGE \
+        WHERE TABLE_SCHEMA = DATABASE() AND REFERENCED_TABLE_SCHEMA = DATABASE() \
+        ORDER BY TABLE_NAME, CONSTRAINT_NAME, ORDINAL_POSITION";
+    let foreign_keys: Vec<ForeignKey> = sqlx::query_as(sql)
+        .fetch_all(&mut *target_tx)
+        .await?;
+    Ok(foreign_keys)
+}
+
+///
+/// discover the tables referring to the root through the foreign keys
+/// the primary key of the root table is filled in if it is not given,
+/// and the rows referring to the root directly are counted
+///
+/// # Arguments
+///
+/// * `target_tx`: the transaction opened on the application's database
+/// * `root`: the row which owns the user's data
+///
+/// returns: Result<Vec<Dependent, Global>, MyError>
+///
+pub async fn discover_dependents_db(
+    target_tx: &mut Transaction<'_, MySql>,
+    root: &mut DiscoverRoot,
+) -> Result<Vec<Dependent>, MyError> {
+    let table_name = root.table_name.clone()
+        .ok_or_else(|| MyError::InvalidInput("The table of the root is required.".into()))?;
+    if root.primary_key_value.as_ref().is_none_or(|value| value.is_null()) {
+        return Err(MyError::InvalidInput("The primary key value of the root is required.".into()));
+    }
+    if root.primary_key_name.is_none() {
+        let (field_names, _, primary_key_index) = describe_table_db(target_tx, &table_name).await?;
+        root.primary_key_name = Some(field_names[primary_key_index].clone());
+    }
+    let primary_key_name = root.primary_key_name.clone().unwrap();
+
+    let foreign_keys = get_foreign_keys_db(target_tx).await?;
+    let mut dependents = find_dependents(&foreign_keys, &table_name, &primary_key_name);
+    //count the rows referring to the root
+    for dependent in dependents.iter_mut().filter(|dependent| dependent.depth == Some(1)) {
+        let foreign_key = dependent.foreign_key.as_ref().unwrap();
+        let predicate = Predicate::eq(
+            foreign_key.column_name.as_ref().unwrap(),
+            root.primary_key_value.clone().unwrap()
+        ).render(false)?;
+        dependent.rows = Some(count_rows_db(target_tx, foreign_key.table_name.as_ref().unwrap(), &predicate).await?);
+    }
+    Ok(dependents)
+}
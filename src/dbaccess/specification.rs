@@ -0,0 +1,144 @@
+use chrono::Local;
+use sqlx::MySqlPool;
+use crate::error::MyError;
+use crate::models::specification::{Specification, SpecificationFromDB};
+
+///
+/// store the specification as the next version of its name
+/// into the "specification" table of the server's database
+///
+/// # Arguments
+///
+/// * `vault_pool`: the server's database
+/// * `specification`: the specification to store
+///
+/// returns: Result<Specification, MyError>
+///
+pub async fn create_specification_db(
+    vault_pool: &MySqlPool,
+    specification: &Specification,
+) -> Result<Specification, MyError> {
+    let name = specification.name.as_ref().unwrap();
+    let transformations = serde_json::to_string(specification.transformations.as_ref().unwrap()).unwrap();
+    let time = Local::now().to_string();
+    let mut vault_tx = vault_pool.begin().await?;
+    //lock the versions of this name until the new one is inserted
+    let sql = "SELECT MAX(version) FROM specification WHERE name=? FOR UPDATE";
+    let (latest, ): (Option<i32>, ) = sqlx::query_as(sql)
+        .bind(name)
+        .fetch_one(&mut *vault_tx)
+        .await?;
+    let version = latest.unwrap_or(0) + 1;
+    let sql = "INSERT INTO specification (name, version, transformations, reversible, time) VALUES (?, ?, ?, ?, ?)";
+    sqlx::query(sql)
+        .bind(name)
+        .bind(version)
+        .bind(transformations)
+        .bind(specification.is_reversible())
+        .bind(time.as_str())
+        .execute(&mut *vault_tx)
+        .await?;
+    vault_tx.commit().await?;
+
+    let mut specification = specification.clone();
+    specification.version = Some(version);
+    specification.reversible = Some(specification.is_reversible());
+    specification.time = Some(time);
+    Ok(specification)
+}
+
+///
+/// get the specification by its name
+/// the latest version is returned if the version is not given
+///
+/// # Arguments
+///
+/// * `vault_pool`: the server's database
+/// * `name`: the name of the specification
+/// * `version`: the version of the specification
+///
+/// returns: Result<Specification, MyError>
+///
+pub async fn get_specification_db(
+    vault_pool: &MySqlPool,
+    name: &str,
+    version: Option<i32>,
+) -> Result<Specification, MyError> {
+    let specification: Option<SpecificationFromDB> = match version {
+        Some(version) => {
+            let sql = "SELECT name, version, transformations, reversible, time FROM specification WHERE name=? and version=?";
+            sqlx::query_as(sql)
+                .bind(name)
+                .bind(version)
+                .fetch_optional(vault_pool)
+                .await?
+        }
+        None => {
+            let sql = "SELECT name, version, transformations, reversible, time FROM specification WHERE name=? ORDER BY version DESC LIMIT 1";
+            sqlx::query_as(sql)
+                .bind(name)
+                .fetch_optional(vault_pool)
+                .await?
+        }
+    };
+    specification
+        .ok_or_else(|| MyError::NotFound("The specification is not found.".into()))?
+        .try_into()
+}
+
+///
+/// get all the versions of all the specifications
+/// sorted by the name and the version
+///
+/// # Arguments
+///
+/// * `vault_pool`: the server's database
+///
+/// returns: Result<Vec<Specification, Global>, MyError>
+///
+pub async fn get_specifications_db(vault_pool: &MySqlPool) -> Result<Vec<Specification>, MyError> {
+    let sql = "SELECT name, version, transformations, reversible, time FROM specification ORDER BY name, version";
+    let specifications: Vec<SpecificationFromDB> = sqlx::query_as(sql)
+        .fetch_all(vault_pool)
+        .await?;
+    specifications.into_iter().map(|specification| specification.try_into()).collect()
+}
+
+///
+/// delete one version of the specification,
+/// or all its versions if the version is not given
+/// the applied disguises are kept in the vault
+///
+/// # Arguments
+///
+/// * `vault_pool`: the server's database
+/// * `name`: the name of the specification
+/// * `version`: the version of the specification
+///
+/// returns: Result<String, MyError>
+///
+pub async fn delete_specification_db(
+    vault_pool: &MySqlPool,
+    name: &str,
+    version: Option<i32>,
+) -> Result<String, MyError> {
+    let res = match version {
+        Some(version) => {
+            sqlx::query("DELETE FROM specification WHERE name=? and version=?")
+                .bind(name)
+                .bind(version)
+                .execute(vault_pool)
+                .await?
+        }
+        None => {
+            sqlx::query("DELETE FROM specification WHERE name=?")
+                .bind(name)
+                .execute(vault_pool)
+                .await?
+        }
+    };
+    if res.rows_affected() == 0 {
+        return Err(MyError::NotFound("The specification is not found.".into()));
+    }
+    Ok("The specification has been deleted.".into())
+}
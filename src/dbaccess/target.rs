@@ -1,10 +1,17 @@
-use chrono::{DateTime, Duration, Local};
-use sqlx::{MySqlPool, Row};
+use chrono::{Duration, Local};
+use serde_json::Value;
+use sqlx::mysql::{MySqlArguments, MySqlRow};
+use sqlx::query::Query;
+use sqlx::{MySql, MySqlPool, Row, Transaction, ValueRef};
+use crate::dbaccess::placeholder::insert_placeholder_db;
 use crate::error::MyError;
-use crate::models::placeholder::GeneratePlaceHolder;
-use crate::models::target::{Field, Target};
+use crate::models::crypto::{open_stored, VaultKey};
+use crate::models::placeholder::{GeneratePlaceHolder, PlaceholderInfo, PlaceholderMapping};
+use crate::models::predicate::{binary_value, decode_binary, quote_identifier, Change, Changes, Predicate, SqlFragment};
+use crate::models::recovery::{plan_recovery, plan_undo, ConflictPolicy, FunctionDiff, RecoveryAction, RecoveryConflict, RecoveryPlan, RecoveryPolicies};
+use crate::models::target::{Field, FieldValue, Target};
 use crate::models::transformation::Transformation;
-use crate::models::vault::Disguise;
+use crate::models::vault::{Disguise, Function};
 
 ///
 /// generate the placeholder in the target database
@@ -14,26 +21,19 @@ use crate::models::vault::Disguise;
 ///
 /// * `target_db_pool`: the web-application's database
 /// * `placeholder`: the parameter sent by user or app
+/// * `allow_raw`: if the literal fields and values are accepted
 ///
 /// returns: Result<String, MyError>
 ///
 pub async fn generate_placeholder_db(
     target_db_pool: &MySqlPool,
     placeholder: &GeneratePlaceHolder,
+    allow_raw: bool,
 ) -> Result<String, MyError> {
     //insert the generate placeholder into database
-    let mut sql = String::from("");
-    sql.push_str("INSERT INTO ");
-    sql.push_str(placeholder.table.as_ref().unwrap().as_str());
-    sql.push_str("(");
-    sql.push_str(placeholder.fields.as_ref().unwrap().as_str());
-    sql.push_str(") VALUES(");
-    sql.push_str(placeholder.field_values.as_ref().unwrap().as_str());
-    sql.push_str(")");
-    let id = sqlx::query(sql.as_str())
-        .execute(target_db_pool)
-        .await?
-        .last_insert_id();
+    let mut target_tx = target_db_pool.begin().await?;
+    let id = insert_placeholder_db(&mut target_tx, placeholder, None, allow_raw).await?;
+    target_tx.commit().await?;
 
     //the operation is successful
     //return the id of the new generated placeholder
@@ -47,13 +47,13 @@ pub async fn generate_placeholder_db(
 ///
 /// # Arguments
 ///
-/// * `target_pool`: the application's database
+/// * `target_tx`: the transaction opened on the application's database
 /// * `transformations`: the transformations of the disguise
 ///
 /// returns: Result<Vec<Vec<Target, Global>, Global>, MyError>
 ///
 pub async fn get_targets_db(
-    target_pool: &MySqlPool,
+    target_tx: &mut Transaction<'_, MySql>,
     transformations: &Vec<Transformation>
 ) -> Result<Vec<Vec<Target>>, MyError> {
     let mut res = vec![];
@@ -61,173 +61,200 @@ pub async fn get_targets_db(
     //every transformation
     for transformation in transformations {
         //every row affected by the same transformation
-        let table_name = transformation.table_name.as_ref().clone().unwrap().as_str();
-        let predicate = transformation.predicate.as_ref().clone().unwrap().as_str();
-        let mut targets = vec![];
-        //get the names and types of target's fields in this table
-        //and the primary key's index
-        let mut field_names = vec![];
-        let mut field_types = vec![];
-        let mut primary_key_index = 0;
-        let sql = "DESC ".to_string() + table_name;
-        let rows = sqlx::query(sql.as_str())
-            .fetch_all(target_pool)
-            .await?;
-        for (i, row) in rows.iter().enumerate() {
-            let field_name: String = row.get("Field");
-            let field_type: String = row.get("Type");
-            let primary_key: String = row.get("Key");
-            field_names.push(field_name);
-            field_types.push(field_type);
-            if primary_key.eq("PRI") {
-                primary_key_index = i;
-            }
+        let table_name = transformation.table_name.as_ref().unwrap();
+        let predicate = transformation.predicate.as_ref().unwrap().render(transformation.allow_raw())?;
+        let targets = select_targets_db(target_tx, table_name, &predicate).await?;
+        //if the query result is 0
+        if targets.is_empty() {
+            return Err(MyError::OperationError("No data fits the requirement.".to_string()));
         }
-        //query the targets
-        let sql = "SELECT * FROM ".to_string() + table_name + " WHERE " + predicate;
-        let query_res = sqlx::query(sql.as_str())
-            .fetch_all(target_pool)
-            .await;
-        match query_res {
-            //push every target into the targets vector
-            Ok(rows) => {
-                //if the query result is 0
-                if rows.len() == 0 {
-                    return Err(MyError::OperationError("No data fits the requirement.".to_string()));
-                }
-                for row in rows {
-                    let mut fields = vec![];
-                    //push every field of the row(target)
-                    for (i, field_name) in field_names.iter().enumerate() {
-                        let field_type = &field_types[i];
-                        let mut field_value = String::new();
-                        if field_type.contains("varchar") {
-                            let value: String = row.get(field_name.as_str());
-                            field_value.push_str(value.as_str());
-                        }else if field_type.contains("int") {
-                            let value: i32 = row.get(field_name.as_str());
-                            field_value.push_str(value.to_string().as_str());
-                        }else if field_type.contains("time"){
-                            let value: DateTime<Local> = row.get(field_name.as_str());
-                            field_value.push_str(value.to_string().as_str());
-                        }
-                        fields.push(Field {
-                            field_name: Some(field_name.clone()),
-                            field_type: Some(field_type.clone()),
-                            field_value: Some(field_value)
-                        })
-                    }
-                    targets.push(Target {
-                        primary_key_index: Some(primary_key_index),
-                        fields: Some(fields)
-                    })
-                }
-                res.push(targets);
+        res.push(targets);
+    }
+    Ok(res)
+}
 
-            }
-            //the input err make the query failed.
-            Err(_) => {
-                return Err(MyError::InvalidInput("The input \"table\" or \"predicate\" is not correct.".to_string()));
-            }
-        };
+///
+/// get the state of the operated targets after the transformations
+/// the targets are found again by their primary keys,
+/// and the removed targets are None
+///
+/// # Arguments
+///
+/// * `target_tx`: the transaction opened on the application's database
+/// * `transformations`: the executed transformations of the disguise
+/// * `originals`: the original state of the targets of transformations
+///
+/// returns: Result<Vec<Vec<Option<Target>, Global>, Global>, MyError>
+///
+pub async fn get_updated_targets_db(
+    target_tx: &mut Transaction<'_, MySql>,
+    transformations: &[Transformation],
+    originals: &[Vec<Target>]
+) -> Result<Vec<Vec<Option<Target>>>, MyError> {
+    let mut res = vec![];
+    for (i, transformation) in transformations.iter().enumerate() {
+        let table_name = transformation.table_name.as_ref().unwrap();
+        let mut targets = vec![];
+        for original in &originals[i] {
+            let predicate = field_predicate(&original.primary_key().unwrap())?.render(false)?;
+            targets.push(select_targets_db(target_tx, table_name, &predicate).await?.pop());
+        }
+        res.push(targets);
     }
     Ok(res)
 }
 
+///
+/// get the names and types of the table's fields
+/// and the index of the primary key
+///
+/// # Arguments
+///
+/// * `target_tx`: the transaction opened on the application's database
+/// * `table_name`: the name of the table
+///
+/// returns: Result<(Vec<String, Global>, Vec<String, Global>, usize), MyError>
+///
+pub async fn describe_table_db(
+    target_tx: &mut Transaction<'_, MySql>,
+    table_name: &str
+) -> Result<(Vec<String>, Vec<String>, usize), MyError> {
+    let mut field_names = vec![];
+    let mut field_types = vec![];
+    let mut primary_key_index = 0;
+    let sql = "DESC ".to_string() + quote_identifier(table_name)?.as_str();
+    let rows = sqlx::query(sql.as_str())
+        .fetch_all(&mut *target_tx)
+        .await?;
+    for (i, row) in rows.iter().enumerate() {
+        let field_name: String = row.get("Field");
+        let field_type: String = row.get("Type");
+        let primary_key: String = row.get("Key");
+        field_names.push(field_name);
+        field_types.push(field_type);
+        if primary_key.eq("PRI") {
+            primary_key_index = i;
+        }
+    }
+    Ok((field_names, field_types, primary_key_index))
+}
+
+///
+/// get the targets of the table selected by the predicate
+///
+/// # Arguments
+///
+/// * `target_tx`: the transaction opened on the application's database
+/// * `table_name`: the name of the table
+/// * `predicate`: the rendered predicate of the targets
+///
+/// returns: Result<Vec<Target, Global>, MyError>
+///
+pub async fn select_targets_db(
+    target_tx: &mut Transaction<'_, MySql>,
+    table_name: &str,
+    predicate: &SqlFragment
+) -> Result<Vec<Target>, MyError> {
+    //get the names and types of target's fields in this table
+    //and the primary key's index
+    let (field_names, field_types, primary_key_index) = describe_table_db(target_tx, table_name).await?;
+    //query the targets
+    let sql = "SELECT * FROM ".to_string() + quote_identifier(table_name)?.as_str() + " WHERE " + predicate.sql.as_str();
+    let rows = bind_values(sqlx::query(sql.as_str()), &predicate.values)
+        .fetch_all(&mut *target_tx)
+        .await
+        //the input err make the query failed.
+        .map_err(|_err| MyError::InvalidInput("The input \"table\" or \"predicate\" is not correct.".to_string()))?;
+    let mut targets = vec![];
+    //push every target into the targets vector
+    for row in rows {
+        let mut fields = vec![];
+        //push every field of the row(target)
+        for (i, field_name) in field_names.iter().enumerate() {
+            let field_type = &field_types[i];
+            fields.push(Field {
+                field_name: Some(field_name.clone()),
+                field_type: Some(field_type.clone()),
+                field_value: Some(decode_field_value(&row, field_name, field_type)?)
+            })
+        }
+        targets.push(Target {
+            primary_key_index: Some(primary_key_index),
+            fields: Some(fields)
+        })
+    }
+    Ok(targets)
+}
+
 ///
 /// execute the transformations to the target database
-/// return the changes of the transformations
+/// return the number of rows affected by each transformation
+/// and the placeholders generated for the decorrelated rows
 ///
 /// # Arguments
 ///
-/// * `placeholder_pred`: the placeholder's predicate
-/// * `target_pool`: application's database
+/// * `placeholder_info`: the placeholder of the vault
+/// * `target_tx`: the transaction opened on the application's database
 /// * `transformations`: the transformations of the disguise
+/// * `allow_raw`: if the literal template of the placeholder is accepted
 ///
-/// returns: Result<Vec<String, Global>, MyError>
+/// returns: Result<(Vec<u64>, Vec<PlaceholderMapping>), MyError>
 ///
 pub async fn execute_transformations_db(
-    placeholder_pred: &str,
-    target_pool: &MySqlPool,
-    transformations: &Vec<Transformation>
-) -> Result<Vec<String>, MyError> {
-    //to store all the changes in transformations
-    let mut all_changes = vec![];
+    placeholder_info: &PlaceholderInfo,
+    target_tx: &mut Transaction<'_, MySql>,
+    transformations: &Vec<Transformation>,
+    allow_raw: bool
+) -> Result<(Vec<u64>, Vec<PlaceholderMapping>), MyError> {
+    //to store the number of rows affected by the transformations
+    let mut row_counts = vec![];
+    let mut placeholders = vec![];
     //execute the transformations
     for transformation in transformations {
         //get the table_name and predicate
-        let table_name = transformation.table_name.as_ref().unwrap();
-        let predicate = transformation.predicate.as_ref().unwrap();
+        let table_name = quote_identifier(transformation.table_name.as_ref().unwrap())?;
+        let predicate = transformation.predicate.as_ref().unwrap().render(transformation.allow_raw())?;
         //check the transformations' type
         match transformation.transform_type.as_ref().unwrap().to_lowercase().as_str() {
             //if the transformation is removal
             "removal" => {
                 //generate the sql sentence
-                let mut sql = String::from("");
-                sql.push_str("DELETE FROM ");
-                sql.push_str(&table_name);
-                sql.push_str(" WHERE ");
-                sql.push_str(&predicate);
+                let sql = "DELETE FROM ".to_string() + table_name.as_str() + " WHERE " + predicate.sql.as_str();
                 //execute the sql
-                let num = sqlx::query(sql.as_str())
-                    .execute(target_pool)
+                let num = bind_values(sqlx::query(sql.as_str()), &predicate.values)
+                    .execute(&mut *target_tx)
                     .await?
                     .rows_affected();
                 //if the predicate is not correct
-                if num <= 0 {
+                if num == 0 {
                     return Err(MyError::InvalidInput("The predicate is not correct.".to_string()));
                 }
-                all_changes.push("".to_string());
+                row_counts.push(num);
             }
             //if the transformation is modification
             "modification" => {
-                let changes = transformation.changes.as_ref().unwrap().as_str();
-                //generate the sql sentence
-                let mut sql = String::from("");
-                sql.push_str("UPDATE ");
-                sql.push_str(&table_name);
-                sql.push_str(" SET ");
-                sql.push_str(changes);
-                sql.push_str(" WHERE ");
-                sql.push_str(&predicate);
-                //execute the sql
-                let num = sqlx::query(
-                    sql.as_str()
-                )
-                    .execute(target_pool)
-                    .await?
-                    .rows_affected();
-                //if the predicate is not correct
-                if num <= 0 {
-                    return Err(MyError::InvalidInput("The predicate is not correct.".to_string()));
-                }
-                all_changes.push(String::from(changes));
+                let changes = transformation.changes.as_ref().unwrap();
+                let num = execute_update_db(target_tx, &table_name, changes, &predicate, transformation.allow_raw()).await?;
+                row_counts.push(num);
+            }
+            //if every decorrelated row needs its own placeholder
+            "decorrelation" if transformation.is_unique_placeholder() => {
+                let generated = decorrelate_unique_db(placeholder_info, target_tx, transformation, &predicate, allow_raw).await?;
+                row_counts.push(generated.len() as u64);
+                placeholders.extend(generated);
             }
             //if the transformation is decorrelation
             "decorrelation" => {
-                // let foreign_key = transformation.foreign_key.unwrap();
-                let changes = placeholder_pred;
-
-                //generate the sql sentence
-                let mut sql = String::from("");
-                sql.push_str("UPDATE ");
-                sql.push_str(&table_name);
-                sql.push_str(" SET ");
-                sql.push_str(changes);
-                sql.push_str(" WHERE ");
-                sql.push_str(&predicate);
-                //execute the sql
-                let num = sqlx::query(
-                    sql.as_str()
-                )
-                    .execute(target_pool)
-                    .await?
-                    .rows_affected();
-                //if the predicate is not correct
-                if num <= 0 {
-                    return Err(MyError::InvalidInput("The predicate is not correct.".to_string()));
-                }
-                all_changes.push(String::from(changes));
+                //point the foreign key to the placeholder
+                let changes = match Predicate::from_vault(placeholder_info.pred.as_ref().unwrap())? {
+                    Predicate::Condition { column, value, .. } => Changes::Columns(vec![Change {
+                        column: transformation.foreign_key.clone().unwrap_or(column),
+                        value
+                    }]),
+                    _ => return Err(MyError::OperationError("The placeholder is not correct.".to_string())),
+                };
+                let num = execute_update_db(target_tx, &table_name, &changes, &predicate, false).await?;
+                row_counts.push(num);
             }
             //if the transform type is different from the three types
             _ => {
@@ -238,71 +265,458 @@ pub async fn execute_transformations_db(
             }
         };
     }
-    Ok(all_changes)
+    Ok((row_counts, placeholders))
+}
+
+///
+/// decorrelate every selected row to its own new placeholder
+/// which is generated by the template of the vault
+/// with the values of the row's original owner
+///
+/// # Arguments
+///
+/// * `placeholder_info`: the placeholder of the vault
+/// * `target_tx`: the transaction opened on the application's database
+/// * `transformation`: the decorrelation
+/// * `predicate`: the rendered predicate of the decorrelated rows
+/// * `allow_raw`: if the literal template of the placeholder is accepted
+///
+/// returns: Result<Vec<PlaceholderMapping, Global>, MyError>
+///
+async fn decorrelate_unique_db(
+    placeholder_info: &PlaceholderInfo,
+    target_tx: &mut Transaction<'_, MySql>,
+    transformation: &Transformation,
+    predicate: &SqlFragment,
+    allow_raw: bool,
+) -> Result<Vec<PlaceholderMapping>, MyError> {
+    let template = placeholder_info.template.as_ref()
+        .ok_or_else(|| MyError::OperationError("The vault has no template to generate the placeholders.".to_string()))?;
+    let primary_key_name = template.primary_key_name.as_ref().unwrap();
+    let column = transformation.foreign_key.clone().unwrap_or_else(|| primary_key_name.clone());
+    let table_name = transformation.table_name.as_ref().unwrap();
+    let targets = select_targets_db(target_tx, table_name, predicate).await?;
+    //if the predicate is not correct
+    if targets.is_empty() {
+        return Err(MyError::InvalidInput("The predicate is not correct.".to_string()));
+    }
+    let mut placeholders = vec![];
+    for target in targets {
+        //the placeholder could copy the values of the original owner
+        let owner = match (template.copies_owner(), target.foreign_key(&column)) {
+            (true, Some(foreign_key)) if foreign_key.field_value != Some(FieldValue::Null) => {
+                let owner_table = template.table.as_ref().unwrap();
+                let owner_key = field_predicate(&Field {
+                    field_name: Some(primary_key_name.clone()),
+                    field_type: foreign_key.field_type,
+                    field_value: foreign_key.field_value
+                })?.render(false)?;
+                select_targets_db(target_tx, owner_table, &owner_key).await?.pop()
+            }
+            _ => None,
+        };
+        let placeholder_id = insert_placeholder_db(target_tx, template, owner.as_ref(), allow_raw).await?;
+        let key = field_predicate(&target.primary_key().unwrap())?;
+        let changes = Changes::Columns(vec![Change { column: column.clone(), value: Value::from(placeholder_id) }]);
+        execute_update_db(target_tx, &quote_identifier(table_name)?, &changes, &key.render(false)?, false).await?;
+        placeholders.push(PlaceholderMapping {
+            table_name: Some(table_name.clone()),
+            predicate: Some(key.to_vault()),
+            placeholder_table: template.table.clone(),
+            placeholder_predicate: Some(Predicate::eq(primary_key_name, Value::from(placeholder_id)).to_vault())
+        });
+    }
+    Ok(placeholders)
+}
+
+///
+/// update the targets selected by the predicate with the changes
+///
+/// # Arguments
+///
+/// * `target_tx`: the transaction opened on the application's database
+/// * `table_name`: the quoted name of the table
+/// * `changes`: the changes of the targets
+/// * `predicate`: the rendered predicate of the targets
+/// * `allow_raw`: if the raw sql changes are accepted
+///
+/// returns: Result<u64, MyError>
+///
+async fn execute_update_db(
+    target_tx: &mut Transaction<'_, MySql>,
+    table_name: &str,
+    changes: &Changes,
+    predicate: &SqlFragment,
+    allow_raw: bool
+) -> Result<u64, MyError> {
+    let changes = changes.render(allow_raw)?;
+    //generate the sql sentence
+    let sql = "UPDATE ".to_string() + table_name + " SET " + changes.sql.as_str() + " WHERE " + predicate.sql.as_str();
+    //execute the sql
+    let query = bind_values(sqlx::query(sql.as_str()), &changes.values);
+    let num = bind_values(query, &predicate.values)
+        .execute(&mut *target_tx)
+        .await?
+        .rows_affected();
+    //if the predicate is not correct
+    if num == 0 {
+        return Err(MyError::InvalidInput("The predicate is not correct.".to_string()));
+    }
+    Ok(num)
 }
 
 ///
 /// recover the applied disguise in the vault
 /// the disguises can be downloaded by the function "download_disguise_db"
+/// the functions are recovered in the reverse order of the execution,
+/// so the parents are recovered before their children
+/// and the placeholders generated for the disguise are deleted at last
+/// every row is compared with its state right after the disguise first,
+/// the rows changed by the application since then are resolved by the policies of their tables,
+/// and the placeholders of the skipped rows are kept
+/// the conflicts, the skipped functions and the number of restored rows are returned,
+/// or the conflicts are reported in the error when any of them aborts the recovery
 ///
 /// # Arguments
 ///
-/// * `target_db`: the application's database
+/// * `target_tx`: the transaction opened on the application's database
 /// * `disguise`: the information of the disguise from vault
+/// * `key`: the data key of the disguise
+/// * `policies`: the policies to resolve the conflicts
 ///
-/// returns: Result<String, MyError>
+/// returns: Result<(Vec<RecoveryConflict, Global>, Vec<Function, Global>, i64), MyError>
 ///
 pub async fn recover_db(
-    target_db: &MySqlPool,
+    target_tx: &mut Transaction<'_, MySql>,
     disguise: &Disguise,
-) -> Result<String, MyError>{
+    key: Option<&VaultKey>,
+    policies: &RecoveryPolicies,
+) -> Result<(Vec<RecoveryConflict>, Vec<Function>, i64), MyError>{
     let mut functions = disguise.functions.as_ref().unwrap().clone();
     functions.reverse();
 
+    let mut conflicts = vec![];
+    let mut skipped = vec![];
+    let mut restored = 0;
+    let mut aborted = false;
     for function in functions {
         let function_type = function.function_type.as_ref().unwrap();
         let table_name = function.table_name.as_ref().unwrap();
-        let predicate = function.predicate.as_ref().unwrap();
-        let original = function.original.as_ref().unwrap();
+        let predicate = Predicate::from_vault(function.predicate.as_ref().unwrap())?.render(false)?;
+        let policy = policies.of(table_name);
+        let (original, _, plan) = plan_function_db(target_tx, &function, key, policy).await?;
+        if let Some(conflict) = plan.conflict {
+            conflicts.push(RecoveryConflict {
+                disguise_id: disguise.public_id.clone(),
+                table_name: Some(table_name.clone()),
+                function_type: Some(function_type.clone()),
+                primary_key: original.primary_key().and_then(|key| key.field_value).map(|value| value.to_value()),
+                conflict: Some(conflict.to_string()),
+                changed_fields: Some(plan.changed_fields),
+                resolution: Some(policy.resolution().to_string())
+            });
+        }
+        let table_name = quote_identifier(table_name)?;
 
-        match function_type.as_str() {
-            //if the function type is removal
-            "removal" => {
-                let sql = "INSERT INTO ".to_string() + table_name + " VALUES (" + original + ")";
-                sqlx::query(sql.as_str())
-                    .execute(target_db)
-                    .await?;
+        match plan.action {
+            //the removed row, or the changed row deleted by the application
+            RecoveryAction::Insert(target) => {
+                insert_target_db(target_tx, table_name.as_str(), &target).await?;
+                restored += 1;
             },
-            //if the function type is modification or decorrelation
-            _ => {
-                //get the fields name
-                let mut fields = vec![];
-                let sql = "DESC ".to_string() + table_name;
-                let rows = sqlx::query(sql.as_str())
-                    .fetch_all(target_db)
-                    .await?;
-                for row in rows {
-                    let field: &str = row.get(0);
-                    fields.push(String::from(field));
-                }
-                //transform to the format of "field_name=field_value"
-                let values = original.split(", ").collect::<Vec<&str>>();
-                let mut updates = String::new();
-                for (i, field) in fields.iter().enumerate() {
-                    if i > 0 {
-                        updates += ",";
-                    }
-                    updates = updates + field + "=" + values.get(i).as_ref().unwrap();
-                }
-                //update the values
-                let sql = "UPDATE ".to_string() + table_name + " SET " + updates.as_str() + " WHERE " + predicate;
-                sqlx::query(sql.as_str())
-                    .execute(target_db)
-                    .await?;
+            //the modified or decorrelated row, or the row using the removed row's key
+            RecoveryAction::Update(target) => {
+                update_target_db(target_tx, table_name.as_str(), &target, &predicate).await?;
+                restored += 1;
             }
+            RecoveryAction::Skip => skipped.push(function.clone()),
+            RecoveryAction::Abort => aborted = true,
         }
     }
-    Ok("The target has been recovered.".to_string())
+    if aborted {
+        let rows: Vec<String> = conflicts.iter()
+            .map(|conflict| format!(
+                "{} {} ({})",
+                conflict.table_name.as_deref().unwrap_or_default(),
+                conflict.primary_key.as_ref().unwrap_or(&Value::Null),
+                conflict.conflict.as_deref().unwrap_or_default()
+            ))
+            .collect();
+        return Err(MyError::Conflict(format!(
+            "The recovery has been aborted, the rows have been changed after the disguise: {}.", rows.join(", ")
+        )));
+    }
+    //the rows point to their original owners again
+    if let Some(placeholders) = &disguise.placeholders {
+        let placeholders: Vec<PlaceholderMapping> = placeholders.iter()
+            .filter(|placeholder| !skipped.iter().any(|function| {
+                function.table_name == placeholder.table_name && function.predicate == placeholder.predicate
+            }))
+            .cloned()
+            .collect();
+        delete_placeholders_db(target_tx, &placeholders).await?;
+    }
+    Ok((conflicts, skipped, restored))
+}
+
+///
+/// insert the row with all the fields of the target
+///
+/// # Arguments
+///
+/// * `target_tx`: the transaction opened on the application's database
+/// * `table_name`: the quoted name of the table
+/// * `target`: the row to insert
+///
+/// returns: Result<String, MyError>
+///
+async fn insert_target_db(
+    target_tx: &mut Transaction<'_, MySql>,
+    table_name: &str,
+    target: &Target,
+) -> Result<String, MyError> {
+    let values = target.field_values();
+    let placeholders = vec!["?"; values.len()].join(", ");
+    let sql = "INSERT INTO ".to_string() + table_name
+        + " (" + target.field_names()?.as_str() + ") VALUES (" + placeholders.as_str() + ")";
+    bind_field_values(sqlx::query(sql.as_str()), &values)?
+        .execute(&mut *target_tx)
+        .await?;
+    Ok("The row has been inserted.".to_string())
+}
+
+///
+/// update the fields of the row found by the predicate with the values of the target
+///
+/// # Arguments
+///
+/// * `target_tx`: the transaction opened on the application's database
+/// * `table_name`: the quoted name of the table
+/// * `target`: the fields to update
+/// * `predicate`: the rendered predicate of the row
+///
+/// returns: Result<String, MyError>
+///
+async fn update_target_db(
+    target_tx: &mut Transaction<'_, MySql>,
+    table_name: &str,
+    target: &Target,
+    predicate: &SqlFragment,
+) -> Result<String, MyError> {
+    let values = target.field_values();
+    //transform to the format of "field_name = ?"
+    let mut updates = vec![];
+    for field in target.fields.as_ref().unwrap() {
+        updates.push(quote_identifier(field.field_name.as_ref().unwrap())? + " = ?");
+    }
+    //update the values
+    let sql = "UPDATE ".to_string() + table_name + " SET " + updates.join(", ").as_str()
+        + " WHERE " + predicate.sql.as_str();
+    let query = bind_field_values(sqlx::query(sql.as_str()), &values)?;
+    bind_values(query, &predicate.values)
+        .execute(&mut *target_tx)
+        .await?;
+    Ok("The row has been updated.".to_string())
+}
+
+///
+/// undo the function of the step whose row has been changed again by the later disguises
+/// the fields changed by the disguise are restored in the current row
+/// unless they have been changed again after it,
+/// and the later functions are returned with their state rewritten without the disguise,
+/// which are encrypted by their own data keys, with the number of restored rows
+///
+/// # Arguments
+///
+/// * `target_tx`: the transaction opened on the application's database
+/// * `step`: the step of the disguise with one function
+/// * `key`: the data key of the disguise
+/// * `later`: the later functions on the same row with their data keys, from the oldest one
+///
+/// returns: Result<(Vec<Function, Global>, i64), MyError>
+///
+pub async fn undo_db(
+    target_tx: &mut Transaction<'_, MySql>,
+    step: &Disguise,
+    key: Option<&VaultKey>,
+    later: &[(Function, Option<VaultKey>)],
+) -> Result<(Vec<Function>, i64), MyError> {
+    let function = &step.functions.as_ref().unwrap()[0];
+    let table_name = function.table_name.as_ref().unwrap();
+    let predicate = Predicate::from_vault(function.predicate.as_ref().unwrap())?.render(false)?;
+    let original = decode_original_db(target_tx, function, key).await?;
+    //the fields changed by the disguise are unknown without its state after the disguise
+    let updated = decode_updated(function, key)?.ok_or_else(|| MyError::Conflict(
+        "The disguise could not be undone under the later disguises, which should be recovered first.".to_string()
+    ))?;
+    let mut states = vec![];
+    for (later_function, later_key) in later {
+        let later_original = match later_function.original {
+            Some(_) => Some(decode_original_db(target_tx, later_function, later_key.as_ref()).await?),
+            None => None,
+        };
+        states.push((later_original, decode_updated(later_function, later_key.as_ref())?));
+    }
+    let current = select_targets_db(target_tx, table_name, &predicate).await?.pop();
+    let plan = plan_undo(&original, &updated, &states, current.as_ref());
+    let mut restored = 0;
+    if let Some(target) = &plan.current {
+        update_target_db(target_tx, quote_identifier(table_name)?.as_str(), target, &predicate).await?;
+        restored += 1;
+    }
+    //the placeholders are not used by the undone row or the rewritten state
+    delete_placeholders_db(target_tx, step.placeholders.as_deref().unwrap_or_default()).await?;
+
+    let seal = |target: Target, later_key: &Option<VaultKey>| match later_key {
+        Some(later_key) => later_key.encrypt(target.to_vault().as_str()),
+        None => target.to_vault(),
+    };
+    let rewritten = later.iter()
+        .zip(plan.later)
+        .map(|((later_function, later_key), (later_original, later_updated))| Function {
+            original: later_original.map(|target| seal(target, later_key)),
+            updated: later_updated.map(|target| seal(target, later_key)),
+            ..later_function.clone()
+        })
+        .collect();
+    Ok((rewritten, restored))
+}
+
+///
+/// plan the recovery of the function's row by its current state
+/// the original row, the current row and the plan are returned
+///
+/// # Arguments
+///
+/// * `target_tx`: the transaction opened on the application's database
+/// * `function`: the function of the disguise from vault
+/// * `key`: the data key of the disguise
+/// * `policy`: the policy of the function's table
+///
+/// returns: Result<(Target, Option<Target>, RecoveryPlan), MyError>
+///
+async fn plan_function_db(
+    target_tx: &mut Transaction<'_, MySql>,
+    function: &Function,
+    key: Option<&VaultKey>,
+    policy: ConflictPolicy,
+) -> Result<(Target, Option<Target>, RecoveryPlan), MyError> {
+    let table_name = function.table_name.as_ref().unwrap();
+    let predicate = Predicate::from_vault(function.predicate.as_ref().unwrap())?.render(false)?;
+    let original = decode_original_db(target_tx, function, key).await?;
+    let updated = decode_updated(function, key)?;
+    let current = select_targets_db(target_tx, table_name, &predicate).await?.pop();
+    let plan = plan_recovery(function.function_type.as_ref().unwrap(), &original, updated.as_ref(), current.as_ref(), policy);
+    Ok((original, current, plan))
+}
+
+///
+/// preview the recovery of the disguise without changing anything
+/// every function is planned as "recover_db" does,
+/// and its row is compared with the row the recovery would write
+/// the function which could not be planned is reported as the failure
+///
+/// # Arguments
+///
+/// * `target_tx`: the transaction opened on the application's database, which is only read
+/// * `disguise`: the information of the disguise from vault
+/// * `key`: the data key of the disguise
+/// * `policies`: the policies to resolve the conflicts
+///
+/// returns: Result<Vec<FunctionDiff, Global>, MyError>
+///
+pub async fn preview_recovery_db(
+    target_tx: &mut Transaction<'_, MySql>,
+    disguise: &Disguise,
+    key: Option<&VaultKey>,
+    policies: &RecoveryPolicies,
+) -> Result<Vec<FunctionDiff>, MyError> {
+    let mut functions = disguise.functions.as_ref().unwrap().clone();
+    functions.reverse();
+
+    let mut diffs = vec![];
+    for function in functions {
+        let policy = policies.of(function.table_name.as_ref().unwrap());
+        let mut diff = FunctionDiff::new(disguise.public_id.clone(), &function);
+        match plan_function_db(target_tx, &function, key, policy).await {
+            Ok((original, current, plan)) => diff.set_plan(&original, current.as_ref(), plan, policy),
+            Err(err) => diff.set_failure(err.to_string()),
+        }
+        diffs.push(diff);
+    }
+    Ok(diffs)
+}
+
+///
+/// delete the placeholders generated for the decorrelated rows
+///
+/// # Arguments
+///
+/// * `target_tx`: the transaction opened on the application's database
+/// * `placeholders`: the placeholders of the disguise
+///
+/// returns: Result<String, MyError>
+///
+pub async fn delete_placeholders_db(
+    target_tx: &mut Transaction<'_, MySql>,
+    placeholders: &[PlaceholderMapping],
+) -> Result<String, MyError> {
+    for placeholder in placeholders {
+        let table_name = quote_identifier(placeholder.placeholder_table.as_ref().unwrap())?;
+        let predicate = Predicate::from_vault(placeholder.placeholder_predicate.as_ref().unwrap())?.render(false)?;
+        let sql = "DELETE FROM ".to_string() + table_name.as_str() + " WHERE " + predicate.sql.as_str();
+        bind_values(sqlx::query(sql.as_str()), &predicate.values)
+            .execute(&mut *target_tx)
+            .await?;
+    }
+    Ok("The placeholders have been deleted.".to_string())
+}
+
+///
+/// decode the original state of the target stored in the function
+/// the encrypted state is decrypted by the disguise's data key first,
+/// and the old format of the values is read with the table's fields
+///
+/// # Arguments
+///
+/// * `target_tx`: the transaction opened on the application's database
+/// * `function`: the function of the disguise from vault
+/// * `key`: the data key of the disguise
+///
+/// returns: Result<Target, MyError>
+///
+pub async fn decode_original_db(
+    target_tx: &mut Transaction<'_, MySql>,
+    function: &Function,
+    key: Option<&VaultKey>,
+) -> Result<Target, MyError> {
+    let original = function.original.as_ref()
+        .ok_or_else(|| MyError::OperationError("The disguise is not reversible.".to_string()))?;
+    let original = &open_stored(original, key)?;
+    if let Some(target) = Target::from_vault(original) {
+        return Ok(target);
+    }
+    let table_name = function.table_name.as_ref().unwrap();
+    let (field_names, field_types, primary_key_index) = describe_table_db(target_tx, table_name).await?;
+    Target::from_legacy(original, &field_names, &field_types, primary_key_index)
+}
+
+///
+/// decode the state of the target after the function
+/// the encrypted state is decrypted by the disguise's data key first
+///
+/// # Arguments
+///
+/// * `function`: the function of the disguise from vault
+/// * `key`: the data key of the disguise
+///
+/// returns: Result<Option<Target>, MyError>
+///
+pub fn decode_updated(function: &Function, key: Option<&VaultKey>) -> Result<Option<Target>, MyError> {
+    match function.updated.as_deref() {
+        Some(updated) => Ok(Target::from_vault(open_stored(updated, key)?.as_str())),
+        None => Ok(None),
+    }
 }
 
 ///
@@ -316,14 +730,14 @@ pub async fn recover_db(
 ///
 /// # Arguments
 ///
-/// * `target_pool`: application's database
+/// * `target_tx`: the transaction opened on the application's database
 /// * `transformations`: the transformations whose predicate is not primary key
 /// * `delete_age`: the number of years who is inactive will be deleted
 ///
 /// returns: Result<Vec<Transformation, Global>, MyError>
 ///
 pub async fn transfer_transformations(
-    target_pool: &MySqlPool,
+    target_tx: &mut Transaction<'_, MySql>,
     transformations: &Vec<Transformation>,
     delete_age: i64
 ) -> Result<Vec<Transformation>, MyError> {
@@ -336,10 +750,21 @@ pub async fn transfer_transformations(
     //transform the predicate into "last_login_time<delete_time"
     for transformation in transformations {
         //the transformation is for users
-        if transformation.predicate.is_some() {
+        if let Some(predicate) = &transformation.predicate {
+            //the predicate of the users is the name of the login time column
+            let column = match predicate {
+                Predicate::Raw(column) | Predicate::Condition { column, .. } => column.clone(),
+                _ => return Err(MyError::InvalidInput(
+                    "The predicate of the users should be the login time column.".to_string()
+                )),
+            };
             users_transformation = transformation.clone();
-            let predicate = users_transformation.predicate.as_ref().unwrap().clone();
-            users_transformation.predicate = Some(predicate + "<\"" + delete_time.to_string().as_str() + "\"");
+            users_transformation.predicate = Some(Predicate::Condition {
+                column,
+                operator: "<".to_string(),
+                value: Value::from(delete_time.format("%Y-%m-%d %H:%M:%S").to_string())
+            });
+            users_transformation.raw_sql = None;
         }else {
             //the transformation is for contributions
             contribution_transformations.push(transformation.clone());
@@ -347,24 +772,24 @@ pub async fn transfer_transformations(
     }
 
     //get the users who didn't login for a long time
-    let targets = get_targets_db(target_pool, &vec![users_transformation.clone()]).await?;
+    let targets = get_targets_db(target_tx, &vec![users_transformation.clone()]).await?;
     //transformed contribution transformations
     let mut new_contribution_transformations = vec![];
     for target in &targets[0] {
         let primary_key = target.primary_key().unwrap();
-        let predicate = primary_key.field_name.unwrap().clone() + "=" + primary_key.field_value.unwrap().as_str();
         let mut user_transformation = users_transformation.clone();
-        user_transformation.predicate = Some(predicate);
+        user_transformation.predicate = Some(field_predicate(&primary_key)?);
 
         for transformation in &contribution_transformations {
             let foreign_key = target.foreign_key(transformation.foreign_key.as_ref().unwrap().as_str()).unwrap();
-            let predicate = foreign_key.field_name.unwrap() + "=" + foreign_key.field_value.unwrap().as_str();
             new_contribution_transformations.push(Transformation {
                 transform_type: transformation.transform_type.clone(),
                 table_name: transformation.table_name.clone(),
-                predicate: Some(predicate),
+                predicate: Some(field_predicate(&foreign_key)?),
                 foreign_key: transformation.foreign_key.clone(),
-                changes: None
+                changes: None,
+                raw_sql: None,
+                unique_placeholder: transformation.unique_placeholder
             });
         }
 
@@ -381,28 +806,249 @@ pub async fn transfer_transformations(
     Ok(res)
 }
 
+///
+/// get the predicate of "field_name = field_value"
+/// the binary value is bound as the bytes,
+/// and the null value is selected by "IS NULL"
+///
+/// # Arguments
+///
+/// * `field`: the field of the target
+///
+/// returns: Result<Predicate, MyError>
+///
+pub fn field_predicate(field: &Field) -> Result<Predicate, MyError> {
+    let field_name = field.field_name.as_ref().unwrap();
+    let field_value = field.field_value.as_ref().unwrap();
+    match field_value {
+        FieldValue::Null => Ok(Predicate::is_null(field_name)),
+        FieldValue::Binary(value) => Ok(Predicate::eq(field_name, binary_value(value))),
+        _ => Ok(Predicate::eq(field_name, field_value.to_value())),
+    }
+}
+
+///
+/// decode the value of a field in the row by its column type
+/// the column type is the "Type" of "DESC table"
+///
+/// # Arguments
+///
+/// * `row`: the row of the target
+/// * `field_name`: the name of the field
+/// * `field_type`: the column type of the field
+///
+/// returns: Result<FieldValue, MyError>
+///
+pub fn decode_field_value(row: &MySqlRow, field_name: &str, field_type: &str) -> Result<FieldValue, MyError> {
+    if row.try_get_raw(field_name)?.is_null() {
+        return Ok(FieldValue::Null);
+    }
+    let field_type = field_type.to_lowercase();
+    let base_type = field_type.split(['(', ' ']).next().unwrap();
+    let value = match base_type {
+        "tinyint" | "smallint" | "mediumint" | "int" | "integer" | "bigint" | "year" => {
+            if field_type.contains("unsigned") {
+                FieldValue::UnsignedInt(row.try_get_unchecked(field_name)?)
+            } else {
+                let value: i64 = row.try_get_unchecked(field_name)?;
+                match value {
+                    0 | 1 if field_type.starts_with("tinyint(1)") => FieldValue::Bool(value == 1),
+                    _ => FieldValue::Int(value),
+                }
+            }
+        }
+        "bool" | "boolean" => FieldValue::Bool(row.try_get_unchecked::<i64, _>(field_name)? != 0),
+        "float" => FieldValue::Float(row.try_get_unchecked::<f32, _>(field_name)? as f64),
+        "double" | "real" => FieldValue::Float(row.try_get_unchecked(field_name)?),
+        "decimal" | "numeric" | "dec" | "fixed" => FieldValue::Decimal(row.try_get_unchecked(field_name)?),
+        "bit" => {
+            let bytes: Vec<u8> = row.try_get_unchecked(field_name)?;
+            FieldValue::Bit(bytes.iter().fold(0, |value, byte| (value << 8) | *byte as u64))
+        }
+        "binary" | "varbinary" | "tinyblob" | "blob" | "mediumblob" | "longblob"
+        | "geometry" | "point" | "linestring" | "polygon" | "multipoint"
+        | "multilinestring" | "multipolygon" | "geometrycollection" => {
+            let bytes: Vec<u8> = row.try_get_unchecked(field_name)?;
+            FieldValue::Binary(base64::encode(bytes))
+        }
+        "date" => FieldValue::Date(decode_date_time(&row.try_get_unchecked::<Vec<u8>, _>(field_name)?, false)),
+        "datetime" | "timestamp" => FieldValue::DateTime(decode_date_time(&row.try_get_unchecked::<Vec<u8>, _>(field_name)?, true)),
+        "time" => FieldValue::Time(decode_time(&row.try_get_unchecked::<Vec<u8>, _>(field_name)?)),
+        "json" => FieldValue::Json(row.try_get_unchecked(field_name)?),
+        //char, varchar, text, enum, set and the other string types
+        _ => FieldValue::Text(row.try_get_unchecked(field_name)?),
+    };
+    Ok(value)
+}
+
+///
+/// decode the binary protocol value of "DATE", "DATETIME" or "TIMESTAMP"
+/// the zero date "0000-00-00" is kept as it is
+///
+/// # Arguments
+///
+/// * `buf`: the value with its length at the first byte
+/// * `with_time`: if the time of the day should be included
+///
+/// returns: String
+///
+fn decode_date_time(buf: &[u8], with_time: bool) -> String {
+    let byte = |i: usize| buf.get(i).copied().unwrap_or(0) as u32;
+    let year = byte(1) | (byte(2) << 8);
+    let mut res = format!("{:04}-{:02}-{:02}", year, byte(3), byte(4));
+    if with_time {
+        res.push_str(format!(" {:02}:{:02}:{:02}", byte(5), byte(6), byte(7)).as_str());
+        let micro = byte(8) | (byte(9) << 8) | (byte(10) << 16) | (byte(11) << 24);
+        if micro > 0 {
+            res.push_str(format!(".{:06}", micro).as_str());
+        }
+    }
+    res
+}
+
+///
+/// decode the binary protocol value of "TIME"
+/// which could be negative or longer than one day
+///
+/// # Arguments
+///
+/// * `buf`: the value with its length at the first byte
+///
+/// returns: String
+///
+fn decode_time(buf: &[u8]) -> String {
+    let byte = |i: usize| buf.get(i).copied().unwrap_or(0) as u32;
+    let sign = if byte(1) == 1 { "-" } else { "" };
+    let days = byte(2) | (byte(3) << 8) | (byte(4) << 16) | (byte(5) << 24);
+    let mut res = format!("{}{:02}:{:02}:{:02}", sign, days * 24 + byte(6), byte(7), byte(8));
+    let micro = byte(9) | (byte(10) << 8) | (byte(11) << 16) | (byte(12) << 24);
+    if micro > 0 {
+        res.push_str(format!(".{:06}", micro).as_str());
+    }
+    res
+}
+
 ///
 /// delete the decorrelated targets
 /// when the disguises in vault are deleted
+/// the deleted targets are returned, so they could be inserted again
 ///
 /// # Arguments
 ///
-/// * `target_pool`: application's database
+/// * `target_tx`: the transaction opened on the application's database
 /// * `table_name`: the table name of the publications
-/// * `predicate`: the predicate of the publications
+/// * `predicate`: the predicate of the publications stored in the vault
 ///
-/// returns: Result<String, MyError>
+/// returns: Result<Vec<Target, Global>, MyError>
 ///
 pub async fn delete_decorrelated_targets_db(
-    target_pool: &MySqlPool,
+    target_tx: &mut Transaction<'_, MySql>,
     table_name: &str,
     predicate: &str
-) -> Result<String, MyError> {
-    let sql = "DELETE FROM ".to_string() + table_name + " WHERE " + predicate;
-    sqlx::query(sql.as_str())
-        .execute(target_pool)
+) -> Result<Vec<Target>, MyError> {
+    let predicate = Predicate::from_vault(predicate)?.render(false)?;
+    let deleted = select_targets_db(target_tx, table_name, &predicate).await?;
+    let sql = "DELETE FROM ".to_string() + quote_identifier(table_name)?.as_str() + " WHERE " + predicate.sql.as_str();
+    bind_values(sqlx::query(sql.as_str()), &predicate.values)
+        .execute(&mut *target_tx)
         .await?;
-    Ok("The decorrelated targets has been deleted.".to_string())
+    Ok(deleted)
+}
+
+///
+/// insert the rows deleted by the clearing again
+/// when the vault failed to record the clearing,
+/// the rows are inserted in the reverse order of the deletion
+///
+/// # Arguments
+///
+/// * `target_tx`: the transaction opened on the application's database
+/// * `rows`: the tables and the deleted rows
+///
+/// returns: Result<String, MyError>
+///
+pub async fn restore_targets_db(
+    target_tx: &mut Transaction<'_, MySql>,
+    rows: &[(String, Target)],
+) -> Result<String, MyError> {
+    for (table_name, target) in rows.iter().rev() {
+        insert_target_db(target_tx, quote_identifier(table_name)?.as_str(), target).await?;
+    }
+    Ok("The deleted targets have been restored.".to_string())
+}
+
+///
+/// bind the values of the rendered sql to the query in order
+///
+/// # Arguments
+///
+/// * `query`: the query with "?" placeholders
+/// * `values`: the values to bind
+///
+/// returns: Query<MySql, MySqlArguments>
+///
+pub fn bind_values<'q>(
+    mut query: Query<'q, MySql, MySqlArguments>,
+    values: &'q [Value]
+) -> Query<'q, MySql, MySqlArguments> {
+    for value in values {
+        query = match value {
+            Value::Null => query.bind(None::<String>),
+            Value::Bool(value) => query.bind(*value),
+            Value::Number(number) => {
+                if let Some(number) = number.as_i64() {
+                    query.bind(number)
+                } else if let Some(number) = number.as_u64() {
+                    query.bind(number)
+                } else {
+                    query.bind(number.as_f64())
+                }
+            }
+            Value::String(value) => query.bind(value.as_str()),
+            Value::Object(_) => match decode_binary(value) {
+                Some(bytes) => query.bind(bytes),
+                None => query.bind(value.to_string()),
+            },
+            _ => query.bind(value.to_string()),
+        };
+    }
+    query
+}
+
+///
+/// bind the values of the fields to the query in order
+/// the binary values are decoded from base64
+///
+/// # Arguments
+///
+/// * `query`: the query with "?" placeholders
+/// * `values`: the values of the fields to bind
+///
+/// returns: Result<Query<MySql, MySqlArguments>, MyError>
+///
+pub fn bind_field_values<'q>(
+    mut query: Query<'q, MySql, MySqlArguments>,
+    values: &'q [FieldValue]
+) -> Result<Query<'q, MySql, MySqlArguments>, MyError> {
+    for value in values {
+        query = match value {
+            FieldValue::Null => query.bind(None::<String>),
+            FieldValue::Int(value) => query.bind(*value),
+            FieldValue::UnsignedInt(value) | FieldValue::Bit(value) => query.bind(*value),
+            FieldValue::Float(value) => query.bind(*value),
+            FieldValue::Bool(value) => query.bind(*value),
+            FieldValue::Binary(value) => query.bind(base64::decode(value).map_err(|_err| {
+                MyError::OperationError("The binary value is not correct.".to_string())
+            })?),
+            FieldValue::Decimal(value)
+            | FieldValue::Text(value)
+            | FieldValue::Date(value)
+            | FieldValue::Time(value)
+            | FieldValue::DateTime(value)
+            | FieldValue::Json(value) => query.bind(value.as_str()),
+        };
+    }
+    Ok(query)
 }
 
 //get the state of the objects before or after the transformations
@@ -415,8 +1061,8 @@ pub async fn delete_decorrelated_targets_db(
 //     //log all the fields' value of the affected data
 //     //put them into res
 //     for transformation in transformations {
-//         let table = transformation.table_name.as_ref().clone().unwrap().as_str();
-//         let predicate = transformation.predicate.as_ref().clone().unwrap().as_str();
+//         let table = transformation.table_name.as_ref().unwrap().as_str();
+//         let predicate = transformation.predicate.as_ref().unwrap().as_str();
 //         let mut all_values = vec![];
 //         //get the affected data
 //         let sql = "SELECT * FROM ".to_string() + table + " WHERE " + predicate;
@@ -508,4 +1154,42 @@ pub async fn delete_decorrelated_targets_db(
 //     }
 //
 //     Ok(keys_vec)
-// }
\ No newline at end of file
+// }
+#[cfg(test)]
+mod tests {
+    use crate::dbaccess::target::{decode_date_time, decode_time, field_predicate};
+    use crate::models::predicate::{decode_binary, Predicate};
+    use crate::models::target::{Field, FieldValue};
+
+    #[test]
+    fn decode_date_time_test() {
+        assert_eq!(decode_date_time(&[4, 0xe6, 0x07, 7, 14], false), "2022-07-14");
+        assert_eq!(decode_date_time(&[0], false), "0000-00-00");
+        assert_eq!(decode_date_time(&[7, 0xe6, 0x07, 7, 14, 9, 5, 3], true), "2022-07-14 09:05:03");
+        assert_eq!(
+            decode_date_time(&[11, 0xe6, 0x07, 7, 14, 9, 5, 3, 0x40, 0xe2, 0x01, 0], true),
+            "2022-07-14 09:05:03.123456"
+        );
+    }
+
+    #[test]
+    fn decode_time_test() {
+        assert_eq!(decode_time(&[0]), "00:00:00");
+        assert_eq!(decode_time(&[8, 1, 1, 0, 0, 0, 2, 30, 0]), "-26:30:00");
+    }
+
+    #[test]
+    fn field_predicate_test() {
+        let field = |value: FieldValue| Field {
+            field_name: Some("user_id".to_string()),
+            field_type: Some("binary(16)".to_string()),
+            field_value: Some(value)
+        };
+        //the uuid stored in binary(16) is bound as its bytes
+        let predicate = field_predicate(&field(FieldValue::Binary("AAECAwQFBgcICQoLDA0ODw==".to_string()))).unwrap();
+        let fragment = predicate.render(false).unwrap();
+        assert_eq!(fragment.sql, "`user_id` = ?");
+        assert_eq!(decode_binary(&fragment.values[0]), Some((0..16).collect()));
+        assert_eq!(field_predicate(&field(FieldValue::Null)).unwrap(), Predicate::is_null("user_id"));
+    }
+}
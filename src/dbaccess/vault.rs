@@ -1,11 +1,15 @@
 use chrono::{Duration, Local};
-use sqlx::mysql::MySqlRow;
-use sqlx::MySqlPool;
-use crate::dbaccess::target::delete_decorrelated_targets_db;
+use sqlx::{MySql, MySqlPool, Transaction};
+use crate::dbaccess::target::{delete_decorrelated_targets_db, delete_placeholders_db, field_predicate, select_targets_db};
 use crate::error::MyError;
+use crate::models::crypto::{fingerprint, KeyStore, VaultKey, VaultKeys};
+use crate::models::placeholder::{new_uuid, PlaceholderMapping};
+use crate::models::predicate::Predicate;
+use crate::models::recovery::StackedFunction;
 use crate::models::requirement::Requirement;
 use crate::models::target::Target;
-use crate::models::vault::{Disguise, DisguiseFromDB, Function, Vault};
+use crate::models::transformation::Transformation;
+use crate::models::vault::{ClearedDisguises, Disguise, DisguiseFromDB, DisguiseState, Function, FunctionCount, Tombstone, Vault};
 
 
 ///
@@ -14,18 +18,22 @@ use crate::models::vault::{Disguise, DisguiseFromDB, Function, Vault};
 ///
 /// # Arguments
 ///
-/// * `vault_db`: the server's database
+/// * `vault_tx`: the transaction opened on the server's database
 /// * `vault`: the vault to generate
 ///
 /// returns: Result<String, MyError>
 ///
 ///
-pub async fn generate_vault_db(vault_db: &MySqlPool, vault: Vault) -> Result<String, MyError> {
-    sqlx::query("INSERT INTO vault (vault_id, email, placeholder_info) values (?, ? , ?)")
+pub async fn generate_vault_db(vault_tx: &mut Transaction<'_, MySql>, vault: Vault) -> Result<String, MyError> {
+    let sql = "INSERT INTO vault (vault_id, email, placeholder_info, recovery_public_key, recovery_private_key, recovery_salt) values (?, ?, ?, ?, ?, ?)";
+    sqlx::query(sql)
         .bind(vault.vault_id.clone())
         .bind(vault.email.clone())
         .bind(vault.placeholder_info.clone())
-        .execute(vault_db)
+        .bind(vault.recovery_public_key.clone())
+        .bind(vault.recovery_private_key.clone())
+        .bind(vault.recovery_salt.clone())
+        .execute(&mut *vault_tx)
         .await?;
 
     Ok("The vault has been generated.".into())
@@ -71,113 +79,362 @@ pub async fn get_vault_by_email_db(vault_db: &MySqlPool, email: &str) -> Result<
 
 ///
 /// upload the applied disguise into vault
+/// the uploaded disguise is returned,
+/// so that the target database could be recovered from it
+/// if the vault transaction failed to commit
+/// the original state is not stored if the disguise is not reversible,
+/// and it is encrypted by a new data key of the disguise
+/// if the server has the master key or the vault has the recovery key,
+/// the data key is stored wrapped by them
+/// the wrapped key for the key store is returned with the disguise,
+/// which is only written into the store after the vault is committed
 ///
 /// # Arguments
 ///
-/// * `vault_pool`: the server's database
+/// * `vault_tx`: the transaction opened on the server's database
 /// * `requirement`: the disguise's requirement
+/// * `transformations`: the transformations which have been executed
 /// * `originals`: the original state of the targets of transformations
-/// * `updated`: the changes of the transformations
+/// * `updated`: the state of the targets after the transformations
+/// * `reversible`: if the original state of the targets is stored
+/// * `placeholders`: the placeholders generated for the decorrelated rows
+/// * `keys`: the keys to wrap the data key of the disguise
 ///
-/// returns: Result<String, MyError>
+/// returns: Result<(Disguise, Option<String>), MyError>
 ///
+#[allow(clippy::too_many_arguments)]
 pub async fn upload_disguise_db(
-    vault_pool: &MySqlPool,
+    vault_tx: &mut Transaction<'_, MySql>,
     requirement: &Requirement,
+    transformations: &[Transformation],
     originals: Vec<Vec<Target>>,
-    updated: Vec<String>,
-) -> Result<String, MyError> {
-    let time = Local::now().to_string();
-    let disguise_type = requirement.disguise_name.as_ref().unwrap().clone();
-    let vault_id = requirement.vault_id.as_ref().unwrap().clone();
-    let transformations = requirement.transformations.as_ref().unwrap();
-
-    //insert the disguise into database
-    let disguise_id = sqlx::query("INSERT INTO disguise (time, vault_id, disguise_type) values (?, ?, ?)")
-        .bind(time)
-        .bind(vault_id)
-        .bind(disguise_type)
-        .execute(vault_pool)
-        .await?
-        .last_insert_id();
-
-    //insert the functions into database
+    updated: Vec<Vec<Option<Target>>>,
+    reversible: bool,
+    placeholders: Vec<PlaceholderMapping>,
+    keys: &VaultKeys,
+) -> Result<(Disguise, Option<String>), MyError> {
+    let mut functions = vec![];
+    //iterate all the rows affected by the same transformation
     for (i, transformation) in transformations.iter().enumerate() {
-        let transform_type = transformation.transform_type.as_ref().unwrap();
-        let table = transformation.table_name.as_ref().unwrap();
-        let original_vec = &originals[i];
-        //iterate all the rows affected by the same transformation
-        for original in original_vec {
+        for (j, original) in originals[i].iter().enumerate() {
             let key = original.primary_key().unwrap();
-            //insert the functions into the database
-            let sql = "INSERT INTO function (disguise_id, function_type, table_name, predicate, original, updated) values (?, ?, ?, ?, ?, ?)";
-            let predicate = String::from(key.field_name.unwrap()) + "=" + key.field_value.unwrap().as_str();
-            let original_values = original.field_values().unwrap();
-            sqlx::query(sql)
-                .bind(disguise_id)
-                .bind(transform_type)
-                .bind(table)
-                .bind(predicate)
-                .bind(original_values)
-                .bind(updated.get(i))
-                .execute(vault_pool)
-                .await?;
+            functions.push(Function {
+                function_id: None,
+                disguise_id: None,
+                function_type: transformation.transform_type.clone(),
+                table_name: transformation.table_name.clone(),
+                predicate: Some(field_predicate(&key)?.to_vault()),
+                original: Some(original.to_vault()),
+                updated: updated[i][j].as_ref().map(|target| target.to_vault()),
+                recovered_time: None
+            });
         }
     }
+    let mut disguise = Disguise {
+        disguise_id: None,
+        public_id: None,
+        time: Some(Local::now().to_string()),
+        vault_id: requirement.vault_id.clone(),
+        disguise_type: requirement.disguise_name.clone(),
+        status: Some("applied".to_string()),
+        functions: Some(functions),
+        placeholders: Some(placeholders)
+    };
 
-    Ok("".to_string())
+    //insert the disguise and its functions into database
+    //the returned disguise keeps the original and updated states in plaintext
+    let data_key = keys.new_data_key()?;
+    let mut stored = disguise.clone();
+    for function in stored.functions.as_mut().unwrap() {
+        function.original = match (reversible, &data_key) {
+            (false, _) => None,
+            (true, Some((data_key, _))) => function.original.as_deref().map(|original| data_key.encrypt(original)),
+            (true, None) => function.original.take(),
+        };
+        //the updated state keeps the fields which are not changed by the disguise
+        if let Some((data_key, _)) = &data_key {
+            function.updated = function.updated.as_deref().map(|updated| data_key.encrypt(updated));
+        }
+    }
+    let (disguise_id, public_id) = upload_disguise_object_db(vault_tx, &stored).await?;
+    let mut pending_key = None;
+    if let Some((_, wrapped)) = data_key {
+        //the data key in the key store is only identified by its fingerprint in the database
+        let (wrapped_key, key_fingerprint) = match &keys.key_store {
+            Some(_) => {
+                let key_fingerprint = fingerprint(wrapped.as_str());
+                pending_key = Some(wrapped);
+                (None, Some(key_fingerprint))
+            }
+            None => (Some(wrapped), None),
+        };
+        let sql = "INSERT INTO data_key (disguise_id, vault_id, wrapped_key, key_fingerprint, time) VALUES (?, ?, ?, ?, ?)";
+        sqlx::query(sql)
+            .bind(disguise_id)
+            .bind(disguise.vault_id.as_deref())
+            .bind(wrapped_key)
+            .bind(key_fingerprint)
+            .bind(disguise.time.as_deref())
+            .execute(&mut *vault_tx)
+            .await?;
+    }
+    disguise.disguise_id = Some(disguise_id);
+    disguise.public_id = Some(public_id);
+    Ok((disguise, pending_key))
 }
 
 ///
-/// download the disguise in vault for recovering
+/// write the wrapped data key of the committed disguise into the key store,
+/// so the rolled back disguises leave no key in the store
+/// the key is kept in the vault database instead if the store fails,
+/// then the disguise could still be recovered, but not crypto-shredded
+///
+/// # Arguments
+///
+/// * `vault_pool`: the server's database
+/// * `key_store`: the store of the data keys outside the vault database
+/// * `disguise_id`: the id of the committed disguise
+/// * `wrapped`: the wrapped data key of the disguise
+///
+/// returns: Result<String, MyError>
+///
+pub async fn store_data_key_db(
+    vault_pool: &MySqlPool,
+    key_store: &KeyStore,
+    disguise_id: i32,
+    wrapped: &str,
+) -> Result<String, MyError> {
+    if let Err(err) = key_store.put(disguise_id, wrapped) {
+        println!("The data key could not be stored, it is kept in the vault database: {}", err);
+        sqlx::query("UPDATE data_key SET wrapped_key=? WHERE disguise_id=?")
+            .bind(wrapped)
+            .bind(disguise_id)
+            .execute(vault_pool)
+            .await?;
+        return Ok("The data key has been kept in the vault database.".to_string());
+    }
+    Ok("The data key has been stored.".to_string())
+}
+
+///
+/// find the disguises of the vault
+/// by the public id of the disguise,
+/// or by the disguise type with the selector "latest" or "all"
+/// the disguises are sorted from the newest to the oldest
 ///
 /// # Arguments
 ///
 /// * `vault_pool`: the server's database
-/// * `disguise_name`: the name of the disguise
 /// * `vault_id`: the id of the vault
+/// * `public_id`: the public id of the disguise
+/// * `disguise_name`: the type of the disguise
+/// * `selector`: which disguises of the type are selected
+///
+/// returns: Result<Vec<DisguiseFromDB, Global>, MyError>
+///
+pub async fn find_disguises_db(
+    vault_pool: &MySqlPool,
+    vault_id: &str,
+    public_id: Option<&str>,
+    disguise_name: Option<&str>,
+    selector: &str,
+) -> Result<Vec<DisguiseFromDB>, MyError> {
+    let mut disguises: Vec<DisguiseFromDB> = match (public_id, disguise_name) {
+        //get disguise by public id
+        (Some(public_id), _) => {
+            let sql = "SELECT * FROM disguise WHERE public_id=? and vault_id=?";
+            sqlx::query_as(sql)
+                .bind(public_id)
+                .bind(vault_id)
+                .fetch_all(vault_pool)
+                .await?
+        }
+        //get disguises by type
+        (None, Some(disguise_name)) => {
+            let sql = "SELECT * FROM disguise WHERE disguise_type=? and vault_id=? \
+                and status NOT IN ('recovered', 'cleared') ORDER BY disguise_id DESC";
+            sqlx::query_as(sql)
+                .bind(disguise_name)
+                .bind(vault_id)
+                .fetch_all(vault_pool)
+                .await?
+        }
+        (None, None) => {
+            return Err(MyError::InvalidInput("The disguise id or the disguise name is required.".into()));
+        }
+    };
+    if disguises.is_empty() {
+        return Err(MyError::NotFound("The disguise is not found.".into()));
+    }
+    match selector {
+        "latest" => {
+            disguises.truncate(1);
+            Ok(disguises)
+        }
+        "all" => Ok(disguises),
+        _ => Err(MyError::InvalidInput("The selector should be \"latest\" or \"all\".".into())),
+    }
+}
+
+///
+/// get all the disguises applied to the vault
+/// the latest one is the first
+///
+/// # Arguments
+///
+/// * `vault_pool`: the server's database
+/// * `vault_id`: the id of the vault
+///
+/// returns: Result<Vec<DisguiseFromDB, Global>, MyError>
+///
+pub async fn list_disguises_db(
+    vault_pool: &MySqlPool,
+    vault_id: &str,
+) -> Result<Vec<DisguiseFromDB>, MyError> {
+    let sql = "SELECT * FROM disguise WHERE vault_id=? ORDER BY disguise_id DESC";
+    let disguises: Vec<DisguiseFromDB> = sqlx::query_as(sql)
+        .bind(vault_id)
+        .fetch_all(vault_pool)
+        .await?;
+    Ok(disguises)
+}
+
+///
+/// get the number of the functions of the disguise
+/// by their tables and types
+///
+/// # Arguments
+///
+/// * `vault_pool`: the server's database
+/// * `disguise_id`: the id of the disguise in the vault
+///
+/// returns: Result<Vec<FunctionCount, Global>, MyError>
+///
+pub async fn count_functions_db(
+    vault_pool: &MySqlPool,
+    disguise_id: i32,
+) -> Result<Vec<FunctionCount>, MyError> {
+    let sql = "SELECT table_name, function_type, COUNT(*) AS count FROM function \
+        WHERE disguise_id=? GROUP BY table_name, function_type ORDER BY table_name, function_type";
+    let counts: Vec<FunctionCount> = sqlx::query_as(sql)
+        .bind(disguise_id)
+        .fetch_all(vault_pool)
+        .await?;
+    Ok(counts)
+}
+
+///
+/// download the disguise in vault for recovering
+///
+/// # Arguments
+///
+/// * `vault_pool`: the server's database
+/// * `public_id`: the public id of the disguise
 ///
 /// returns: Result<Disguise, MyError>
 ///
 pub async fn download_disguise_db(
     vault_pool: &MySqlPool,
-    disguise_name: &str,
-    vault_id: &str,
+    public_id: &str,
 ) -> Result<Disguise, MyError> {
-    //get disguise by id and type
-    let sql = "SELECT * FROM disguise WHERE disguise_type=? and vault_id=?";
+    //get disguise by public id
+    let sql = "SELECT * FROM disguise WHERE public_id=?";
     let disguise: DisguiseFromDB = sqlx::query_as(sql)
-        .bind(disguise_name)
-        .bind(vault_id)
+        .bind(public_id)
         .fetch_one(vault_pool)
         .await?;
     //get functions by disguise id
-    let sql = "SELECT * FROM function WHERE disguise_id=?";
+    let sql = "SELECT * FROM function WHERE disguise_id=? ORDER BY function_id";
     let functions: Vec<Function> = sqlx::query_as(sql)
         .bind(disguise.disguise_id)
         .fetch_all(vault_pool)
         .await?;
+    //get the generated placeholders by disguise id
+    let sql = "SELECT table_name, predicate, placeholder_table, placeholder_predicate FROM placeholder_mapping WHERE disguise_id=?";
+    let placeholders: Vec<PlaceholderMapping> = sqlx::query_as(sql)
+        .bind(disguise.disguise_id)
+        .fetch_all(vault_pool)
+        .await?;
     let mut disguise: Disguise = disguise.into();
     disguise.functions = Some(functions);
+    disguise.placeholders = Some(placeholders);
     Ok(disguise)
 }
 
-pub async fn upload_disguise_object_db(
+///
+/// get the data key of the disguise to decrypt its original state
+/// the disguise encrypted before the data keys has no data key,
+/// so the vault's key is returned for it
+/// the vault's key is derived from the master key and could not be destroyed alone,
+/// so the copies of such a disguise in the backups stay decryptable after it is cleared,
+/// and its tombstone is only "delete"
+///
+/// # Arguments
+///
+/// * `vault_pool`: the server's database
+/// * `disguise_id`: the id of the disguise in the vault
+/// * `keys`: the keys to unwrap the data key
+///
+/// returns: Result<Option<VaultKey>, MyError>
+///
+pub async fn get_data_key_db(
     vault_pool: &MySqlPool,
+    disguise_id: i32,
+    keys: &VaultKeys,
+) -> Result<Option<VaultKey>, MyError> {
+    let sql = "SELECT wrapped_key FROM data_key WHERE disguise_id=?";
+    let wrapped: Option<(Option<String>, )> = sqlx::query_as(sql)
+        .bind(disguise_id)
+        .fetch_optional(vault_pool)
+        .await?;
+    match wrapped {
+        Some((Some(wrapped), )) => keys.open_data_key(wrapped.as_str()).map(Some),
+        //the data key is kept in the key store
+        Some((None, )) => {
+            let key_store = keys.key_store.as_ref().ok_or_else(|| MyError::OperationError(
+                "The key store of the data keys is not configured.".to_string()
+            ))?;
+            let wrapped = key_store.get(disguise_id)?.ok_or_else(|| MyError::OperationError(
+                "The data key of the disguise has been destroyed.".to_string()
+            ))?;
+            keys.open_data_key(wrapped.as_str()).map(Some)
+        }
+        None => Ok(keys.vault_key.clone()),
+    }
+}
+
+///
+/// upload a disguise object with all its functions and placeholders into vault
+/// the disguise keeps its public id,
+/// or a new public id is generated for it
+///
+/// # Arguments
+///
+/// * `vault_tx`: the transaction opened on the server's database
+/// * `disguise`: the disguise to upload
+///
+/// returns: Result<(i32, String), MyError>
+///
+pub async fn upload_disguise_object_db(
+    vault_tx: &mut Transaction<'_, MySql>,
     disguise: &Disguise
-) -> Result<String, MyError> {
+) -> Result<(i32, String), MyError> {
+    let public_id = disguise.public_id.clone().unwrap_or_else(new_public_id);
     let time = disguise.time.as_ref().unwrap().as_str();
     let vault_id = disguise.vault_id.as_ref().unwrap().as_str();
     let disguise_type = disguise.disguise_type.as_ref().unwrap().as_str();
+    let status = disguise.status.as_deref().unwrap_or("applied");
     let functions = disguise.functions.as_ref().unwrap().clone();
 
-    let sql = "INSERT INTO disguise (time, vault_id, disguise_type) VALUES (?, ?, ?)";
+    let sql = "INSERT INTO disguise (public_id, time, vault_id, disguise_type, status) VALUES (?, ?, ?, ?, ?)";
     //insert the disguise into database
     let disguise_id = sqlx::query(sql)
+        .bind(public_id.as_str())
         .bind(time)
         .bind(vault_id)
         .bind(disguise_type)
-        .execute(vault_pool)
+        .bind(status)
+        .execute(&mut *vault_tx)
         .await?
         .last_insert_id();
 
@@ -185,10 +442,10 @@ pub async fn upload_disguise_object_db(
         let transform_type = function.function_type.as_ref().unwrap().as_str();
         let table = function.table_name.as_ref().unwrap().as_str();
         let predicate = function.predicate.as_ref().unwrap().as_str();
-        let original_values = function.original.as_ref().unwrap().as_str();
-        let updated = function.updated.as_ref().unwrap().as_str();
+        let original_values = function.original.as_deref();
+        let updated = function.updated.as_deref();
 
-        let sql = "INSERT INTO function (disguise_id, function_type, table_name, predicate, original, updated) VALUES(?, ?, ?, ?, ?, ?)";
+        let sql = "INSERT INTO function (disguise_id, function_type, table_name, predicate, original, updated, recovered_time) VALUES(?, ?, ?, ?, ?, ?, ?)";
         sqlx::query(sql)
             .bind(disguise_id)
             .bind(transform_type)
@@ -196,177 +453,533 @@ pub async fn upload_disguise_object_db(
             .bind(predicate)
             .bind(original_values)
             .bind(updated)
-            .execute(vault_pool)
+            .bind(function.recovered_time.as_deref())
+            .execute(&mut *vault_tx)
             .await?;
     }
+    let sql = "INSERT INTO disguise_history (disguise_id, status, time) VALUES (?, ?, ?)";
+    sqlx::query(sql)
+        .bind(disguise_id)
+        .bind(status)
+        .bind(time)
+        .execute(&mut *vault_tx)
+        .await?;
 
-    Ok("The disguise object has been uploaded.".to_string())
+    for placeholder in disguise.placeholders.as_deref().unwrap_or_default() {
+        let sql = "INSERT INTO placeholder_mapping (disguise_id, table_name, predicate, placeholder_table, placeholder_predicate) VALUES(?, ?, ?, ?, ?)";
+        sqlx::query(sql)
+            .bind(disguise_id)
+            .bind(placeholder.table_name.as_deref())
+            .bind(placeholder.predicate.as_deref())
+            .bind(placeholder.placeholder_table.as_deref())
+            .bind(placeholder.placeholder_predicate.as_deref())
+            .execute(&mut *vault_tx)
+            .await?;
+        //the placeholder is owned by the vault until the disguise is deleted
+        let sql = "INSERT INTO owned_placeholder (vault_id, disguise_id, placeholder_table, placeholder_predicate, time) VALUES(?, ?, ?, ?, ?)";
+        sqlx::query(sql)
+            .bind(vault_id)
+            .bind(disguise_id)
+            .bind(placeholder.placeholder_table.as_deref())
+            .bind(placeholder.placeholder_predicate.as_deref())
+            .bind(time)
+            .execute(&mut *vault_tx)
+            .await?;
+    }
+
+    Ok((disguise_id as i32, public_id))
+}
+
+/// generate a random public id of the disguise in the format of uuid
+pub fn new_public_id() -> String {
+    new_uuid()
 }
 
 ///
-/// delete the disguise in the vault after recover
+/// move the disguise to the status, and record the time it is entered in its history
 ///
 /// # Arguments
 ///
-/// * `vault_pool`: the server's database
-/// * `disguise_name`: the name of the disguise
-/// * `vault_id`: the id of the vault
+/// * `vault_tx`: the transaction opened on the server's database
+/// * `disguise_id`: the id of the disguise in the vault
+/// * `status`: "recovering", "partially_recovered", "recovered" or "cleared"
 ///
 /// returns: Result<String, MyError>
 ///
-pub async fn delete_disguise_db(
-    vault_pool: &MySqlPool,
-    disguise_name: &str,
-    vault_id: &str,
+pub async fn set_disguise_status_db(
+    vault_tx: &mut Transaction<'_, MySql>,
+    disguise_id: i32,
+    status: &str,
 ) -> Result<String, MyError> {
-    //get disguise by id and type
-    let sql = "SELECT * FROM disguise WHERE disguise_type=? and vault_id=?";
-    let disguise: DisguiseFromDB = sqlx::query_as(sql)
-        .bind(disguise_name)
-        .bind(vault_id)
-        .fetch_one(vault_pool)
+    sqlx::query("UPDATE disguise SET status=? WHERE disguise_id=?")
+        .bind(status)
+        .bind(disguise_id)
+        .execute(&mut *vault_tx)
         .await?;
-    //delete functions by disguise id
-    let sql = "DELETE FROM function WHERE disguise_id=?";
+    let sql = "INSERT INTO disguise_history (disguise_id, status, time) VALUES (?, ?, ?)";
     sqlx::query(sql)
-        .bind(disguise.disguise_id)
-        .execute(vault_pool)
+        .bind(disguise_id)
+        .bind(status)
+        .bind(Local::now().to_string())
+        .execute(&mut *vault_tx)
         .await?;
-    //delete the disguise
-    let sql = "DELETE FROM disguise WHERE disguise_id=?";
-    sqlx::query(sql)
-        .bind(disguise.disguise_id)
-        .execute(vault_pool)
+    Ok(format!("The disguise is {}.", status))
+}
+
+///
+/// get the states the disguise has entered from the oldest one
+///
+/// # Arguments
+///
+/// * `vault_pool`: the server's database
+/// * `disguise_id`: the id of the disguise in the vault
+///
+/// returns: Result<Vec<DisguiseState, Global>, MyError>
+///
+pub async fn get_disguise_history_db(
+    vault_pool: &MySqlPool,
+    disguise_id: i32,
+) -> Result<Vec<DisguiseState>, MyError> {
+    let sql = "SELECT status, time FROM disguise_history WHERE disguise_id=? ORDER BY history_id";
+    let history: Vec<DisguiseState> = sqlx::query_as(sql)
+        .bind(disguise_id)
+        .fetch_all(vault_pool)
+        .await?;
+    Ok(history)
+}
+
+///
+/// get the functions of the disguise whose rows have been changed again
+/// by the later disguises of the same vault which are not recovered,
+/// the later functions are ordered from the oldest one
+/// the removed row is not stacked, since the later disguise could only change another row with its key
+///
+/// # Arguments
+///
+/// * `vault_pool`: the server's database
+/// * `disguise_id`: the id of the disguise in the vault
+///
+/// returns: Result<Vec<StackedFunction, Global>, MyError>
+///
+pub async fn get_stacked_functions_db(
+    vault_pool: &MySqlPool,
+    disguise_id: i32,
+) -> Result<Vec<StackedFunction>, MyError> {
+    let sql = "SELECT function.function_id, function.table_name, function.predicate, \
+        later_disguise.public_id AS later_disguise_id, later.function_id AS later_function_id \
+        FROM function \
+        JOIN disguise ON disguise.disguise_id=function.disguise_id \
+        JOIN function AS later ON later.table_name=function.table_name and later.predicate=function.predicate \
+            and later.disguise_id>function.disguise_id \
+        JOIN disguise AS later_disguise ON later_disguise.disguise_id=later.disguise_id \
+        WHERE function.disguise_id=? and function.function_type<>'removal' and function.recovered_time IS NULL \
+            and later.recovered_time IS NULL and later_disguise.vault_id=disguise.vault_id \
+            and later_disguise.status NOT IN ('recovered', 'cleared') \
+        ORDER BY function.function_id, later.disguise_id, later.function_id";
+    let stacked: Vec<StackedFunction> = sqlx::query_as(sql)
+        .bind(disguise_id)
+        .fetch_all(vault_pool)
+        .await?;
+    Ok(stacked)
+}
+
+///
+/// get the function by its id
+///
+/// # Arguments
+///
+/// * `vault_pool`: the server's database
+/// * `function_id`: the id of the function
+///
+/// returns: Result<Function, MyError>
+///
+pub async fn get_function_db(
+    vault_pool: &MySqlPool,
+    function_id: i32,
+) -> Result<Function, MyError> {
+    let function: Function = sqlx::query_as("SELECT * FROM function WHERE function_id=?")
+        .bind(function_id)
+        .fetch_one(vault_pool)
+        .await?;
+    Ok(function)
+}
+
+///
+/// store the original and the updated state of the later functions
+/// rewritten without the disguise undone under them
+///
+/// # Arguments
+///
+/// * `vault_tx`: the transaction opened on the server's database
+/// * `functions`: the rewritten functions
+///
+/// returns: Result<String, MyError>
+///
+pub async fn rewrite_functions_db(
+    vault_tx: &mut Transaction<'_, MySql>,
+    functions: &[Function],
+) -> Result<String, MyError> {
+    for function in functions {
+        sqlx::query("UPDATE function SET original=?, updated=? WHERE function_id=?")
+            .bind(function.original.as_deref())
+            .bind(function.updated.as_deref())
+            .bind(function.function_id)
+            .execute(&mut *vault_tx)
+            .await?;
+    }
+    Ok("The later functions have been rewritten.".to_string())
+}
+
+///
+/// mark the function of the recovered step as recovered
+/// its original and updated state are erased,
+/// and the placeholders of its row are deleted from the vault
+/// the function is not recovered again when the recovery is resumed
+/// the skipped functions are kept with their placeholders,
+/// so the disguise is only partially recovered
+///
+/// # Arguments
+///
+/// * `vault_tx`: the transaction opened on the server's database
+/// * `step`: the recovered step of the disguise
+/// * `skipped`: the functions of the step which have not been recovered
+///
+/// returns: Result<String, MyError>
+///
+pub async fn mark_recovered_db(
+    vault_tx: &mut Transaction<'_, MySql>,
+    step: &Disguise,
+    skipped: &[Function],
+) -> Result<String, MyError> {
+    let time = Local::now().to_string();
+    let is_skipped = |table_name: &Option<String>, predicate: &Option<String>| skipped.iter()
+        .any(|function| &function.table_name == table_name && &function.predicate == predicate);
+    for function in step.functions.as_deref().unwrap_or_default() {
+        if is_skipped(&function.table_name, &function.predicate) {
+            continue;
+        }
+        sqlx::query("UPDATE function SET original=NULL, updated=NULL, recovered_time=? WHERE function_id=?")
+            .bind(time.as_str())
+            .bind(function.function_id)
+            .execute(&mut *vault_tx)
+            .await?;
+    }
+    for placeholder in step.placeholders.as_deref().unwrap_or_default() {
+        if is_skipped(&placeholder.table_name, &placeholder.predicate) {
+            continue;
+        }
+        for sql in [
+            "DELETE FROM placeholder_mapping WHERE disguise_id=? and placeholder_table=? and placeholder_predicate=?",
+            "DELETE FROM owned_placeholder WHERE disguise_id=? and placeholder_table=? and placeholder_predicate=?",
+        ] {
+            sqlx::query(sql)
+                .bind(step.disguise_id)
+                .bind(placeholder.placeholder_table.as_deref())
+                .bind(placeholder.placeholder_predicate.as_deref())
+                .execute(&mut *vault_tx)
+                .await?;
+        }
+    }
+    Ok("The recovered functions of the disguise have been marked.".to_string())
+}
+
+///
+/// finish the recovery of the disguise after its selected functions are marked
+/// the disguise with all its functions recovered is kept as "recovered",
+/// and its data key is deleted since nothing is encrypted by it anymore
+/// the disguise with the rest of its functions is "partially_recovered"
+///
+/// # Arguments
+///
+/// * `vault_tx`: the transaction opened on the server's database
+/// * `disguise_id`: the id of the disguise in the vault
+///
+/// returns: Result<String, MyError>, the status of the disguise
+///
+pub async fn finish_recovery_db(
+    vault_tx: &mut Transaction<'_, MySql>,
+    disguise_id: i32,
+) -> Result<String, MyError> {
+    let sql = "SELECT COUNT(*) FROM function WHERE disguise_id=? and recovered_time IS NULL";
+    let (pending, ): (i64, ) = sqlx::query_as(sql)
+        .bind(disguise_id)
+        .fetch_one(&mut *vault_tx)
         .await?;
-    Ok("The disguise in the vault has been deleted.".to_string())
+    let status = if pending == 0 { "recovered" } else { "partially_recovered" };
+    if pending == 0 {
+        for sql in [
+            "DELETE FROM placeholder_mapping WHERE disguise_id=?",
+            "DELETE FROM owned_placeholder WHERE disguise_id=?",
+            "DELETE FROM data_key WHERE disguise_id=?",
+        ] {
+            sqlx::query(sql)
+                .bind(disguise_id)
+                .execute(&mut *vault_tx)
+                .await?;
+        }
+    }
+    set_disguise_status_db(vault_tx, disguise_id, status).await?;
+    Ok(status.to_string())
 }
 
 ///
-/// delete the old disguises in the vault
+/// clear the old disguises in the vault
 /// by the number of the years between right now and the applied time
 /// And the decorrelated publications in application's database will be deleted
+/// the recovered and the cleared disguises are not cleared again
 ///
 /// # Arguments
 ///
-/// * `vault_pool`: the server's database
+/// * `target_tx`: the transaction opened on the application's database
+/// * `vault_tx`: the transaction opened on the server's database
 /// * `age`: the number of the years between right now and the applied time
+/// * `key_store`: the store of the data keys outside the vault database
+/// * `cleared`: the cleared disguises collected for the caller
 ///
 /// returns: Result<String, MyError>
 ///
 pub async fn delete_disguise_by_age_db(
-    target_pool: &MySqlPool,
-    vault_pool: &MySqlPool,
+    target_tx: &mut Transaction<'_, MySql>,
+    vault_tx: &mut Transaction<'_, MySql>,
     age: i64,
+    key_store: Option<&KeyStore>,
+    cleared: &mut ClearedDisguises,
 ) -> Result<String, MyError> {
     let delete_age = Local::now() - Duration::days(365 * age);
     //get disguise by id and type
-    let sql = "SELECT * FROM disguise WHERE time<?";
+    let sql = "SELECT * FROM disguise WHERE time<? and status NOT IN ('recovered', 'cleared')";
     let disguises: Vec<DisguiseFromDB> = sqlx::query_as(sql)
         .bind(delete_age)
-        .fetch_all(vault_pool)
+        .fetch_all(&mut *vault_tx)
         .await?;
-    //delete functions by disguise id
     for disguise in disguises {
-        //get the decorrelated publications' predicate
-        let sql = "SELECT * FROM function WHERE disguise_id=? and function_type=?";
-        let functions: Vec<Function> = sqlx::query_as(sql)
-            .bind(disguise.disguise_id)
-            .bind("decorrelation")
-            .fetch_all(vault_pool)
-            .await?;
-        //destroy them
-        for function in functions {
-            delete_decorrelated_targets_db(
-                target_pool,
-                function.table_name.unwrap().as_str(),
-                function.predicate.unwrap().as_str()
-            ).await?;
-        }
-        //then delete the functions in vault
-        let sql = "DELETE FROM function WHERE disguise_id=?";
-        sqlx::query(sql)
-            .bind(disguise.disguise_id)
-            .execute(vault_pool)
-            .await?;
+        clear_disguise_db(target_tx, vault_tx, &disguise, key_store, cleared).await?;
     }
-    //delete the disguise
-    let sql = "DELETE FROM disguise WHERE time<?";
-    sqlx::query(sql)
-        .bind(delete_age)
-        .execute(vault_pool)
-        .await?;
     Ok("The old data in vault has been deleted.".to_string())
 }
 
 ///
-/// delete the old disguises in the vault by name
+/// clear the old disguise in the vault by its public id
 /// And the decorrelated publications in application's database will be deleted
 ///
 /// # Arguments
 ///
-/// * `vault_pool`: the server's database
-/// * `name`: the applied disguise name
+/// * `target_tx`: the transaction opened on the application's database
+/// * `vault_tx`: the transaction opened on the server's database
+/// * `public_id`: the public id of the disguise
+/// * `key_store`: the store of the data keys outside the vault database
+/// * `cleared`: the cleared disguises collected for the caller
 ///
 /// returns: Result<String, MyError>
 ///
-pub async fn delete_disguise_by_vault_id_and_name_db(
-    target_pool: &MySqlPool,
-    vault_pool: &MySqlPool,
-    vault_id: &str,
-    name: &str,
+pub async fn delete_disguise_by_public_id_db(
+    target_tx: &mut Transaction<'_, MySql>,
+    vault_tx: &mut Transaction<'_, MySql>,
+    public_id: &str,
+    key_store: Option<&KeyStore>,
+    cleared: &mut ClearedDisguises,
 ) -> Result<String, MyError> {
-    //get disguise by id and type
-    let sql = "SELECT * FROM disguise WHERE vault_id=? and disguise_type=?";
+    //get disguise by public id
+    let sql = "SELECT * FROM disguise WHERE public_id=?";
     let disguise: DisguiseFromDB = sqlx::query_as(sql)
-        .bind(vault_id)
-        .bind(name)
-        .fetch_one(vault_pool)
+        .bind(public_id)
+        .fetch_one(&mut *vault_tx)
         .await?;
+    clear_disguise_db(target_tx, vault_tx, &disguise, key_store, cleared).await
+}
+
+///
+/// clear the disguise, which is kept as "cleared" without its original and updated state
+/// the decorrelated publications which have not been recovered are deleted,
+/// and its data key is destroyed
+/// the disguise is collected into the cleared disguises,
+/// with its id if its data key should be destroyed from the key store after the commit
+///
+/// # Arguments
+///
+/// * `target_tx`: the transaction opened on the application's database
+/// * `vault_tx`: the transaction opened on the server's database
+/// * `disguise`: the disguise to clear
+/// * `key_store`: the store of the data keys outside the vault database
+/// * `cleared`: the cleared disguises collected for the caller
+///
+/// returns: Result<String, MyError>
+///
+async fn clear_disguise_db(
+    target_tx: &mut Transaction<'_, MySql>,
+    vault_tx: &mut Transaction<'_, MySql>,
+    disguise: &DisguiseFromDB,
+    key_store: Option<&KeyStore>,
+    cleared: &mut ClearedDisguises,
+) -> Result<String, MyError> {
     //get the decorrelated publications' predicate
-    let sql = "SELECT * FROM function WHERE disguise_id=? and function_type=?";
+    //the recovered publications belong to their owners again
+    let sql = "SELECT * FROM function WHERE disguise_id=? and function_type=? and recovered_time IS NULL";
     let functions: Vec<Function> = sqlx::query_as(sql)
         .bind(disguise.disguise_id)
         .bind("decorrelation")
-        .fetch_all(vault_pool)
+        .fetch_all(&mut *vault_tx)
         .await?;
-    //destroy them
+    //destroy them, and keep the deleted rows in case the vault fails to commit
     for function in functions {
-        delete_decorrelated_targets_db(
-            target_pool,
-            function.table_name.unwrap().as_str(),
-            function.predicate.unwrap().as_str()
-        ).await?;
+        let table_name = function.table_name.unwrap();
+        let deleted = delete_decorrelated_targets_db(target_tx, table_name.as_str(), function.predicate.unwrap().as_str()).await?;
+        cleared.deleted_rows.extend(deleted.into_iter().map(|target| (table_name.clone(), target)));
     }
-    //delete functions by disguise id
-    let sql = "DELETE FROM function WHERE disguise_id=?";
+    //the placeholders of the destroyed publications are useless
+    let deleted = clear_placeholders_db(target_tx, vault_tx, disguise.disguise_id.unwrap()).await?;
+    cleared.deleted_rows.extend(deleted);
+    //the original state could never be decrypted after its data key is destroyed
+    if shred_disguise_db(vault_tx, disguise, key_store).await? {
+        cleared.shredded_ids.push(disguise.disguise_id.unwrap());
+    }
+    //then erase the state kept in the functions
+    let sql = "UPDATE function SET original=NULL, updated=NULL WHERE disguise_id=?";
     sqlx::query(sql)
         .bind(disguise.disguise_id)
-        .execute(vault_pool)
+        .execute(&mut *vault_tx)
+        .await?;
+    set_disguise_status_db(vault_tx, disguise.disguise_id.unwrap(), "cleared").await?;
+    cleared.public_ids.push(disguise.public_id.clone().unwrap());
+    Ok("The old data in vault has been deleted.".to_string())
+}
+
+///
+/// destroy the data key of the cleared disguise
+/// and record the tombstone of the disguise
+/// only the data key destroyed from the key store is "crypto_shred",
+/// then the copies of its state in the binlogs and the backups could not be decrypted,
+/// the data key kept in the vault database is still in its backups, so it is "key_deleted"
+/// the key in the store is not destroyed here, since the clearing could still be rolled back,
+/// and it is returned if the key should be destroyed after the vault is committed
+///
+/// # Arguments
+///
+/// * `vault_tx`: the transaction opened on the server's database
+/// * `disguise`: the cleared disguise
+/// * `key_store`: the store of the data keys outside the vault database
+///
+/// returns: Result<bool, MyError>
+///
+async fn shred_disguise_db(
+    vault_tx: &mut Transaction<'_, MySql>,
+    disguise: &DisguiseFromDB,
+    key_store: Option<&KeyStore>,
+) -> Result<bool, MyError> {
+    let sql = "SELECT wrapped_key, key_fingerprint FROM data_key WHERE disguise_id=? FOR UPDATE";
+    let wrapped: Option<(Option<String>, Option<String>)> = sqlx::query_as(sql)
+        .bind(disguise.disguise_id)
+        .fetch_optional(&mut *vault_tx)
+        .await?;
+    let sql = "SELECT COUNT(*) FROM function WHERE disguise_id=? and recovered_time IS NULL";
+    let (functions, ): (i64, ) = sqlx::query_as(sql)
+        .bind(disguise.disguise_id)
+        .fetch_one(&mut *vault_tx)
         .await?;
-    //delete the disguise
-    let sql = "DELETE FROM disguise WHERE vault_id=? and disguise_type=?";
+    sqlx::query("DELETE FROM data_key WHERE disguise_id=?")
+        .bind(disguise.disguise_id)
+        .execute(&mut *vault_tx)
+        .await?;
+    //the disguise without the data key is only deleted,
+    //including the one encrypted by the vault's key before the data keys
+    let (key_fingerprint, method) = match wrapped {
+        Some((Some(wrapped), _)) => (Some(fingerprint(wrapped.as_str())), "key_deleted"),
+        Some((None, key_fingerprint)) => {
+            if key_store.is_none() {
+                return Err(MyError::OperationError("The key store of the data keys is not configured.".to_string()));
+            }
+            (key_fingerprint, "crypto_shred")
+        }
+        None => (None, "delete"),
+    };
+    let sql = "INSERT INTO tombstone (public_id, vault_id, disguise_type, functions, key_fingerprint, method, time) VALUES (?, ?, ?, ?, ?, ?, ?)";
     sqlx::query(sql)
-        .bind(vault_id)
-        .bind(name)
-        .execute(vault_pool)
+        .bind(disguise.public_id.as_deref())
+        .bind(disguise.vault_id.as_deref())
+        .bind(disguise.disguise_type.as_deref())
+        .bind(functions)
+        .bind(key_fingerprint)
+        .bind(method)
+        .bind(Local::now().to_string())
+        .execute(&mut *vault_tx)
         .await?;
-    Ok("The old data in vault has been deleted.".to_string())
+    Ok(method == "crypto_shred")
 }
 
+///
+/// get the tombstones of the disguises cleared from the vault
+///
+/// # Arguments
+///
+/// * `vault_pool`: the server's database
+/// * `vault_id`: the id of the vault
+///
+/// returns: Result<Vec<Tombstone, Global>, MyError>
+///
+pub async fn get_tombstones_db(
+    vault_pool: &MySqlPool,
+    vault_id: &str,
+) -> Result<Vec<Tombstone>, MyError> {
+    let sql = "SELECT public_id, vault_id, disguise_type, functions, key_fingerprint, method, time FROM tombstone WHERE vault_id=? ORDER BY tombstone_id";
+    let tombstones: Vec<Tombstone> = sqlx::query_as(sql)
+        .bind(vault_id)
+        .fetch_all(vault_pool)
+        .await?;
+    Ok(tombstones)
+}
 
+///
+/// delete the placeholders generated for the disguise
+/// in the application's database and their mappings and owners in the vault
+/// the deleted placeholders are returned with their tables
+///
+/// # Arguments
+///
+/// * `target_tx`: the transaction opened on the application's database
+/// * `vault_tx`: the transaction opened on the server's database
+/// * `disguise_id`: the id of the disguise in the vault
+///
+/// returns: Result<Vec<(String, Target), Global>, MyError>
+///
+async fn clear_placeholders_db(
+    target_tx: &mut Transaction<'_, MySql>,
+    vault_tx: &mut Transaction<'_, MySql>,
+    disguise_id: i32,
+) -> Result<Vec<(String, Target)>, MyError> {
+    let sql = "SELECT table_name, predicate, placeholder_table, placeholder_predicate FROM placeholder_mapping WHERE disguise_id=?";
+    let placeholders: Vec<PlaceholderMapping> = sqlx::query_as(sql)
+        .bind(disguise_id)
+        .fetch_all(&mut *vault_tx)
+        .await?;
+    let mut deleted = vec![];
+    for placeholder in &placeholders {
+        let table_name = placeholder.placeholder_table.clone().unwrap();
+        let predicate = Predicate::from_vault(placeholder.placeholder_predicate.as_ref().unwrap())?.render(false)?;
+        let targets = select_targets_db(target_tx, table_name.as_str(), &predicate).await?;
+        deleted.extend(targets.into_iter().map(|target| (table_name.clone(), target)));
+    }
+    delete_placeholders_db(target_tx, &placeholders).await?;
+    for sql in [
+        "DELETE FROM placeholder_mapping WHERE disguise_id=?",
+        "DELETE FROM owned_placeholder WHERE disguise_id=?",
+    ] {
+        sqlx::query(sql)
+            .bind(disguise_id)
+            .execute(&mut *vault_tx)
+            .await?;
+    }
+    Ok(deleted)
+}
 
 #[cfg(test)]
 mod tests {
     use std::env;
-    use std::sync::Mutex;
-    use actix_web::http::StatusCode;
     use actix_web::web;
     use sqlx::mysql::MySqlPoolOptions;
     use dotenv::dotenv;
     use crate::dbaccess::vault::{generate_vault_db, get_vault_by_id_db, upload_disguise_object_db};
-    use crate::handlers::vault::generate_vault;
-    use crate::models::placeholder::{GeneratePlaceHolder, PlaceholderInfo};
-    use crate::models::vault::{Disguise, Function, GenerateVault, Vault};
+    use crate::models::placeholder::PlaceholderInfo;
+    use crate::models::vault::{Disguise, Function, Vault};
     use crate::state::AppState;
 
     #[ignore]
@@ -384,18 +997,28 @@ mod tests {
         let shared_data = web::Data::new(AppState {
             vault_db,
             target_db,
+            allow_raw_sql: false,
+            master_key: None,
+            key_store: None,
+            api_auth: false,
         });
         let placeholder_info = PlaceholderInfo {
             pred: Some("contact_id=0".into()),
             table: Some("contact_info".into()),
+            template: None,
         };
         let vault = Vault {
             vault_id: Some("19".into()),
             email: Some("bea@mail.com".into()),
             placeholder_info: Some(serde_json::to_string(&placeholder_info).unwrap()),
+            recovery_public_key: None,
+            recovery_private_key: None,
+            recovery_salt: None,
         };
-        let res = generate_vault_db(&shared_data.vault_db, vault).await;
-        assert_eq!(res.is_ok(), true);
+        let mut vault_tx = shared_data.vault_db.begin().await.unwrap();
+        let res = generate_vault_db(&mut vault_tx, vault).await;
+        assert!(res.is_ok());
+        vault_tx.commit().await.unwrap();
     }
 
     #[ignore]
@@ -406,7 +1029,7 @@ mod tests {
         let vault_database_url = env::var("VAULT_DATABASE_URL").expect("VAULT_DATABASE_URL is not set yet.");
         let vault_db = MySqlPoolOptions::new().connect(&vault_database_url).await.unwrap();
         let res = get_vault_by_id_db(&vault_db, "19").await;
-        assert_eq!(res.is_ok(), true);
+        assert!(res.is_ok());
     }
 
     #[ignore]
@@ -417,23 +1040,30 @@ mod tests {
         let vault_database_url = env::var("VAULT_DATABASE_URL").expect("VAULT_DATABASE_URL is not set yet.");
         let vault_db = MySqlPoolOptions::new().connect(&vault_database_url).await.unwrap();
         let function = Function {
+            function_id: None,
             disguise_id: Some(999),
             function_type: Some("sdf".to_string()),
             table_name: Some("fsdf".to_string()),
             predicate: Some("sf".to_string()),
             original: Some("sf".to_string()),
-            updated: Some("sf".to_string())
+            updated: Some("sf".to_string()),
+            recovered_time: None
         };
         let disguise = Disguise {
             disguise_id: Some(999),
+            public_id: None,
             time: Some("1111".to_string()),
             vault_id: Some("19".to_string()),
             disguise_type: Some("123".to_string()),
-            functions: Some(vec![function])
+            status: None,
+            functions: Some(vec![function]),
+            placeholders: None
         };
-        let res = upload_disguise_object_db(&vault_db, &disguise).await;
+        let mut vault_tx = vault_db.begin().await.unwrap();
+        let res = upload_disguise_object_db(&mut vault_tx, &disguise).await;
         println!("{:?}", res);
-        assert_eq!(res.is_ok(), true);
+        assert!(res.is_ok());
+        vault_tx.commit().await.unwrap();
     }
 
 
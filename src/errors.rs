@@ -13,6 +13,9 @@ pub enum MyError {
     NotFound(String),
     InvalidInput(String),
     OperationError(String),
+    Unauthorized(String),
+    Forbidden(String),
+    Conflict(String),
 }
 #[derive(Debug, Serialize)]
 pub struct MyErrorResponse {
@@ -42,13 +45,25 @@ impl MyError {
                 println!("The operation is wrong: {:?}", msg);
                 msg.into()
             }
+            MyError::Unauthorized(msg) => {
+                println!("Unauthorized request received: {:?}", msg);
+                msg.into()
+            }
+            MyError::Forbidden(msg) => {
+                println!("Forbidden request received: {:?}", msg);
+                msg.into()
+            }
+            MyError::Conflict(msg) => {
+                println!("The operation conflicts with the data: {:?}", msg);
+                msg.into()
+            }
         }
     }
 }
 
 impl Display for MyError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self)
+        write!(f, "{:?}", self)
     }
 }
 
@@ -59,6 +74,9 @@ impl error::ResponseError for MyError {
             MyError::NotFound(_msg) => StatusCode::NOT_FOUND,
             MyError::InvalidInput(_msg) => StatusCode::BAD_REQUEST,
             MyError::OperationError(_msg) => StatusCode::BAD_REQUEST,
+            MyError::Unauthorized(_msg) => StatusCode::UNAUTHORIZED,
+            MyError::Forbidden(_msg) => StatusCode::FORBIDDEN,
+            MyError::Conflict(_msg) => StatusCode::CONFLICT,
         }
     }
     fn error_response(&self) -> HttpResponse<BoxBody> {
@@ -0,0 +1,91 @@
+use actix_web::{HttpResponse, web};
+use crate::dbaccess::audit::{get_audit_entries_db, get_audit_log_db, record_audit_db};
+use crate::error::MyError;
+use crate::models::audit::{verify_chain, AuditEntry, AuditQuery, AuditTrail};
+use crate::state::AppState;
+
+///
+/// get the audit entries of the "vault_id" in the query,
+/// and in the time range from "from" to "to"
+/// the hashes of the entries are checked as they are returned
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `query`: the vault and the time range in the query
+///
+/// returns: Result<HttpResponse<BoxBody>, MyError>
+///
+pub async fn get_audit_entries(
+    app_state: web::Data<AppState>,
+    query: web::Query<AuditQuery>,
+) -> Result<HttpResponse, MyError> {
+    let entries = get_audit_entries_db(&app_state.vault_db, &query).await?;
+    let broken_at = verify_chain(&entries, false);
+    Ok(HttpResponse::Ok().json(AuditTrail {
+        entries: Some(entries),
+        verified: Some(broken_at.is_none()),
+        broken_at,
+        message: Some(match broken_at {
+            None => "The audit entries have not been changed.".to_string(),
+            Some(audit_id) => format!("The audit entry {} has been changed.", audit_id),
+        })
+    }))
+}
+
+///
+/// check the chain of the whole audit log,
+/// which also finds the deleted entries
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+///
+/// returns: Result<HttpResponse<BoxBody>, MyError>
+///
+pub async fn verify_audit_log(
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, MyError> {
+    let entries = get_audit_log_db(&app_state.vault_db).await?;
+    let broken_at = verify_chain(&entries, true);
+    Ok(HttpResponse::Ok().json(AuditTrail {
+        entries: None,
+        verified: Some(broken_at.is_none()),
+        broken_at,
+        message: Some(match broken_at {
+            None => format!("The {} audit entries have not been changed.", entries.len()),
+            Some(audit_id) => format!("The audit log has been changed or deleted at the entry {}.", audit_id),
+        })
+    }))
+}
+
+///
+/// record the outcome of the operation unless it has been recorded
+/// the succeeded operations are recorded in their vault transactions,
+/// so this mostly records the failed ones after their transactions were rolled back
+/// the failure of the audit log itself does not hide the operation's result
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `audit`: the entry of the operation
+/// * `result`: the result of the operation
+///
+/// returns: ()
+///
+pub async fn finish_audit<T>(
+    app_state: &AppState,
+    audit: &mut AuditEntry,
+    result: &Result<T, MyError>,
+) {
+    if audit.audit_id.is_some() {
+        return;
+    }
+    match result {
+        Ok(_) => audit.set_outcome(true, audit.message.clone().unwrap_or_default()),
+        Err(err) => audit.set_outcome(false, err.to_string()),
+    }
+    if let Err(err) = record_audit_db(&app_state.vault_db, audit).await {
+        println!("The operation could not be audited: {:?}", err);
+    }
+}
@@ -1,57 +1,147 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
 use actix_web::*;
-use chrono::Local;
-use sqlx::MySqlPool;
+use serde_json::{Map, Value};
+use sqlx::{MySql, MySqlPool, Transaction};
+use crate::dbaccess::audit::append_audit_db;
 use crate::error::MyError;
+use crate::handlers::audit::finish_audit;
+use crate::middleware::Actor;
+use crate::models::audit::AuditEntry;
+use crate::models::crypto::VaultKeys;
 use crate::models::requirement::*;
+use crate::models::transformation::Transformation;
 use crate::dbaccess::target::*;
 use crate::dbaccess::vault::*;
-use crate::models::placeholder::PlaceholderInfo;
+use crate::dbaccess::specification::get_specification_db;
+use crate::dbaccess::schema::get_foreign_keys_db;
+use crate::handlers::schema::discover_transformations;
+use crate::models::schema::order_transformations;
+use crate::models::placeholder::{PlaceholderInfo, PlaceholderMapping};
+use crate::models::predicate::{Change, Changes, Predicate};
+use crate::models::recovery::{blocked_functions, RecoveredDisguises, RecoveryPolicies, RecoveryPreview, StackPolicy, StackedDisguise, StackedFunction};
+use crate::models::target::Target;
+use crate::models::vault::{is_active, AppliedDisguise, ClearedDisguises, Disguise, DisguisePreview, HandledDisguises, RowCount, TablePreview, TargetPreview};
 use crate::state::AppState;
 
 
 ///
-/// delete the user's information
-/// but retaining the anonymized publications
+/// apply any named disguise described by the requirement
+/// "userscrub", "anonymize" and "expiration" are the same as their own interfaces,
+/// and the disguise with "delete_age" is applied as an expiration
+/// without the transformations, the stored "specification" is applied with the "parameters",
+/// which is the specification with the disguise name by default,
+/// or they are discovered from the "root" row for "userscrub" and "anonymize"
+/// the "preview" disguise only returns the rows it would change
+/// the disguise is recorded in the audit log by its actor
 ///
 /// # Arguments
 ///
 /// * `app_state`: the state of the server
+/// * `actor`: the api key of the request
 /// * `requirement`: the data from user or web
 ///
 /// returns: Result<HttpResponse<BoxBody>, MyError>
 ///
-pub async fn scrub_user(
+pub async fn apply_disguise(
     app_state: web::Data<AppState>,
+    actor: Actor,
     requirement: web::Json<Requirement>,
 ) -> Result<HttpResponse, MyError> {
-    println!("Request to scrub user.");
-    let target_pool = &app_state.target_db;
-    let vault_pool = &app_state.vault_db;
-    let disguise_name = requirement.disguise_name.as_ref().unwrap().to_lowercase();
-    let vault_id = requirement.vault_id.as_ref().unwrap();
-    let transformations = requirement.transformations.as_ref().unwrap();
-    //check if the disguise name is right
-    if disguise_name != String::from("userscrub") {
+    println!("Request to apply disguise.");
+    let mut audit = AuditEntry::new(actor.0.as_str(), "apply", requirement.vault_id.clone());
+    let result = apply_requirement(&app_state, requirement.into(), &mut audit).await;
+    finish_audit(&app_state, &mut audit, &result).await;
+    result
+}
+
+///
+/// apply the disguise described by the requirement as "apply_disguise" does
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `requirement`: the data from user or web
+/// * `audit`: the audit entry of the disguise
+///
+/// returns: Result<HttpResponse<BoxBody>, MyError>
+///
+async fn apply_requirement(
+    app_state: &AppState,
+    mut requirement: Requirement,
+    audit: &mut AuditEntry,
+) -> Result<HttpResponse, MyError> {
+    let disguise_name = requirement.disguise_name.as_ref()
+        .or(requirement.specification.as_ref())
+        .ok_or_else(|| MyError::InvalidInput("The disguise name is required.".into()))?
+        .to_lowercase();
+    //the name of clearing vault could not be applied as a disguise
+    if disguise_name.is_empty() || disguise_name == "clearvault" {
         return Err(MyError::InvalidInput("The disguise name is not correct.".into()));
     }
-    //check if the vault exists in database for this user
-    let vault = get_vault_by_id_db(vault_pool, vault_id).await?;
-    //get the placeholder info from the vault
-    let placeholder_info: PlaceholderInfo = serde_json::from_str(vault.placeholder_info.unwrap().as_str()).unwrap();
-    let placeholder_pred = placeholder_info.pred.as_ref().unwrap().as_str();
-
-    //get the original state of the target
-    let original = get_targets_db(target_pool, &transformations).await?;
-
-    //execute the transformations to the target
-    //and get all the changes of the transformations
-    let all_changes = execute_transformations_db(placeholder_pred, target_pool, transformations).await?;
-
-    //upload this disguise into the vault
-    upload_disguise_db(vault_pool, &requirement, original, all_changes).await?;
+    let vault_id = requirement.vault_id.clone()
+        .ok_or_else(|| MyError::InvalidInput("The vault id is required.".into()))?;
+    //discover the transformations from the root row of the user's data
+    if requirement.transformations.is_none() {
+        if let Some(mut root) = requirement.root.clone() {
+            let (_, transformations) = discover_transformations(app_state, &disguise_name, &mut root, Some(vault_id.as_str())).await?;
+            requirement.transformations = Some(transformations);
+        }
+    }
+    //get the transformations from the stored specification
+    let mut reversible = true;
+    if requirement.transformations.is_none() {
+        let name = requirement.specification.clone().unwrap_or_else(|| disguise_name.clone());
+        let specification = get_specification_db(&app_state.vault_db, name.as_str(), requirement.version).await?;
+        let mut parameters = requirement.parameters.clone().unwrap_or_default();
+        //the specification is always applied to the rows of the requirement's vault
+        parameters.insert("vault_id".to_string(), vault_id.into());
+        requirement.transformations = Some(specification.instantiate(&parameters)?);
+        reversible = specification.is_reversible();
+    }
+    requirement.disguise_name = Some(disguise_name);
+    if requirement.transformations.as_ref().is_none_or(|transformations| transformations.is_empty()) {
+        return Err(MyError::InvalidInput("The transformations are required.".into()));
+    }
+    //the preview is not written into the databases
+    //the preview is audited as well, since it reads the user's rows
+    if requirement.is_preview() {
+        audit.operation = Some("preview".to_string());
+        let preview = preview_disguise(app_state, &requirement).await?;
+        //only the number of the rows is audited without their values
+        let row_counts = preview.row_counts();
+        audit.summary = serde_json::to_string(&row_counts).ok();
+        audit.row_count = Some(row_counts.iter().map(|count| count.rows.unwrap_or(0) as i64).sum());
+        audit.message = preview.message.clone();
+        println!("The policy has been previewed.");
+        return Ok(HttpResponse::Ok().json(preview));
+    }
+    let applied = execute_disguise(app_state, &requirement, reversible, audit).await?;
 
     println!("The policy has been applied.");
-    Ok(HttpResponse::Ok().json("The policy has been applied.".to_string()))
+    Ok(HttpResponse::Ok().json(applied))
+}
+
+///
+/// delete the user's information
+/// but retaining the anonymized publications
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `actor`: the api key of the request
+/// * `requirement`: the data from user or web
+///
+/// returns: Result<HttpResponse<BoxBody>, MyError>
+///
+pub async fn scrub_user(
+    app_state: web::Data<AppState>,
+    actor: Actor,
+    requirement: web::Json<Requirement>,
+) -> Result<HttpResponse, MyError> {
+    println!("Request to scrub user.");
+    check_disguise_name(&requirement, "userscrub")?;
+    apply_disguise(app_state, actor, requirement).await
 }
 
 ///
@@ -60,42 +150,19 @@ pub async fn scrub_user(
 /// # Arguments
 ///
 /// * `app_state`: the state of the server
+/// * `actor`: the api key of the request
 /// * `requirement`: the data from user or web
 ///
 /// returns: Result<HttpResponse<BoxBody>, MyError>
 ///
 pub async fn anonymize(
     app_state: web::Data<AppState>,
+    actor: Actor,
     requirement: web::Json<Requirement>
 ) -> Result<HttpResponse, MyError> {
     println!("Request to anonymize user.");
-    let target_pool = &app_state.target_db;
-    let vault_pool = &app_state.vault_db;
-    let disguise_name = requirement.disguise_name.as_ref().unwrap().to_lowercase();
-    let vault_id = requirement.vault_id.as_ref().unwrap();
-    let transformations = requirement.transformations.as_ref().unwrap();
-    //check if the disguise name is right
-    if disguise_name != String::from("anonymize") {
-        return Err(MyError::InvalidInput("The disguise name is not correct.".into()));
-    }
-    //check if the vault exists in database for this user
-    let vault = get_vault_by_id_db(vault_pool, vault_id).await?;
-    //get the placeholder info from the vault
-    let placeholder_info: PlaceholderInfo = serde_json::from_str(vault.placeholder_info.unwrap().as_str()).unwrap();
-    let placeholder_pred = placeholder_info.pred.as_ref().unwrap().as_str();
-
-    //get the original state of the target
-    let original = get_targets_db(target_pool, &transformations).await?;
-
-    //execute the transformations to the target
-    //and get all the changes of the transformations
-    let all_changes = execute_transformations_db(placeholder_pred, target_pool, transformations).await?;
-
-    //upload this disguise into the vault
-    upload_disguise_db(vault_pool, &requirement, original, all_changes).await?;
-
-    println!("The policy has been applied.");
-    Ok(HttpResponse::Ok().json("The policy has been applied.".to_string()))
+    check_disguise_name(&requirement, "anonymize")?;
+    apply_disguise(app_state, actor, requirement).await
 }
 
 ///
@@ -105,128 +172,849 @@ pub async fn anonymize(
 /// # Arguments
 ///
 /// * `app_state`: the state of the server
+/// * `actor`: the api key of the request
 /// * `requirement`: the data from the user or the web
 ///
 /// returns: Result<HttpResponse<BoxBody>, MyError>
 ///
 pub async fn expiration(
     app_state: web::Data<AppState>,
+    actor: Actor,
     requirement: web::Json<Requirement>
 ) -> Result<HttpResponse, MyError> {
     println!("Request to use expiration.");
+    check_disguise_name(&requirement, "expiration")?;
+    if requirement.delete_age.is_none() {
+        return Err(MyError::InvalidInput("The delete age is required.".into()));
+    }
+    apply_disguise(app_state, actor, requirement).await
+}
+
+/// check if the disguise name of the requirement is right
+fn check_disguise_name(requirement: &Requirement, disguise_name: &str) -> Result<(), MyError> {
+    match &requirement.disguise_name {
+        Some(name) if name.to_lowercase() == disguise_name => Ok(()),
+        _ => Err(MyError::InvalidInput("The disguise name is not correct.".into())),
+    }
+}
+
+///
+/// apply the transformations of the requirement as one disguise
+/// the target database and the vault are changed in their own transactions,
+/// the vault is only committed after the target has been committed,
+/// and the target is recovered if the vault failed to commit
+/// the public id of the applied disguise is returned
+/// with the number of rows changed by every transformation,
+/// which is also appended to the audit log in the vault transaction
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `requirement`: the data from user or web
+/// * `reversible`: if the original state of the targets is kept in the vault
+/// * `audit`: the audit entry of the disguise
+///
+/// returns: Result<AppliedDisguise, MyError>
+///
+async fn execute_disguise(
+    app_state: &AppState,
+    requirement: &Requirement,
+    reversible: bool,
+    audit: &mut AuditEntry,
+) -> Result<AppliedDisguise, MyError> {
     let target_pool = &app_state.target_db;
     let vault_pool = &app_state.vault_db;
-    let disguise_name = requirement.disguise_name.as_ref().unwrap().to_lowercase();
+
+    let mut target_tx = target_pool.begin().await?;
+    let (transformations, row_counts, original, updated, placeholders) =
+        transform_targets(app_state, requirement, &mut target_tx).await?;
+    let mut vault_tx = vault_pool.begin().await?;
+    //the data key of the disguise is wrapped by the vault's keys
+    let vault = get_vault_by_id_db(vault_pool, requirement.vault_id.as_ref().unwrap()).await?;
+    let keys = VaultKeys::sealing(app_state.master_key.as_ref(), app_state.key_store.as_ref(), &vault);
+
+    let row_counts: Vec<RowCount> = transformations.iter().zip(row_counts).map(|(transformation, rows)| RowCount {
+        transform_type: transformation.transform_type.clone(),
+        table_name: transformation.table_name.clone(),
+        rows: Some(rows)
+    }).collect();
+
+    //upload this disguise into the vault with its audit entry
+    let uploaded = match upload_disguise_db(
+        &mut vault_tx, requirement, &transformations, original, updated, reversible, placeholders, &keys
+    ).await {
+        Ok((disguise, pending_key)) => {
+            audit.set_disguises(&[disguise.public_id.clone().unwrap()]);
+            audit.summary = serde_json::to_string(&row_counts).ok();
+            audit.row_count = Some(row_counts.iter().map(|count| count.rows.unwrap_or(0) as i64).sum());
+            audit.set_outcome(true, "The policy has been applied.".to_string());
+            append_audit_db(&mut vault_tx, audit).await.map(|_| (disguise, pending_key))
+        }
+        Err(err) => Err(err),
+    };
+    let (disguise, pending_key) = match uploaded {
+        Ok(uploaded) => uploaded,
+        Err(err) => {
+            //nothing has been committed yet
+            target_tx.rollback().await?;
+            vault_tx.rollback().await?;
+            return Err(err);
+        }
+    };
+
+    //the target is committed first,
+    //the disguise is only recorded when the target has been changed
+    target_tx.commit().await?;
+    if let Err(err) = vault_tx.commit().await {
+        //the vault failed to record the disguise,
+        //so the committed target is recovered with the disguise in memory
+        println!("The vault failed to commit, recovering the target.");
+        audit.audit_id = None;
+        let err: MyError = err.into();
+        //both errors are reported if the target could not be recovered
+        if let Err(recover_err) = recover_committed_target(target_pool, &disguise).await {
+            return Err(MyError::DBError(format!(
+                "The vault failed to commit: {}, and the target could not be recovered: {}", err, recover_err
+            )));
+        }
+        return Err(err);
+    }
+    //the data key is only kept in the store after the disguise is committed
+    if let (Some(key_store), Some(wrapped)) = (app_state.key_store.as_ref(), pending_key) {
+        store_data_key_db(vault_pool, key_store, disguise.disguise_id.unwrap(), wrapped.as_str()).await?;
+    }
+    Ok(AppliedDisguise {
+        disguise_id: disguise.public_id,
+        row_counts: Some(row_counts),
+        message: Some("The policy has been applied.".to_string())
+    })
+}
+
+///
+/// recover the target which has been committed
+/// with the disguise whose vault transaction failed to commit
+///
+/// # Arguments
+///
+/// * `target_pool`: the application's database
+/// * `disguise`: the disguise kept in memory
+///
+/// returns: Result<(), MyError>
+///
+async fn recover_committed_target(target_pool: &MySqlPool, disguise: &Disguise) -> Result<(), MyError> {
+    let mut target_tx = target_pool.begin().await?;
+    recover_db(&mut target_tx, disguise, None, &RecoveryPolicies::overwrite()).await?;
+    target_tx.commit().await?;
+    Ok(())
+}
+
+///
+/// preview the transformations of the requirement
+/// the targets are only selected, and nothing is written into the databases
+/// the state after the disguise is rendered from the selected rows and the changes,
+/// the rows removed or changed by an earlier transformation are previewed in that state
+/// the rows of every transformation are returned with their state before and after
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `requirement`: the data from user or web
+///
+/// returns: Result<DisguisePreview, MyError>
+///
+async fn preview_disguise(
+    app_state: &AppState,
+    requirement: &Requirement,
+) -> Result<DisguisePreview, MyError> {
+    let mut target_tx = app_state.target_db.begin().await?;
+    let (placeholder_info, transformations, original) =
+        select_targets(app_state, requirement, &mut target_tx).await?;
+    //the transaction has only been read
+    target_tx.rollback().await?;
+
+    let mut unknown = false;
+    //the state of the rows after the earlier transformations by their tables and keys
+    let mut states: HashMap<(String, String), Option<Map<String, Value>>> = HashMap::new();
+    let mut tables = vec![];
+    for (i, transformation) in transformations.iter().enumerate() {
+        let change = preview_change(&placeholder_info, transformation)?;
+        unknown |= matches!(change, PreviewChange::Unknown);
+        let mut targets = vec![];
+        let mut primary_key_name = None;
+        for target in &original[i] {
+            let primary_key = target.primary_key().unwrap();
+            primary_key_name = primary_key.field_name.clone();
+            let primary_key = primary_key.field_value.map(|value| value.to_value());
+            let state_key = (
+                transformation.table_name.clone().unwrap_or_default(),
+                primary_key.as_ref().map_or(String::new(), |value| value.to_string())
+            );
+            let before = match states.get(&state_key) {
+                Some(Some(state)) => state.clone(),
+                //the row has been removed by an earlier transformation
+                Some(None) => continue,
+                None => target.to_map(),
+            };
+            let after = match &change {
+                PreviewChange::Removed => None,
+                PreviewChange::Columns(changes) => {
+                    let mut after = before.clone();
+                    for change in changes {
+                        after.insert(change.column.clone(), change.value.clone());
+                    }
+                    Some(after)
+                }
+                PreviewChange::Unknown => None,
+            };
+            let changed_fields = after.as_ref().map(|after| before.iter()
+                .filter(|(name, value)| after.get(*name) != Some(value))
+                .map(|(name, _)| name.clone())
+                .collect());
+            if !matches!(change, PreviewChange::Unknown) {
+                states.insert(state_key, after.clone());
+            }
+            targets.push(TargetPreview {
+                primary_key,
+                before: Some(before),
+                after,
+                changed_fields
+            });
+        }
+        tables.push(TablePreview {
+            transform_type: transformation.transform_type.clone(),
+            table_name: transformation.table_name.clone(),
+            primary_key_name,
+            rows: Some(targets.len() as u64),
+            targets: Some(targets)
+        });
+    }
+    let message = if unknown {
+        "The policy has been previewed without any change, \
+            the rows of the raw changes are only shown before them."
+    } else {
+        "The policy has been previewed without any change."
+    };
+    Ok(DisguisePreview {
+        disguise_name: requirement.disguise_name.clone(),
+        tables: Some(tables),
+        message: Some(message.to_string())
+    })
+}
+
+/// the change of the rows selected by a transformation in the preview
+enum PreviewChange {
+    Removed,
+    Columns(Vec<Change>),
+    //the raw changes could not be rendered without executing them
+    Unknown,
+}
+
+///
+/// get the change which the transformation would make to its rows
+/// the new placeholders of the unique decorrelation have no ids yet,
+/// so their keys are null in the preview
+///
+/// # Arguments
+///
+/// * `placeholder_info`: the placeholder of the vault
+/// * `transformation`: the transformation to preview
+///
+/// returns: Result<PreviewChange, MyError>
+///
+fn preview_change(placeholder_info: &PlaceholderInfo, transformation: &Transformation) -> Result<PreviewChange, MyError> {
+    match transformation.transform_type.as_ref().unwrap().to_lowercase().as_str() {
+        "removal" => Ok(PreviewChange::Removed),
+        "modification" => match transformation.changes.as_ref().unwrap() {
+            Changes::Columns(changes) => Ok(PreviewChange::Columns(changes.clone())),
+            Changes::Raw(_) => Ok(PreviewChange::Unknown),
+        },
+        "decorrelation" if transformation.is_unique_placeholder() => {
+            let template = placeholder_info.template.as_ref()
+                .ok_or_else(|| MyError::OperationError("The vault has no template to generate the placeholders.".to_string()))?;
+            let column = transformation.foreign_key.clone()
+                .unwrap_or_else(|| template.primary_key_name.clone().unwrap());
+            Ok(PreviewChange::Columns(vec![Change { column, value: Value::Null }]))
+        }
+        "decorrelation" => match Predicate::from_vault(placeholder_info.pred.as_ref().unwrap())? {
+            Predicate::Condition { column, value, .. } => Ok(PreviewChange::Columns(vec![Change {
+                column: transformation.foreign_key.clone().unwrap_or(column),
+                value
+            }])),
+            _ => Err(MyError::OperationError("The placeholder is not correct.".to_string())),
+        },
+        _ => Err(MyError::InvalidInput(
+            "The transform type is not correct, \
+            the execution has been terminated.".to_string()
+        )),
+    }
+}
+
+///
+/// execute the transformations of the requirement in the target transaction
+/// in the order of the foreign keys between their tables
+/// the transformations, the number of the changed rows of every transformation,
+/// the state of the targets before and after them,
+/// and the placeholders generated for the decorrelated rows are returned
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `requirement`: the data from user or web
+/// * `target_tx`: the transaction opened on the application's database
+///
+/// returns: Result<(Vec<Transformation>, Vec<u64>, Vec<Vec<Target>>, Vec<Vec<Option<Target>>>, Vec<PlaceholderMapping>), MyError>
+///
+async fn transform_targets(
+    app_state: &AppState,
+    requirement: &Requirement,
+    target_tx: &mut Transaction<'_, MySql>,
+) -> Result<(Vec<Transformation>, Vec<u64>, Vec<Vec<Target>>, Vec<Vec<Option<Target>>>, Vec<PlaceholderMapping>), MyError> {
+    let (placeholder_info, transformations, original) = select_targets(app_state, requirement, target_tx).await?;
+
+    //execute the transformations to the target
+    let (row_counts, placeholders) = execute_transformations_db(&placeholder_info, target_tx, &transformations, app_state.allow_raw_sql).await?;
+    //and get the state of the targets after the transformations
+    let updated = get_updated_targets_db(target_tx, &transformations, &original).await?;
+    Ok((transformations, row_counts, original, updated, placeholders))
+}
+
+///
+/// get the transformations of the requirement
+/// in the order of the foreign keys between their tables,
+/// and select their targets without changing them
+/// the placeholder of the vault, the transformations and the targets are returned
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `requirement`: the data from user or web
+/// * `target_tx`: the transaction opened on the application's database
+///
+/// returns: Result<(PlaceholderInfo, Vec<Transformation>, Vec<Vec<Target>>), MyError>
+///
+async fn select_targets(
+    app_state: &AppState,
+    requirement: &Requirement,
+    target_tx: &mut Transaction<'_, MySql>,
+) -> Result<(PlaceholderInfo, Vec<Transformation>, Vec<Vec<Target>>), MyError> {
     let vault_id = requirement.vault_id.as_ref().unwrap();
-    let delete_age = requirement.delete_age.unwrap();
     let transformations = requirement.transformations.as_ref().unwrap();
-    //check if the disguise name is right
-    if disguise_name != String::from("expiration") {
-        return Err(MyError::InvalidInput("The disguise name is not correct.".into()));
+    //the raw sql is only accepted when the server allows it
+    if !app_state.allow_raw_sql && transformations.iter().any(|transformation| transformation.allow_raw()) {
+        return Err(MyError::InvalidInput("The raw sql is not allowed by the server.".into()));
     }
     //check if the vault exists in database for this user
-    let vault = get_vault_by_id_db(vault_pool, vault_id).await?;
+    let vault = get_vault_by_id_db(&app_state.vault_db, vault_id).await?;
     //get the placeholder info from the vault
     let placeholder_info: PlaceholderInfo = serde_json::from_str(vault.placeholder_info.unwrap().as_str()).unwrap();
-    let placeholder_pred = placeholder_info.pred.as_ref().unwrap().as_str();
-    //transfer the transformations
-    let transformations = transfer_transformations(target_pool, transformations, delete_age).await?;
-
-    //get the original state of the target
-    let original = get_targets_db(target_pool, &transformations).await?;
 
-    //execute the transformations to the target
-    //and get all the changes of the transformations
-    let all_changes = execute_transformations_db(placeholder_pred, target_pool, &transformations).await?;
+    //the expiration's transformations should be transferred by the age
+    let transformations: Vec<Transformation> = match requirement.delete_age {
+        Some(delete_age) => transfer_transformations(target_tx, transformations, delete_age).await?,
+        None => transformations.clone(),
+    };
+    //the children are transformed before their parents
+    let foreign_keys = get_foreign_keys_db(target_tx).await?;
+    let transformations = order_transformations(transformations, &foreign_keys)?;
 
-    //upload this disguise into the vault
-    upload_disguise_db(vault_pool, &requirement, original, all_changes).await?;
+    //get the original state of the target
+    let original = get_targets_db(target_tx, &transformations).await?;
+    Ok((placeholder_info, transformations, original))
+}
 
-    println!("The policy has been applied.");
-    Ok(HttpResponse::Ok().json("The policy has been applied.".to_string()))
+///
+/// get the public ids of the applied disguises in the requirement
+/// which is the "disguise_id",
+/// or the disguises of the type "disguise_name" chosen by the "selector"
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `requirement`: the data from user or web
+/// * `disguise_name`: the type of the disguises
+/// * `default_selector`: the selector used when the requirement has none
+///
+/// returns: Result<Vec<String, Global>, MyError>
+///
+async fn select_disguises(
+    app_state: &AppState,
+    requirement: &Requirement,
+    disguise_name: Option<&str>,
+    default_selector: &str,
+) -> Result<Vec<String>, MyError> {
+    let vault_id = requirement.vault_id.as_ref().unwrap();
+    let selector = requirement.selector.as_deref().unwrap_or(default_selector).to_lowercase();
+    let disguises = find_disguises_db(
+        &app_state.vault_db,
+        vault_id,
+        requirement.disguise_id.as_deref(),
+        disguise_name,
+        selector.as_str()
+    ).await?;
+    //the disguise found by its public id may only be kept as the history
+    if let Some(disguise) = disguises.iter().find(|disguise| !is_active(disguise.status.as_deref())) {
+        return Err(MyError::InvalidInput(format!(
+            "The disguise has been {}.", disguise.status.as_deref().unwrap_or_default()
+        )));
+    }
+    Ok(disguises.into_iter().map(|disguise| disguise.public_id.unwrap()).collect())
 }
 
 ///
 /// clear the old disguise data in all the vaults
-/// they will be deleted forever
+/// their original state will be deleted forever,
+/// and they are only kept as "cleared" in the history
+/// the disguises of one vault are chosen by the public id,
+/// or by the "delete_name" with all of them by default
 ///
 /// # Arguments
 ///
 /// * `app_state`: the state of the server
+/// * `actor`: the api key of the request
 /// * `requirement`: the data from user or web
 ///
 /// returns: Result<HttpResponse<BoxBody>, MyError>
 ///
 pub async fn clear_vault(
     app_state: web::Data<AppState>,
+    actor: Actor,
     requirement: web::Json<Requirement>,
 ) -> Result<HttpResponse, MyError> {
     println!("Request to use clear vault.");
+    let mut audit = AuditEntry::new(actor.0.as_str(), "clearvault", requirement.vault_id.clone());
+    let result = clear_disguises(&app_state, &requirement, &mut audit).await;
+    finish_audit(&app_state, &mut audit, &result).await;
+    result
+}
+
+///
+/// clear the disguises chosen by the requirement as "clear_vault" does
+/// the target is committed first, and its deleted rows are inserted again if the vault failed to commit,
+/// then the data keys of the shredded disguises are destroyed from the key store
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `requirement`: the data from user or web
+/// * `audit`: the audit entry of the clearing
+///
+/// returns: Result<HttpResponse<BoxBody>, MyError>
+///
+async fn clear_disguises(
+    app_state: &AppState,
+    requirement: &Requirement,
+    audit: &mut AuditEntry,
+) -> Result<HttpResponse, MyError> {
     let target_pool = &app_state.target_db;
     let vault_pool = &app_state.vault_db;
     let age = requirement.delete_age;
     let disguise_name = requirement.disguise_name.as_ref().unwrap().to_lowercase();
     //check if the disguise name is right
-    if disguise_name != String::from("clearvault") {
+    if disguise_name != "clearvault" {
         return Err(MyError::InvalidInput("The disguise name is not correct.".into()));
     }
+    let key_store = app_state.key_store.as_ref();
+    let mut cleared = ClearedDisguises::default();
+    let mut target_tx = target_pool.begin().await?;
+    let mut vault_tx = vault_pool.begin().await?;
     //check which type the developer want to clear
     match age {
-        //by name
+        //by id or name
         None => {
-            let name = requirement.delete_name.as_ref().unwrap();
-            let vault_id = requirement.vault_id.as_ref().unwrap();
-            delete_disguise_by_vault_id_and_name_db(target_pool, vault_pool, vault_id, name).await?;
+            let name = requirement.delete_name.as_deref();
+            let disguise_ids = select_disguises(app_state, requirement, name, "all").await?;
+            for disguise_id in &disguise_ids {
+                delete_disguise_by_public_id_db(&mut target_tx, &mut vault_tx, disguise_id, key_store, &mut cleared).await?;
+            }
+            audit.summary = Some(format!("The disguises of the type \"{}\"", name.unwrap_or("all")));
         }
         //by age
         Some(age) => {
-            delete_disguise_by_age_db(target_pool, vault_pool, age).await?;
+            delete_disguise_by_age_db(&mut target_tx, &mut vault_tx, age, key_store, &mut cleared).await?;
+            audit.summary = Some(format!("The disguises older than {} years in all the vaults", age));
+        }
+    }
+    audit.set_disguises(&cleared.public_ids);
+    audit.set_outcome(true, "The policy has been applied.".to_string());
+    append_audit_db(&mut vault_tx, audit).await?;
+    target_tx.commit().await?;
+    if let Err(err) = vault_tx.commit().await {
+        //the vault still keeps the disguises as they were,
+        //so the rows deleted from the committed target are inserted again
+        println!("The vault failed to commit, restoring the target.");
+        audit.audit_id = None;
+        let err: MyError = err.into();
+        if let Err(restore_err) = restore_cleared_target(target_pool, &cleared).await {
+            return Err(MyError::DBError(format!(
+                "The vault failed to commit: {}, and the target could not be restored: {}", err, restore_err
+            )));
+        }
+        return Err(err);
+    }
+    //the data keys are only destroyed after the tombstones are committed
+    if let Some(key_store) = key_store {
+        let mut failed = 0;
+        for disguise_id in &cleared.shredded_ids {
+            if let Err(err) = key_store.destroy(*disguise_id) {
+                println!("The data key of the disguise {} could not be destroyed: {}", disguise_id, err);
+                failed += 1;
+            }
+        }
+        if failed > 0 {
+            return Err(MyError::OperationError(format!(
+                "The disguises have been cleared, but {} data keys could not be destroyed from the key store.", failed
+            )));
         }
     }
     println!("The policy has been applied.");
-    Ok(HttpResponse::Ok().json("The policy has been applied.".to_string()))
+    Ok(HttpResponse::Ok().json(HandledDisguises {
+        disguise_ids: Some(cleared.public_ids),
+        message: Some("The policy has been applied.".to_string())
+    }))
+}
+
+///
+/// insert the rows deleted by the clearing into the committed target again
+/// when the vault failed to record the clearing
+///
+/// # Arguments
+///
+/// * `target_pool`: the application's database
+/// * `cleared`: the disguises cleared in the failed vault transaction
+///
+/// returns: Result<(), MyError>
+///
+async fn restore_cleared_target(target_pool: &MySqlPool, cleared: &ClearedDisguises) -> Result<(), MyError> {
+    let mut target_tx = target_pool.begin().await?;
+    restore_targets_db(&mut target_tx, &cleared.deleted_rows).await?;
+    target_tx.commit().await?;
+    Ok(())
 }
 
 ///
 /// recover the applied disguise from the vault
-/// and this disguise history will be deleted as well
+/// and this disguise is kept as "recovered" without its original state
+/// the disguises are chosen by the public id,
+/// or by the type with the latest one by default
+/// the rows changed after the disguise are resolved by the policies of the requirement,
+/// and the recovery is stopped on any conflict by default
+/// the disguise stopped halfway stays "recovering",
+/// and the next recovery is resumed from its first function which has not been recovered
+/// only the functions on the "tables" or with the "function_ids" are recovered if they are given,
+/// and the rest of the disguise is kept in the vault
+/// the disguise whose rows have been changed again by the later disguises of the vault
+/// is refused by default, or recovered after them, or undone alone by the "stack_policy"
 ///
 /// # Arguments
 ///
 /// * `app_state`: the state of the server
+/// * `actor`: the api key of the request
 /// * `requirement`: the data from user or the web
 ///
 /// returns: Result<HttpResponse<BoxBody>, MyError>
 ///
 pub async fn recover_disguise(
     app_state: web::Data<AppState>,
+    actor: Actor,
     requirement: web::Json<Requirement>,
 ) -> Result<HttpResponse, MyError> {
     println!("Request to recover disguise.");
+    let mut audit = AuditEntry::new(actor.0.as_str(), "recover", requirement.vault_id.clone());
+    let result = recover_disguises(&app_state, &requirement, &mut audit).await;
+    finish_audit(&app_state, &mut audit, &result).await;
+    result
+}
+
+///
+/// recover the disguises chosen by the requirement as "recover_disguise" does
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `requirement`: the data from user or the web
+/// * `audit`: the audit entry of the recovery
+///
+/// returns: Result<HttpResponse<BoxBody>, MyError>
+///
+async fn recover_disguises(
+    app_state: &AppState,
+    requirement: &Requirement,
+    audit: &mut AuditEntry,
+) -> Result<HttpResponse, MyError> {
     let target_db = &app_state.target_db;
     let vault_db = &app_state.vault_db;
-    let disguise_type = requirement.disguise_name.as_ref().unwrap();
-    let vault_id = requirement.vault_id.as_ref().unwrap();
+    let disguise_type = requirement.disguise_name.as_deref();
+    //the disguises are recovered from the newest one
+    let disguise_ids = select_disguises(app_state, requirement, disguise_type, "latest").await?;
+    //the vault with the recovery key is only recovered with the user's secret
+    let vault = get_vault_by_id_db(vault_db, requirement.vault_id.as_ref().unwrap()).await?;
+    let keys = VaultKeys::opening(app_state.master_key.as_ref(), app_state.key_store.as_ref(), &vault, requirement.secret.as_deref())?;
+
+    let policies = requirement.recovery_policies();
+    let stack = requirement.stack_policy.unwrap_or_default();
+    let disguises = stack_disguises(app_state, requirement, &disguise_ids, stack == StackPolicy::Reorder).await?;
+    let disguise_ids: Vec<String> = disguises.iter().map(|stacked| stacked.disguise.public_id.clone().unwrap()).collect();
+    //the preview neither changes the target nor deletes the disguises
+    if requirement.is_preview() {
+        audit.operation = Some("recover_preview".to_string());
+        let preview = preview_recovery_of(app_state, requirement, &disguises, stack, &keys).await?;
+        audit.message = preview.message.clone();
+        return Ok(HttpResponse::Ok().json(preview));
+    }
+    //the row changed again by a later disguise would lose the later change if it was recovered first
+    let blocked = blocked_functions(&disguises);
+    if stack != StackPolicy::Undo && !blocked.is_empty() {
+        let mut later_ids: Vec<String> = blocked.into_iter().filter_map(|stacked| stacked.later_disguise_id).collect();
+        later_ids.sort();
+        later_ids.dedup();
+        return Err(MyError::Conflict(format!(
+            "The rows of the disguise have been changed again by the later disguises {}, \
+            which should be recovered first, or the stack policy should be \"reorder\" or \"undo\".",
+            later_ids.join(", ")
+        )));
+    }
 
-    //download disguise from vault
-    let disguise = download_disguise_db(vault_db, disguise_type, vault_id).await?;
+    audit.set_disguises(&disguise_ids);
+    let mut disguise_types = vec![];
+    let mut recovering = vec![];
+    let mut rows = 0;
+    let mut functions = 0;
+    let mut conflicts = vec![];
+    let mut skipped_rows = 0;
+    for StackedDisguise { disguise, steps, .. } in disguises {
+        //the recovery stopped halfway is finished even if all its functions have been recovered
+        let resumed = disguise.status.as_deref() == Some("recovering");
+        if steps.is_empty() && !resumed {
+            continue;
+        }
+        disguise_types.push(disguise.disguise_type.clone().unwrap_or_default());
+        let vault_disguise_id = disguise.disguise_id.unwrap();
+        recovering.push(vault_disguise_id);
+        if !resumed {
+            let mut vault_tx = vault_db.begin().await?;
+            set_disguise_status_db(&mut vault_tx, vault_disguise_id, "recovering").await?;
+            vault_tx.commit().await?;
+        }
 
-    //recover the target
-    recover_db(target_db, &disguise).await?;
+        //recover the target, the original rows are decrypted by the disguise's data key
+        //every function is recovered and marked by its own transactions,
+        //so the recovery stopped by a conflict or a failure is resumed from the next function
+        let data_key = get_data_key_db(vault_db, vault_disguise_id, &keys).await?;
+        //the later functions are found again after the newer disguises have been recovered
+        let stacked = match stack {
+            StackPolicy::Undo => get_stacked_functions_db(vault_db, vault_disguise_id).await?,
+            _ => vec![],
+        };
+        for step in steps {
+            let mut later = vec![];
+            for stacked in stacked.iter().filter(|stacked| step.has_function(stacked.function_id)) {
+                let function = get_function_db(vault_db, stacked.later_function_id.unwrap()).await?;
+                let later_key = get_data_key_db(vault_db, function.disguise_id.unwrap(), &keys).await?;
+                later.push((function, later_key));
+            }
+            let mut target_tx = target_db.begin().await?;
+            //the function under the later disguises is undone by rewriting their state
+            let (rewritten, skipped, restored) = if later.is_empty() {
+                let (resolved, skipped, restored) = recover_db(&mut target_tx, &step, data_key.as_ref(), &policies)
+                    .await
+                    .map_err(|err| match err {
+                        MyError::Conflict(message) => MyError::Conflict(format!(
+                            "{} The {} recovered functions are kept, and the rest could be recovered again.", message, functions
+                        )),
+                        err => err,
+                    })?;
+                conflicts.extend(resolved);
+                (vec![], skipped, restored)
+            } else {
+                let (rewritten, restored) = undo_db(&mut target_tx, &step, data_key.as_ref(), &later).await?;
+                (rewritten, vec![], restored)
+            };
+            target_tx.commit().await?;
+            //the skipped functions are kept in the vault to be recovered later
+            let mut vault_tx = vault_db.begin().await?;
+            rewrite_functions_db(&mut vault_tx, &rewritten).await?;
+            mark_recovered_db(&mut vault_tx, &step, &skipped).await?;
+            vault_tx.commit().await?;
+            if skipped.is_empty() {
+                functions += 1;
+            }
+            //the audit counts the rows written back to the application, as the apply does
+            rows += restored;
+            skipped_rows += skipped.len();
+            audit.row_count = Some(rows);
+        }
+    }
+    if recovering.is_empty() {
+        return Err(MyError::NotFound("No function of the disguises is selected to recover.".into()));
+    }
+    //the disguises are kept in the vault as the history without their original state
+    let mut vault_tx = vault_db.begin().await?;
+    for disguise_id in recovering {
+        finish_recovery_db(&mut vault_tx, disguise_id).await?;
+    }
+    audit.summary = serde_json::to_string(&disguise_types).ok();
+    audit.row_count = Some(rows);
+    let mut message = match conflicts.len() {
+        0 => "The disguise has been recovered.".to_string(),
+        count => format!("The disguise has been recovered with {} conflicts resolved.", count),
+    };
+    if skipped_rows > 0 {
+        message += format!(" The {} skipped rows are kept in the vault.", skipped_rows).as_str();
+    }
+    audit.set_outcome(true, message.clone());
+    append_audit_db(&mut vault_tx, audit).await?;
+    if let Err(err) = vault_tx.commit().await {
+        audit.audit_id = None;
+        return Err(err.into());
+    }
+    println!("{}", message);
+    Ok(HttpResponse::Ok().json(RecoveredDisguises {
+        disguise_ids: Some(disguise_ids),
+        conflicts: Some(conflicts),
+        message: Some(message)
+    }))
+}
 
-    //delete the disguise in the vault
-    delete_disguise_db(vault_db, disguise_type, vault_id).await?;
+///
+/// preview the recovery of the disguises in the requirement,
+/// which is the same as "recover_disguise" with "preview"
+/// every function reports the row the recovery would insert or update,
+/// the fields which would change from their current values,
+/// and the conflicts or the failures which would stop the recovery
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `actor`: the api key of the request
+/// * `requirement`: the data from user or the web
+///
+/// returns: Result<HttpResponse<BoxBody>, MyError>
+///
+pub async fn preview_recovery(
+    app_state: web::Data<AppState>,
+    actor: Actor,
+    requirement: web::Json<Requirement>,
+) -> Result<HttpResponse, MyError> {
+    println!("Request to preview recovery.");
+    let mut requirement: Requirement = requirement.into();
+    requirement.preview = Some(true);
+    let mut audit = AuditEntry::new(actor.0.as_str(), "recover_preview", requirement.vault_id.clone());
+    let result = recover_disguises(&app_state, &requirement, &mut audit).await;
+    finish_audit(&app_state, &mut audit, &result).await;
+    result
+}
 
-    println!("The disguise has been recovered.");
-    Ok(HttpResponse::Ok().json("The disguise has been recovered."))
+///
+/// download the disguises to recover with the steps of their selected functions
+/// and the selected functions stacked under the later disguises of the vault
+/// the later disguises are added to recover first with all their functions if they are reordered
+/// the disguises are sorted from the newest one, which is recovered first
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `requirement`: the data from user or the web
+/// * `disguise_ids`: the public ids of the disguises chosen by the requirement
+/// * `reorder`: if the later disguises are added
+///
+/// returns: Result<Vec<StackedDisguise, Global>, MyError>
+///
+async fn stack_disguises(
+    app_state: &AppState,
+    requirement: &Requirement,
+    disguise_ids: &[String],
+    reorder: bool,
+) -> Result<Vec<StackedDisguise>, MyError> {
+    let vault_db = &app_state.vault_db;
+    let mut disguises: Vec<StackedDisguise> = vec![];
+    let mut pending: Vec<(String, bool)> = disguise_ids.iter().map(|disguise_id| (disguise_id.clone(), true)).collect();
+    while let Some((disguise_id, chosen)) = pending.pop() {
+        if disguises.iter().any(|stacked| stacked.disguise.public_id.as_ref() == Some(&disguise_id)) {
+            continue;
+        }
+        let disguise = download_disguise_db(vault_db, disguise_id.as_str()).await?;
+        let steps = match chosen {
+            true => disguise.select(requirement.tables.as_deref(), requirement.function_ids.as_deref()).steps(),
+            false => disguise.steps(),
+        };
+        let stacked: Vec<StackedFunction> = get_stacked_functions_db(vault_db, disguise.disguise_id.unwrap()).await?
+            .into_iter()
+            .filter(|stacked| steps.iter().any(|step| step.has_function(stacked.function_id)))
+            .collect();
+        if reorder {
+            pending.extend(stacked.iter().filter_map(|stacked| stacked.later_disguise_id.clone().map(|later_id| (later_id, false))));
+        }
+        disguises.push(StackedDisguise {
+            disguise,
+            steps,
+            stacked
+        });
+    }
+    disguises.sort_by_key(|stacked| Reverse(stacked.disguise.disguise_id));
+    Ok(disguises)
+}
+
+///
+/// plan the recovery of the selected functions of the disguises
+/// the target is only read in the transaction which is rolled back
+/// the functions stacked under the later disguises are undone or refused by the stack policy
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `requirement`: the data from user or the web
+/// * `disguises`: the disguises to recover from the newest one
+/// * `stack`: the policy of the stacked functions
+/// * `keys`: the keys to unwrap the data keys of the disguises
+///
+/// returns: Result<RecoveryPreview, MyError>
+///
+async fn preview_recovery_of(
+    app_state: &AppState,
+    requirement: &Requirement,
+    disguises: &[StackedDisguise],
+    stack: StackPolicy,
+    keys: &VaultKeys,
+) -> Result<RecoveryPreview, MyError> {
+    let vault_db = &app_state.vault_db;
+    let policies = requirement.recovery_policies();
+    let blocked = blocked_functions(disguises);
+    let mut target_tx = app_state.target_db.begin().await?;
+    let mut functions = vec![];
+    for StackedDisguise { disguise, steps, .. } in disguises {
+        let data_key = get_data_key_db(vault_db, disguise.disguise_id.unwrap(), keys).await?;
+        //only the functions which have not been recovered are previewed
+        for step in steps {
+            let mut diffs = preview_recovery_db(&mut target_tx, step, data_key.as_ref(), &policies).await?;
+            for diff in diffs.iter_mut().filter(|diff| blocked.iter().any(|stacked| stacked.function_id == diff.function_id)) {
+                diff.set_stacked(stack == StackPolicy::Undo);
+            }
+            functions.extend(diffs);
+        }
+    }
+    target_tx.rollback().await?;
+
+    let count = |action: &str| functions.iter().filter(|diff| diff.action.as_deref() == Some(action)).count();
+    let (aborted, failures) = (count("abort"), count("fail"));
+    let conflicts = functions.iter().filter(|diff| diff.conflict.is_some()).count();
+    let message = match (aborted, failures) {
+        (0, 0) => "The recovery would succeed.".to_string(),
+        (0, failures) => format!("The recovery would fail on {} functions.", failures),
+        (aborted, _) => format!("The recovery would be aborted by {} conflicts.", aborted),
+    };
+    Ok(RecoveryPreview {
+        disguise_ids: Some(disguises.iter().map(|stacked| stacked.disguise.public_id.clone().unwrap()).collect()),
+        functions: Some(functions),
+        conflicts: Some(conflicts),
+        failures: Some(failures),
+        message: Some(message)
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use std::borrow::Borrow;
     use std::env;
-    use std::future::Future;
-    use std::sync::Mutex;
     use std::time::Duration;
     use actix_web::http::StatusCode;
     use actix_web::web;
@@ -234,10 +1022,12 @@ mod tests {
     use chrono::Local;
     use sqlx::mysql::MySqlPoolOptions;
     use dotenv::dotenv;
-    use crate::error::MyError;
     use crate::handlers::disguise::*;
+    use crate::middleware::Actor;
+    use crate::models::predicate::Predicate;
     use crate::models::requirement::Requirement;
     use crate::models::transformation::Transformation;
+    use crate::models::vault::Disguise;
     use crate::state::AppState;
 
     #[ignore]
@@ -256,37 +1046,51 @@ mod tests {
         let shared_data = web::Data::new(AppState {
             vault_db,
             target_db,
+            allow_raw_sql: false,
+            master_key: None,
+            key_store: None,
+            api_auth: false,
         });
         //create the transformations and requirement
         let decorrelate = Transformation {
             transform_type: Some("decorrelation".into()),
             table_name: Some("review".into()),
-            predicate: Some("contact_id=19".into()),
+            predicate: Some(Predicate::eq("contact_id", 19.into())),
             foreign_key: Some("contact_id".into()),
             changes: None,
+            raw_sql: None,
+            unique_placeholder: None,
         };
         let removal = Transformation {
             transform_type: Some("removal".into()),
             table_name: Some("contact_info".into()),
-            predicate: Some("contact_id=19".into()),
+            predicate: Some(Predicate::eq("contact_id", 19.into())),
             foreign_key: None,
             changes: None,
+            raw_sql: None,
+            unique_placeholder: None,
         };
         let requirement = Requirement {
             disguise_name: Some("userscrub".into()),
             vault_id: Some("19".into()),
+            disguise_id: None,
+            selector: None,
             delete_age: None,
             delete_name: None,
             transformations: Some(vec![decorrelate, removal]),
+            ..Requirement::new_empty()
         };
 
         //the requirement of recover
         let requirement_recover = Requirement {
             disguise_name: Some("userscrub".to_string()),
             vault_id: Some("19".to_string()),
+            disguise_id: None,
+            selector: None,
             delete_age: None,
             delete_name: None,
-            transformations: None
+            transformations: None,
+            ..Requirement::new_empty()
         };
 
         let mut i = 0;
@@ -299,14 +1103,14 @@ mod tests {
             let start = Local::now();
 
 
-            let res = scrub_user(shared_data.clone(), Json(requirement.clone())).await.unwrap();
+            let res = scrub_user(shared_data.clone(), Actor::anonymous(), Json(requirement.clone())).await.unwrap();
             assert_eq!(res.status(), StatusCode::OK);
 
             //to test the time
             let time = Local::now() - start;
             println!("{:?}", time.num_milliseconds());
             
-            recover_disguise(shared_data.clone(), Json(requirement_recover.clone())).await.unwrap();
+            recover_disguise(shared_data.clone(), Actor::anonymous(), Json(requirement_recover.clone())).await.unwrap();
             
             i += 1;
         }
@@ -329,30 +1133,42 @@ mod tests {
         let shared_data = web::Data::new(AppState {
             vault_db,
             target_db,
+            allow_raw_sql: false,
+            master_key: None,
+            key_store: None,
+            api_auth: false,
         });
         //create the transformations and requirement
         let decorrelate = Transformation {
             transform_type: Some("decorrelation".into()),
             table_name: Some("review".into()),
-            predicate: Some("contact_id=19".into()),
+            predicate: Some(Predicate::eq("contact_id", 19.into())),
             foreign_key: Some("contact_id".into()),
             changes: None,
+            raw_sql: None,
+            unique_placeholder: None,
         };
         let requirement = Requirement {
             disguise_name: Some("anonymize".into()),
             vault_id: Some("19".into()),
+            disguise_id: None,
+            selector: None,
             delete_age: None,
             delete_name: None,
             transformations: Some(vec![decorrelate]),
+            ..Requirement::new_empty()
         };
 
         //the requirement of recover
         let requirement_recover = Requirement {
             disguise_name: Some("anonymize".to_string()),
             vault_id: Some("19".to_string()),
+            disguise_id: None,
+            selector: None,
             delete_age: None,
             delete_name: None,
-            transformations: None
+            transformations: None,
+            ..Requirement::new_empty()
         };
 
         let mut i = 0;
@@ -365,14 +1181,14 @@ mod tests {
             let start = Local::now();
 
 
-            let res = anonymize(shared_data.clone(), Json(requirement.clone())).await.unwrap();
+            let res = anonymize(shared_data.clone(), Actor::anonymous(), Json(requirement.clone())).await.unwrap();
             assert_eq!(res.status(), StatusCode::OK);
 
             //to test the time
             let time = Local::now() - start;
             println!("{:?}", time.num_milliseconds());
 
-            recover_disguise(shared_data.clone(), Json(requirement_recover.clone())).await.unwrap();
+            recover_disguise(shared_data.clone(), Actor::anonymous(), Json(requirement_recover.clone())).await.unwrap();
 
             i += 1;
         }
@@ -395,6 +1211,10 @@ mod tests {
         let shared_data = web::Data::new(AppState {
             vault_db,
             target_db,
+            allow_raw_sql: false,
+            master_key: None,
+            key_store: None,
+            api_auth: false,
         });
         //create the transformations and requirement
         let removal1 = Transformation {
@@ -403,29 +1223,39 @@ mod tests {
             predicate: None,
             foreign_key: Some("contact_id".into()),
             changes: None,
+            raw_sql: None,
+            unique_placeholder: None,
         };
         let removal2 = Transformation {
             transform_type: Some("removal".into()),
             table_name: Some("contact_info".into()),
-            predicate: Some("last_login_time".into()),
+            predicate: Some(Predicate::Raw("last_login_time".into())),
             foreign_key: None,
             changes: None,
+            raw_sql: None,
+            unique_placeholder: None,
         };
         let requirement = Requirement {
             disguise_name: Some("expiration".into()),
             vault_id: Some("19".into()),
+            disguise_id: None,
+            selector: None,
             delete_age: Some(5),
             delete_name: None,
             transformations: Some(vec![removal1, removal2]),
+            ..Requirement::new_empty()
         };
 
         //the requirement of recover
         let requirement_recover = Requirement {
             disguise_name: Some("expiration".to_string()),
             vault_id: Some("19".to_string()),
+            disguise_id: None,
+            selector: None,
             delete_age: None,
             delete_name: None,
-            transformations: None
+            transformations: None,
+            ..Requirement::new_empty()
         };
 
         let mut i = 0;
@@ -438,14 +1268,14 @@ mod tests {
             let start = Local::now();
 
 
-            let res = expiration(shared_data.clone(), Json(requirement.clone())).await.unwrap();
+            let res = expiration(shared_data.clone(), Actor::anonymous(), Json(requirement.clone())).await.unwrap();
             assert_eq!(res.status(), StatusCode::OK);
 
             //to test the time
             let time = Local::now() - start;
             println!("{:?}", time.num_milliseconds());
 
-            recover_disguise(shared_data.clone(), Json(requirement_recover.clone())).await.unwrap();
+            recover_disguise(shared_data.clone(), Actor::anonymous(), Json(requirement_recover.clone())).await.unwrap();
 
             i += 1;
         }
@@ -468,19 +1298,32 @@ mod tests {
         let shared_data = web::Data::new(AppState {
             vault_db,
             target_db,
+            allow_raw_sql: false,
+            master_key: None,
+            key_store: None,
+            api_auth: false,
         });
         //create the requirement
         let requirement = Requirement {
             disguise_name: Some("clearvault".into()),
             vault_id: Some("19".into()),
+            disguise_id: None,
+            selector: None,
             delete_age: None,
             delete_name: Some("userscrub".into()),
             transformations: None,
+            ..Requirement::new_empty()
         };
+        let disguises = find_disguises_db(
+            shared_data.vault_db.borrow(),
+            "19",
+            None,
+            Some("userscrub"),
+            "latest"
+        ).await.unwrap();
         let disguise = download_disguise_db(
             shared_data.vault_db.borrow(),
-            "userscrub",
-            "19"
+            disguises[0].public_id.as_ref().unwrap()
         ).await.unwrap();
 
         let mut i = 0;
@@ -493,14 +1336,22 @@ mod tests {
             let start = Local::now();
 
 
-            let res = clear_vault(shared_data.clone(), Json(requirement.clone())).await.unwrap();
+            let res = clear_vault(shared_data.clone(), Actor::anonymous(), Json(requirement.clone())).await.unwrap();
             assert_eq!(res.status(), StatusCode::OK);
 
             //to test the time
             let time = Local::now() - start;
             println!("{:?}", time.num_milliseconds());
 
-            upload_disguise_object_db(shared_data.vault_db.borrow(), &disguise).await;
+            //the cleared disguise is kept, so it is uploaded again with a new public id
+            let disguise = Disguise {
+                public_id: None,
+                status: None,
+                ..disguise.clone()
+            };
+            let mut vault_tx = shared_data.vault_db.begin().await.unwrap();
+            upload_disguise_object_db(&mut vault_tx, &disguise).await.unwrap();
+            vault_tx.commit().await.unwrap();
 
             i += 1;
         }
@@ -0,0 +1,74 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use crate::dbaccess::export::{export_vault_db, find_export_vault_db};
+use crate::error::MyError;
+use crate::models::crypto::VaultKeys;
+use crate::models::export::{ExportQuery, VaultExport};
+use crate::state::AppState;
+
+///
+/// export all the original rows kept in the vault as json
+/// the vault is found by the "vault_id" or the "email" in the query,
+/// and the user's recovery secret is given by the header "X-Recovery-Secret"
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `request`: the request with the headers
+/// * `query`: the id or the email of the vault
+///
+/// returns: Result<HttpResponse<BoxBody>, MyError>
+///
+pub async fn export_vault(
+    app_state: web::Data<AppState>,
+    request: HttpRequest,
+    query: web::Query<ExportQuery>,
+) -> Result<HttpResponse, MyError> {
+    println!("Request to export vault.");
+    let export = build_export(&app_state, &request, &query).await?;
+    Ok(HttpResponse::Ok().json(export))
+}
+
+///
+/// export the original rows of one table kept in the vault as csv
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `request`: the request with the headers
+/// * `table_name`: the name of the table in the path
+/// * `query`: the id or the email of the vault
+///
+/// returns: Result<HttpResponse<BoxBody>, MyError>
+///
+pub async fn export_table(
+    app_state: web::Data<AppState>,
+    request: HttpRequest,
+    table_name: web::Path<String>,
+    query: web::Query<ExportQuery>,
+) -> Result<HttpResponse, MyError> {
+    println!("Request to export table.");
+    let export = build_export(&app_state, &request, &query).await?;
+    let table = export.tables.unwrap()
+        .into_iter()
+        .find(|table| table.table_name.as_deref() == Some(table_name.as_str()))
+        .ok_or_else(|| MyError::NotFound("The vault keeps no rows of the table.".into()))?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}.csv\"", table_name)))
+        .body(table.to_csv()))
+}
+
+/// build the export of the vault, nothing is changed in the databases
+async fn build_export(app_state: &AppState, request: &HttpRequest, query: &ExportQuery) -> Result<VaultExport, MyError> {
+    let vault = find_export_vault_db(&app_state.vault_db, query).await?;
+    //the secret is kept out of the query, so it is not in the access logs
+    let secret = request.headers().get("X-Recovery-Secret")
+        .map(|secret| secret.to_str())
+        .transpose()
+        .map_err(|_err| MyError::InvalidInput("The recovery secret is not correct.".into()))?;
+    let keys = VaultKeys::opening(app_state.master_key.as_ref(), app_state.key_store.as_ref(), &vault, secret)?;
+    let mut target_tx = app_state.target_db.begin().await?;
+    let export = export_vault_db(&mut target_tx, &app_state.vault_db, vault, &keys).await?;
+    target_tx.rollback().await?;
+    Ok(export)
+}
@@ -1,2 +1,7 @@
 pub mod disguise;
-pub mod vault;
\ No newline at end of file
+pub mod vault;
+pub mod specification;
+pub mod schema;
+pub mod placeholder;
+pub mod export;
+pub mod audit;
@@ -0,0 +1,58 @@
+use actix_web::{HttpResponse, web};
+use crate::dbaccess::placeholder::collect_placeholders_db;
+use crate::error::MyError;
+use crate::models::placeholder::PlaceholderCollection;
+use crate::state::AppState;
+
+///
+/// report the references to every placeholder owned by the vaults
+/// nothing is removed
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+///
+/// returns: Result<HttpResponse<BoxBody>, MyError>
+///
+pub async fn placeholder_report(
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, MyError> {
+    let mut target_tx = app_state.target_db.begin().await?;
+    let mut vault_tx = app_state.vault_db.begin().await?;
+    let placeholders = collect_placeholders_db(&mut target_tx, &mut vault_tx, false).await?;
+    target_tx.rollback().await?;
+    vault_tx.rollback().await?;
+    Ok(HttpResponse::Ok().json(PlaceholderCollection {
+        placeholders: Some(placeholders),
+        removed: Some(0),
+        message: Some("The placeholders have been counted.".to_string())
+    }))
+}
+
+///
+/// remove the orphaned placeholders which no row refers to
+/// and the missing ones which have been deleted by the application
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+///
+/// returns: Result<HttpResponse<BoxBody>, MyError>
+///
+pub async fn collect_placeholders(
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, MyError> {
+    println!("Request to collect placeholders.");
+    let mut target_tx = app_state.target_db.begin().await?;
+    let mut vault_tx = app_state.vault_db.begin().await?;
+    let placeholders = collect_placeholders_db(&mut target_tx, &mut vault_tx, true).await?;
+    //the vault keeps owning the placeholders if the target failed to commit
+    target_tx.commit().await?;
+    vault_tx.commit().await?;
+    let removed = placeholders.iter().filter(|placeholder| placeholder.removed == Some(true)).count();
+    Ok(HttpResponse::Ok().json(PlaceholderCollection {
+        placeholders: Some(placeholders),
+        removed: Some(removed),
+        message: Some("The orphaned placeholders have been removed.".to_string())
+    }))
+}
@@ -0,0 +1,77 @@
+use actix_web::{HttpResponse, web};
+use crate::dbaccess::schema::discover_dependents_db;
+use crate::dbaccess::vault::get_vault_by_id_db;
+use crate::error::MyError;
+use crate::models::placeholder::PlaceholderInfo;
+use crate::models::requirement::Requirement;
+use crate::models::schema::{propose_transformations, Dependent, Discover, DiscoverRoot, Discovery};
+use crate::models::transformation::Transformation;
+use crate::state::AppState;
+
+///
+/// discover the user's data referring to the root row
+/// and propose the requirement of "userscrub" or "anonymize" for it
+/// the proposed requirement could be sent to "/disguise/apply" as it is
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `discover`: the data from user or web
+///
+/// returns: Result<HttpResponse<BoxBody>, MyError>
+///
+pub async fn discover(
+    app_state: web::Data<AppState>,
+    discover: web::Json<Discover>,
+) -> Result<HttpResponse, MyError> {
+    println!("Request to discover the user's data.");
+    let disguise_name = discover.disguise_name.as_ref()
+        .ok_or_else(|| MyError::InvalidInput("The disguise name is required.".into()))?
+        .to_lowercase();
+    let mut root = discover.root.clone()
+        .ok_or_else(|| MyError::InvalidInput("The root is required.".into()))?;
+    let (dependents, transformations) = discover_transformations(&app_state, &disguise_name, &mut root, discover.vault_id.as_deref()).await?;
+    let mut requirement = Requirement::new_empty();
+    requirement.disguise_name = Some(disguise_name);
+    requirement.vault_id = discover.vault_id.clone();
+    requirement.transformations = Some(transformations);
+    Ok(HttpResponse::Ok().json(Discovery {
+        requirement: Some(requirement),
+        dependents: Some(dependents)
+    }))
+}
+
+///
+/// discover the tables referring to the root
+/// and build the transformations of the disguise for it
+/// the application's database is only read
+/// the placeholder of the vault is checked to be a row of the root table
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `disguise_name`: "userscrub" or "anonymize"
+/// * `root`: the row which owns the user's data
+/// * `vault_id`: the vault whose placeholder the rows are decorrelated to
+///
+/// returns: Result<(Vec<Dependent>, Vec<Transformation>), MyError>
+///
+pub async fn discover_transformations(
+    app_state: &AppState,
+    disguise_name: &str,
+    root: &mut DiscoverRoot,
+    vault_id: Option<&str>,
+) -> Result<(Vec<Dependent>, Vec<Transformation>), MyError> {
+    let placeholder: Option<PlaceholderInfo> = match vault_id {
+        Some(vault_id) => {
+            let vault = get_vault_by_id_db(&app_state.vault_db, vault_id).await?;
+            vault.placeholder_info.and_then(|placeholder_info| serde_json::from_str(placeholder_info.as_str()).ok())
+        }
+        None => None,
+    };
+    let mut target_tx = app_state.target_db.begin().await?;
+    let dependents = discover_dependents_db(&mut target_tx, root).await?;
+    target_tx.rollback().await?;
+    let transformations = propose_transformations(disguise_name, root, &dependents, placeholder.as_ref())?;
+    Ok((dependents, transformations))
+}
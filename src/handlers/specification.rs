@@ -0,0 +1,143 @@
+use actix_web::{HttpResponse, web};
+use crate::dbaccess::specification::*;
+use crate::error::MyError;
+use crate::models::specification::{Specification, SpecificationVersion};
+use crate::state::AppState;
+
+///
+/// store a new specification,
+/// or a new version of the specification with the same name
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `specification`: the data from user or web
+///
+/// returns: Result<HttpResponse<BoxBody>, MyError>
+///
+pub async fn create_specification(
+    app_state: web::Data<AppState>,
+    specification: web::Json<Specification>,
+) -> Result<HttpResponse, MyError> {
+    println!("Request to create specification.");
+    let specification: Specification = specification.into();
+    check_specification(&app_state, &specification)?;
+    create_specification_db(&app_state.vault_db, &specification)
+        .await
+        .map(|specification| HttpResponse::Ok().json(specification))
+}
+
+///
+/// update the specification by storing it as the next version
+/// the older versions are kept for the applied disguises
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `name`: the name of the specification in the path
+/// * `specification`: the data from user or web
+///
+/// returns: Result<HttpResponse<BoxBody>, MyError>
+///
+pub async fn update_specification(
+    app_state: web::Data<AppState>,
+    name: web::Path<String>,
+    specification: web::Json<Specification>,
+) -> Result<HttpResponse, MyError> {
+    println!("Request to update specification.");
+    let mut specification: Specification = specification.into();
+    specification.name = Some(name.into_inner());
+    check_specification(&app_state, &specification)?;
+    //the specification should exist before it is updated
+    get_specification_db(&app_state.vault_db, specification.name.as_ref().unwrap(), None).await?;
+    create_specification_db(&app_state.vault_db, &specification)
+        .await
+        .map(|specification| HttpResponse::Ok().json(specification))
+}
+
+///
+/// get all the versions of all the specifications
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+///
+/// returns: Result<HttpResponse<BoxBody>, MyError>
+///
+pub async fn get_specifications(
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, MyError> {
+    get_specifications_db(&app_state.vault_db)
+        .await
+        .map(|specifications| HttpResponse::Ok().json(specifications))
+}
+
+///
+/// get the specification by its name
+/// and the "version" in the query, which is the latest by default
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `name`: the name of the specification in the path
+/// * `version`: the version in the query
+///
+/// returns: Result<HttpResponse<BoxBody>, MyError>
+///
+pub async fn get_specification(
+    app_state: web::Data<AppState>,
+    name: web::Path<String>,
+    version: web::Query<SpecificationVersion>,
+) -> Result<HttpResponse, MyError> {
+    get_specification_db(&app_state.vault_db, name.as_str(), version.version)
+        .await
+        .map(|specification| HttpResponse::Ok().json(specification))
+}
+
+///
+/// delete the specification by its name
+/// and the "version" in the query, which is all the versions by default
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `name`: the name of the specification in the path
+/// * `version`: the version in the query
+///
+/// returns: Result<HttpResponse<BoxBody>, MyError>
+///
+pub async fn delete_specification(
+    app_state: web::Data<AppState>,
+    name: web::Path<String>,
+    version: web::Query<SpecificationVersion>,
+) -> Result<HttpResponse, MyError> {
+    println!("Request to delete specification.");
+    delete_specification_db(&app_state.vault_db, name.as_str(), version.version)
+        .await
+        .map(|msg| HttpResponse::Ok().json(msg))
+}
+
+/// check if the specification could be stored
+fn check_specification(app_state: &AppState, specification: &Specification) -> Result<(), MyError> {
+    let name = specification.name.as_ref()
+        .ok_or_else(|| MyError::InvalidInput("The name of the specification is required.".into()))?
+        .to_lowercase();
+    //the name of clearing vault could not be applied as a disguise
+    if name.is_empty() || name == "clearvault" {
+        return Err(MyError::InvalidInput("The name of the specification is not correct.".into()));
+    }
+    let transformations = match &specification.transformations {
+        Some(transformations) if !transformations.is_empty() => transformations,
+        _ => return Err(MyError::InvalidInput("The transformations are required.".into())),
+    };
+    for transformation in transformations {
+        if transformation.transform_type.is_none() || transformation.table_name.is_none() {
+            return Err(MyError::InvalidInput("The type and the table of the transformation are required.".into()));
+        }
+        //the raw sql is only accepted when the server allows it
+        if transformation.allow_raw() && !app_state.allow_raw_sql {
+            return Err(MyError::InvalidInput("The raw sql is not allowed by the server.".into()));
+        }
+    }
+    Ok(())
+}
@@ -1,13 +1,21 @@
 use actix_web::{HttpResponse, web};
+use chrono::Local;
+use serde_json::Value;
+use crate::dbaccess::placeholder::register_placeholder_db;
 use crate::dbaccess::target::generate_placeholder_db;
-use crate::dbaccess::vault::generate_vault_db;
+use crate::dbaccess::vault::*;
 use crate::error::MyError;
-use crate::models::placeholder::PlaceholderInfo;
-use crate::models::vault::{GenerateVault, Vault};
+use crate::models::crypto::{new_secret, SealedRecoveryKey, MIN_SECRET_LENGTH};
+use crate::models::placeholder::{OwnedPlaceholder, PlaceholderInfo};
+use crate::models::predicate::Predicate;
+use crate::models::vault::{DisguiseDetail, DisguiseSummary, GenerateVault, GeneratedVault, Vault, VaultInfo, VaultQuery};
 use crate::state::AppState;
 
 ///
 /// generate a new vault for a user
+/// the vault is only recovered with the user's secret,
+/// if the user gives the secret or asks for one by "recovery",
+/// and the generated secret is only returned in the response
 ///
 /// # Arguments
 ///
@@ -21,36 +29,193 @@ pub async fn generate_vault(
     generate_vault: web::Json<GenerateVault>
 ) -> Result<HttpResponse, MyError> {
     let vault_db = &app_state.vault_db;
+    //the user's secret protects the recovery key of the vault
+    let (secret, generated) = match (&generate_vault.secret, generate_vault.recovery) {
+        (Some(secret), _) if secret.chars().count() < MIN_SECRET_LENGTH => {
+            return Err(MyError::InvalidInput(format!(
+                "The recovery secret should have at least {} characters.", MIN_SECRET_LENGTH
+            )));
+        }
+        (Some(secret), _) => (Some(secret.clone()), false),
+        (None, Some(true)) => (Some(new_secret()), true),
+        (None, _) => (None, false),
+    };
+    let recovery_key = match secret.clone() {
+        //generating the key pair takes a while
+        Some(secret) => Some(web::block(move || SealedRecoveryKey::generate(secret.as_str()))
+            .await
+            .map_err(|err| MyError::ActixError(err.to_string()))??),
+        None => None,
+    };
     //generate the placeholder in the database
-    let generate_placeholder = generate_vault.generate_placeholder.as_ref().unwrap().clone();
+    let generate_placeholder = generate_vault.generate_placeholder.clone()
+        .ok_or_else(|| MyError::InvalidInput("The placeholder of the vault is required.".into()))?;
+    let primary_key_name = generate_placeholder.primary_key_name.clone()
+        .ok_or_else(|| MyError::InvalidInput("The primary key of the placeholder is required.".into()))?;
     //get the new placeholder's id and put it into vault
-    let placeholder_id = generate_placeholder_db(&app_state.target_db, &generate_placeholder).await.unwrap();
+    let placeholder_id = generate_placeholder_db(&app_state.target_db, &generate_placeholder, app_state.allow_raw_sql).await?;
+    let placeholder_key = placeholder_id.parse::<u64>()
+        .map_err(|err| MyError::OperationError(format!("The id of the placeholder is not correct: {}", err)))?;
+    let placeholder = OwnedPlaceholder {
+        vault_id: generate_vault.vault_id.clone(),
+        disguise_id: None,
+        placeholder_table: generate_placeholder.table.clone(),
+        placeholder_predicate: Some(Predicate::eq(&primary_key_name, Value::from(placeholder_key)).to_vault()),
+        time: Some(Local::now().to_string())
+    };
+    let placeholder_info = serde_json::to_string(&PlaceholderInfo::new(generate_placeholder, placeholder_id.as_str()))
+        .map_err(|err| MyError::OperationError(err.to_string()))?;
     let vault = Vault {
         vault_id: generate_vault.vault_id.clone(),
         email: generate_vault.email.clone(),
-        placeholder_info: Some(serde_json::to_string(&PlaceholderInfo {
-            pred: Some(generate_placeholder.primary_key_name.unwrap().clone() + "=" + placeholder_id.as_str()),
-            table: Some(generate_placeholder.table.unwrap().clone())
-        }).unwrap())
+        placeholder_info: Some(placeholder_info),
+        recovery_public_key: recovery_key.as_ref().map(|recovery_key| recovery_key.public_key.clone()),
+        recovery_private_key: recovery_key.as_ref().map(|recovery_key| recovery_key.private_key.clone()),
+        recovery_salt: recovery_key.map(|recovery_key| recovery_key.salt)
     };
-    //then generate the vault in the database
-    generate_vault_db(vault_db, vault)
+    //then generate the vault in the database,
+    //and the shared placeholder is owned by the vault in the same transaction
+    let mut vault_tx = vault_db.begin().await?;
+    let msg = generate_vault_db(&mut vault_tx, vault).await?;
+    register_placeholder_db(&mut vault_tx, &placeholder).await?;
+    vault_tx.commit().await?;
+    Ok(HttpResponse::Ok().json(GeneratedVault {
+        vault_id: generate_vault.vault_id.clone(),
+        recovery_secret: secret.filter(|_| generated),
+        message: Some(msg)
+    }))
+}
+
+///
+/// get the vault by its id
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `vault_id`: the id of the vault in the path
+///
+/// returns: Result<HttpResponse<BoxBody>, MyError>
+///
+pub async fn get_vault(
+    app_state: web::Data<AppState>,
+    vault_id: web::Path<String>,
+) -> Result<HttpResponse, MyError> {
+    get_vault_by_id_db(&app_state.vault_db, vault_id.as_str())
+        .await
+        .map(|vault| HttpResponse::Ok().json(VaultInfo::from(vault)))
+}
+
+///
+/// find the vault by the "email" in the query
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `query`: the email of the vault's owner
+///
+/// returns: Result<HttpResponse<BoxBody>, MyError>
+///
+pub async fn find_vault(
+    app_state: web::Data<AppState>,
+    query: web::Query<VaultQuery>,
+) -> Result<HttpResponse, MyError> {
+    let email = query.email.as_ref()
+        .ok_or_else(|| MyError::InvalidInput("The email of the vault is required.".into()))?;
+    get_vault_by_email_db(&app_state.vault_db, email.as_str())
+        .await
+        .map(|vault| HttpResponse::Ok().json(VaultInfo::from(vault)))
+}
+
+///
+/// list the disguises applied to the vault
+/// with the number of the functions on every table
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `vault_id`: the id of the vault in the path
+///
+/// returns: Result<HttpResponse<BoxBody>, MyError>
+///
+pub async fn list_disguises(
+    app_state: web::Data<AppState>,
+    vault_id: web::Path<String>,
+) -> Result<HttpResponse, MyError> {
+    let vault_db = &app_state.vault_db;
+    //the vault should exist even if it has no disguises
+    get_vault_by_id_db(vault_db, vault_id.as_str()).await?;
+    let mut summaries = vec![];
+    for disguise in list_disguises_db(vault_db, vault_id.as_str()).await? {
+        let functions = count_functions_db(vault_db, disguise.disguise_id.unwrap()).await?;
+        summaries.push(DisguiseSummary {
+            disguise_id: disguise.public_id,
+            disguise_type: disguise.disguise_type,
+            time: disguise.time,
+            status: disguise.status,
+            functions: Some(functions)
+        });
+    }
+    Ok(HttpResponse::Ok().json(summaries))
+}
+
+///
+/// get one disguise of the vault with all its functions and the states it has entered,
+/// and the functions whose rows have been changed again by the later disguises
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `path`: the id of the vault and the public id of the disguise
+///
+/// returns: Result<HttpResponse<BoxBody>, MyError>
+///
+pub async fn get_disguise(
+    app_state: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, MyError> {
+    let (vault_id, disguise_id) = path.into_inner();
+    let vault_db = &app_state.vault_db;
+    //the disguise should belong to the vault
+    find_disguises_db(vault_db, vault_id.as_str(), Some(disguise_id.as_str()), None, "latest").await?;
+    let disguise = download_disguise_db(vault_db, disguise_id.as_str()).await?;
+    let history = get_disguise_history_db(vault_db, disguise.disguise_id.unwrap()).await?;
+    let stacked = get_stacked_functions_db(vault_db, disguise.disguise_id.unwrap()).await?;
+    let mut detail = DisguiseDetail::from(disguise);
+    detail.history = Some(history);
+    detail.stacked = Some(stacked);
+    Ok(HttpResponse::Ok().json(detail))
+}
+
+///
+/// get the tombstones of the disguises cleared from the vault,
+/// which prove that their data keys have been destroyed
+///
+/// # Arguments
+///
+/// * `app_state`: the state of the server
+/// * `vault_id`: the id of the vault in the path
+///
+/// returns: Result<HttpResponse<BoxBody>, MyError>
+///
+pub async fn get_tombstones(
+    app_state: web::Data<AppState>,
+    vault_id: web::Path<String>,
+) -> Result<HttpResponse, MyError> {
+    get_tombstones_db(&app_state.vault_db, vault_id.as_str())
         .await
-        .map(|msg| HttpResponse::Ok().json(msg) )
+        .map(|tombstones| HttpResponse::Ok().json(tombstones))
 }
 
 #[cfg(test)]
 mod tests {
     use std::env;
-    use std::sync::Mutex;
-    use actix_web::http::StatusCode;
     use actix_web::web;
     use sqlx::mysql::MySqlPoolOptions;
     use dotenv::dotenv;
-    use crate::dbaccess::vault::generate_vault_db;
+    use serde_json::json;
     use crate::handlers::vault::generate_vault;
-    use crate::models::placeholder::{GeneratePlaceHolder, PlaceholderInfo};
-    use crate::models::vault::{GenerateVault, Vault};
+    use crate::models::placeholder::{GeneratePlaceHolder, Generator, PlaceholderColumn};
+    use crate::models::vault::GenerateVault;
     use crate::state::AppState;
 
     #[ignore]
@@ -67,22 +232,33 @@ mod tests {
         //put the target database pool in the state
         let shared_data = web::Data::new(AppState {
             vault_db,
-            target_db
+            target_db,
+            allow_raw_sql: false,
+            master_key: None,
+            key_store: None,
+            api_auth: false
         });
         let generate_placeholder = GeneratePlaceHolder {
             table: Some("contact_info".into()),
             primary_key_name: Some("contact_id".into()),
-            fields: Some("name, email, disabled".into()),
-            field_values: Some("'placeholder', '777', true".into())
+            fields: None,
+            field_values: None,
+            columns: Some(vec![
+                PlaceholderColumn { column: Some("name".into()), generator: Some(Generator::Constant { value: json!("placeholder") }) },
+                PlaceholderColumn { column: Some("email".into()), generator: Some(Generator::Constant { value: json!("777") }) },
+                PlaceholderColumn { column: Some("disabled".into()), generator: Some(Generator::Constant { value: json!(true) }) }
+            ])
         };
         let new_vault = GenerateVault {
             vault_id: Some("19".into()),
             email: Some("bea@mail.com".into()),
-            generate_placeholder: Some(generate_placeholder)
+            generate_placeholder: Some(generate_placeholder),
+            secret: None,
+            recovery: None
         };
         let new_vault = web::Json(new_vault);
         let res = generate_vault(shared_data, new_vault).await;
-        assert_eq!(res.is_ok(), true);
+        assert!(res.is_ok());
     }
 }
 
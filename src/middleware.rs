@@ -0,0 +1,275 @@
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{web, Error, FromRequest, HttpMessage, HttpRequest};
+use crate::dbaccess::api_key::get_api_key_db;
+use crate::error::MyError;
+use crate::models::api_key::{split_token, Role};
+use crate::state::AppState;
+
+/// the header of the api key besides the bearer token
+const API_KEY_HEADER: &str = "X-API-Key";
+
+/// the api key which requested the operation, recorded in the audit log
+/// it is anonymous when the authentication is turned off
+#[derive(Debug, Clone)]
+pub struct Actor(pub String);
+
+/// the middleware requiring the api key of the role, or of a higher role
+/// the requests are not checked if the authentication is turned off
+#[derive(Debug, Clone, Copy)]
+pub struct RequireRole(pub Role);
+
+impl<S, B> Transform<S, ServiceRequest> for RequireRole
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RequireRoleMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequireRoleMiddleware {
+            service: Rc::new(service),
+            role: self.0
+        }))
+    }
+}
+
+/// the service checking the api key before the wrapped service
+pub struct RequireRoleMiddleware<S> {
+    service: Rc<S>,
+    role: Role
+}
+
+impl<S, B> Service<ServiceRequest> for RequireRoleMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let role = self.role;
+        Box::pin(async move {
+            match authorize(&req, role).await {
+                Ok(actor) => {
+                    req.extensions_mut().insert(actor);
+                }
+                Err(err) => return Ok(req.error_response(err).map_into_right_body()),
+            }
+            service.call(req).await.map(ServiceResponse::map_into_left_body)
+        })
+    }
+}
+
+///
+/// check the api key of the request against the required role
+///
+/// # Arguments
+///
+/// * `req`: the request
+/// * `role`: the lowest role allowed
+///
+/// returns: Result<Actor, MyError>
+///
+async fn authorize(req: &ServiceRequest, role: Role) -> Result<Actor, MyError> {
+    let app_state = req.app_data::<web::Data<AppState>>()
+        .ok_or_else(|| MyError::OperationError("The state of the server is not found.".into()))?;
+    if !app_state.api_auth {
+        return Ok(Actor::anonymous());
+    }
+    let token = get_token(req)
+        .ok_or_else(|| MyError::Unauthorized("The api key is required.".into()))?;
+    let invalid = || MyError::Unauthorized("The api key is not valid.".into());
+    let (key_id, secret) = split_token(token.as_str()).ok_or_else(invalid)?;
+    let api_key = get_api_key_db(&app_state.vault_db, key_id).await?.ok_or_else(invalid)?;
+    if !api_key.verify(secret) {
+        return Err(invalid());
+    }
+    check_role(api_key.role.as_deref().unwrap_or_default(), role)?;
+    Ok(Actor(format!("{} ({})", api_key.name.unwrap_or_default(), key_id)))
+}
+
+/// check the role granted to the api key against the lowest role allowed
+fn check_role(granted: &str, role: Role) -> Result<(), MyError> {
+    let granted = Role::parse(granted)
+        .map_err(|_err| MyError::Unauthorized("The api key is not valid.".into()))?;
+    if granted < role {
+        return Err(MyError::Forbidden(format!("The role \"{}\" is required.", role.name())));
+    }
+    Ok(())
+}
+
+/// get the token from the bearer authorization or the api key header
+fn get_token(req: &ServiceRequest) -> Option<String> {
+    let headers = req.headers();
+    if let Some(value) = headers.get(actix_web::http::header::AUTHORIZATION) {
+        let value = value.to_str().ok()?;
+        return value.strip_prefix("Bearer ").map(|token| token.trim().to_string());
+    }
+    headers.get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(|token| token.trim().to_string())
+}
+
+impl Actor {
+    /// the actor of the requests without the api keys
+    pub fn anonymous() -> Self {
+        Actor("anonymous".to_string())
+    }
+}
+
+impl FromRequest for Actor {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut actix_web::dev::Payload) -> Self::Future {
+        ready(Ok(req.extensions().get::<Actor>().cloned().unwrap_or_else(Actor::anonymous)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{web, App, HttpResponse};
+    use chrono::Local;
+    use dotenv::dotenv;
+    use sqlx::mysql::MySqlPoolOptions;
+    use crate::dbaccess::api_key::{create_api_key_db, revoke_api_key_db};
+    use crate::error::MyError;
+    use crate::middleware::{check_role, get_token, Actor, RequireRole};
+    use crate::models::api_key::{ApiKey, Role};
+    use crate::state::AppState;
+
+    /// the state of the server with the authentication turned on
+    /// the database is only connected when the api key is looked up
+    fn auth_state(vault_database_url: &str) -> web::Data<AppState> {
+        web::Data::new(AppState {
+            vault_db: MySqlPoolOptions::new().connect_lazy(vault_database_url).unwrap(),
+            target_db: MySqlPoolOptions::new().connect_lazy(vault_database_url).unwrap(),
+            allow_raw_sql: false,
+            master_key: None,
+            key_store: None,
+            api_auth: true
+        })
+    }
+
+    /// call the route requiring the "app" role with the headers
+    async fn call_app_route(app_state: web::Data<AppState>, headers: Vec<(&str, String)>) -> StatusCode {
+        let app = init_service(
+            App::new()
+                .app_data(app_state)
+                .route("/", web::get()
+                    .to(|actor: Actor| async move { HttpResponse::Ok().body(actor.0) })
+                    .wrap(RequireRole(Role::App)))
+        ).await;
+        let mut req = TestRequest::get().uri("/");
+        for header in headers {
+            req = req.insert_header(header);
+        }
+        call_service(&app, req.to_request()).await.status()
+    }
+
+    #[test]
+    fn get_token_test() {
+        let req = TestRequest::default()
+            .insert_header(("Authorization", "Bearer key.secret "))
+            .to_srv_request();
+        assert_eq!(get_token(&req), Some("key.secret".to_string()));
+        let req = TestRequest::default()
+            .insert_header(("X-API-Key", "key.secret"))
+            .to_srv_request();
+        assert_eq!(get_token(&req), Some("key.secret".to_string()));
+        //the other authorization schemes are not the api keys
+        let req = TestRequest::default()
+            .insert_header(("Authorization", "Basic a2V5OnNlY3JldA=="))
+            .insert_header(("X-API-Key", "key.secret"))
+            .to_srv_request();
+        assert_eq!(get_token(&req), None);
+        assert_eq!(get_token(&TestRequest::default().to_srv_request()), None);
+    }
+
+    #[test]
+    fn check_role_test() {
+        assert!(check_role("admin", Role::App).is_ok());
+        assert!(check_role("app", Role::App).is_ok());
+        assert!(matches!(check_role("support", Role::App), Err(MyError::Forbidden(_))));
+        //the unknown role is not valid
+        assert!(matches!(check_role("root", Role::Support), Err(MyError::Unauthorized(_))));
+        assert!(matches!(check_role("", Role::Support), Err(MyError::Unauthorized(_))));
+    }
+
+    #[actix_rt::test]
+    async fn missing_key_test() {
+        let app_state = auth_state("mysql://root@localhost:3306/unused");
+        assert_eq!(call_app_route(app_state, vec![]).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_rt::test]
+    async fn malformed_key_test() {
+        let app_state = auth_state("mysql://root@localhost:3306/unused");
+        let headers = vec![("Authorization", "Bearer without-secret".to_string())];
+        assert_eq!(call_app_route(app_state.clone(), headers).await, StatusCode::UNAUTHORIZED);
+        let headers = vec![("Authorization", "Token key.secret".to_string())];
+        assert_eq!(call_app_route(app_state, headers).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_rt::test]
+    async fn auth_off_test() {
+        let app_state = auth_state("mysql://root@localhost:3306/unused");
+        let app_state = web::Data::new(AppState {
+            vault_db: app_state.vault_db.clone(),
+            target_db: app_state.target_db.clone(),
+            allow_raw_sql: false,
+            master_key: None,
+            key_store: None,
+            api_auth: false
+        });
+        assert_eq!(call_app_route(app_state, vec![]).await, StatusCode::OK);
+    }
+
+    #[ignore]
+    #[actix_rt::test]
+    async fn revoked_key_test() {
+        //load the env variables
+        dotenv().ok();
+        let vault_database_url = env::var("VAULT_DATABASE_URL").expect("VAULT_DATABASE_URL is not set yet.");
+        let app_state = auth_state(vault_database_url.as_str());
+        let (api_key, token) = ApiKey::generate("web", Role::App, Local::now().to_string());
+        create_api_key_db(&app_state.vault_db, &api_key).await.unwrap();
+        let headers = vec![("X-API-Key", token.clone())];
+        assert_eq!(call_app_route(app_state.clone(), headers).await, StatusCode::OK);
+        //the revoked key is refused, and so is the wrong secret
+        revoke_api_key_db(&app_state.vault_db, api_key.key_id.as_ref().unwrap()).await.unwrap();
+        let headers = vec![("X-API-Key", token)];
+        assert_eq!(call_app_route(app_state.clone(), headers).await, StatusCode::UNAUTHORIZED);
+        let headers = vec![("X-API-Key", format!("{}.wrong", api_key.key_id.unwrap()))];
+        assert_eq!(call_app_route(app_state, headers).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[ignore]
+    #[actix_rt::test]
+    async fn lower_role_test() {
+        //load the env variables
+        dotenv().ok();
+        let vault_database_url = env::var("VAULT_DATABASE_URL").expect("VAULT_DATABASE_URL is not set yet.");
+        let app_state = auth_state(vault_database_url.as_str());
+        let (api_key, token) = ApiKey::generate("support desk", Role::Support, Local::now().to_string());
+        create_api_key_db(&app_state.vault_db, &api_key).await.unwrap();
+        let headers = vec![("Authorization", format!("Bearer {}", token))];
+        assert_eq!(call_app_route(app_state, headers).await, StatusCode::FORBIDDEN);
+    }
+}
@@ -0,0 +1,125 @@
+use ring::constant_time::verify_slices_are_equal;
+use ring::digest::{digest, SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use crate::error::MyError;
+use crate::models::placeholder::new_uuid;
+
+/// the role of the api key, every role could do what the lower roles do
+/// "support" only reads the vaults, "app" applies and recovers the disguises,
+/// and "admin" also clears the vaults and manages the specifications
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Support,
+    App,
+    Admin,
+}
+/// the api key stored in the vault database
+/// only the hash of its secret is stored
+#[derive(Serialize, Debug, Clone, FromRow)]
+pub struct ApiKey {
+    pub key_id: Option<String>,
+    pub name: Option<String>,
+    #[serde(skip_serializing)]
+    pub key_hash: Option<String>,
+    pub role: Option<String>,
+    pub time: Option<String>,
+    pub revoked: Option<bool>
+}
+
+impl Role {
+    /// get the role by its name
+    pub fn parse(name: &str) -> Result<Role, MyError> {
+        match name {
+            "support" => Ok(Role::Support),
+            "app" => Ok(Role::App),
+            "admin" => Ok(Role::Admin),
+            _ => Err(MyError::InvalidInput("The role should be \"support\", \"app\" or \"admin\".".into())),
+        }
+    }
+
+    /// get the name of the role
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::Support => "support",
+            Role::App => "app",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl ApiKey {
+    ///
+    /// generate a new api key with its token
+    /// the token is "<key id>.<secret>", which is only shown once
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: the name of the key's owner
+    /// * `role`: the role of the key
+    /// * `time`: the time the key is generated
+    ///
+    /// returns: (ApiKey, String)
+    ///
+    #[allow(dead_code)]
+    pub fn generate(name: &str, role: Role, time: String) -> (ApiKey, String) {
+        let mut secret = [0u8; 32];
+        SystemRandom::new().fill(&mut secret).unwrap();
+        let secret = base64::encode_config(secret, base64::URL_SAFE_NO_PAD);
+        let key_id = new_uuid();
+        let token = format!("{}.{}", key_id, secret);
+        let api_key = ApiKey {
+            key_id: Some(key_id),
+            name: Some(name.to_string()),
+            key_hash: Some(hash_secret(secret.as_str())),
+            role: Some(role.name().to_string()),
+            time: Some(time),
+            revoked: Some(false)
+        };
+        (api_key, token)
+    }
+
+    /// check the secret of the token against the stored hash
+    pub fn verify(&self, secret: &str) -> bool {
+        let expected = self.key_hash.as_deref().unwrap_or_default();
+        !self.revoked.unwrap_or(true)
+            && verify_slices_are_equal(expected.as_bytes(), hash_secret(secret).as_bytes()).is_ok()
+    }
+}
+
+/// split the token into its key id and its secret
+pub fn split_token(token: &str) -> Option<(&str, &str)> {
+    token.trim().split_once('.')
+}
+
+/// get the sha-256 of the secret in hex
+/// the secret is random enough without a slow hash
+fn hash_secret(secret: &str) -> String {
+    digest(&SHA256, secret.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::api_key::*;
+
+    #[test]
+    fn api_key_test() {
+        let (mut api_key, token) = ApiKey::generate("web", Role::App, "2022-07-14".into());
+        let (key_id, secret) = split_token(token.as_str()).unwrap();
+        assert_eq!(api_key.key_id.as_deref(), Some(key_id));
+        assert!(api_key.verify(secret));
+        assert!(!api_key.verify("not the secret"));
+        api_key.revoked = Some(true);
+        assert!(!api_key.verify(secret));
+        //the higher role could do what the lower roles do
+        assert!(Role::Admin >= Role::App && Role::App >= Role::Support);
+        assert_eq!(Role::parse("support").unwrap(), Role::Support);
+        assert!(Role::parse("root").is_err());
+    }
+}
@@ -0,0 +1,179 @@
+use chrono::Local;
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::FromRow;
+
+/// the hash before the first entry of the audit log
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// one entry of the append-only audit log
+/// every entry's hash covers its fields and the hash of the entry before it,
+/// so changing or deleting any entry breaks the chain after it
+#[derive(Deserialize, Serialize, Debug, Clone, FromRow)]
+pub struct AuditEntry {
+    pub audit_id: Option<i64>,
+    /// the api key which requested the operation
+    pub actor: Option<String>,
+    /// "apply", "recover" or "clearvault"
+    pub operation: Option<String>,
+    pub vault_id: Option<String>,
+    /// the public ids of the disguises as a json array
+    pub disguise_ids: Option<String>,
+    /// the transformations or the disguises handled by the operation
+    pub summary: Option<String>,
+    pub row_count: Option<i64>,
+    /// "succeeded" or "failed"
+    pub outcome: Option<String>,
+    pub message: Option<String>,
+    pub time: Option<String>,
+    pub prev_hash: Option<String>,
+    pub hash: Option<String>
+}
+/// the audit entries to query, of one vault or in a time range
+#[derive(Deserialize, Debug, Clone)]
+pub struct AuditQuery {
+    pub vault_id: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub limit: Option<i64>
+}
+/// the audit entries returned to the auditors
+/// with the result of checking their hashes
+#[derive(Serialize, Debug, Clone)]
+pub struct AuditTrail {
+    pub entries: Option<Vec<AuditEntry>>,
+    pub verified: Option<bool>,
+    /// the first entry whose hash or link is not correct
+    pub broken_at: Option<i64>,
+    pub message: Option<String>
+}
+
+impl AuditEntry {
+    ///
+    /// create the entry of the operation before it is executed
+    ///
+    /// # Arguments
+    ///
+    /// * `actor`: the api key which requested the operation
+    /// * `operation`: the name of the operation
+    /// * `vault_id`: the vault of the operation
+    ///
+    /// returns: AuditEntry
+    ///
+    pub fn new(actor: &str, operation: &str, vault_id: Option<String>) -> Self {
+        AuditEntry {
+            audit_id: None,
+            actor: Some(actor.to_string()),
+            operation: Some(operation.to_string()),
+            vault_id,
+            disguise_ids: None,
+            summary: None,
+            row_count: None,
+            outcome: None,
+            message: None,
+            time: None,
+            prev_hash: None,
+            hash: None
+        }
+    }
+
+    /// set the public ids of the handled disguises
+    pub fn set_disguises(&mut self, disguise_ids: &[String]) {
+        self.disguise_ids = Some(json!(disguise_ids).to_string());
+    }
+
+    /// set the outcome of the operation with its message
+    pub fn set_outcome(&mut self, succeeded: bool, message: String) {
+        self.outcome = Some(if succeeded { "succeeded" } else { "failed" }.to_string());
+        self.message = Some(message);
+    }
+
+    /// link the entry to the one before it, and hash it with the time it is appended
+    pub fn seal(&mut self, prev_hash: Option<String>) {
+        self.time = Some(Local::now().to_string());
+        self.prev_hash = Some(prev_hash.unwrap_or_else(|| GENESIS_HASH.to_string()));
+        self.hash = Some(self.compute_hash());
+    }
+
+    /// get the sha-256 in hex of the entry's fields and the hash before it
+    pub fn compute_hash(&self) -> String {
+        let fields = json!([
+            self.prev_hash, self.actor, self.operation, self.vault_id, self.disguise_ids,
+            self.summary, self.row_count, self.outcome, self.message, self.time
+        ]);
+        digest(&SHA256, fields.to_string().as_bytes())
+            .as_ref()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+///
+/// check the hashes of the entries in the order they were appended
+/// the whole log is linked from the genesis hash without any gap,
+/// but only the consecutive entries are linked when the log is filtered
+///
+/// # Arguments
+///
+/// * `entries`: the entries ordered by their ids
+/// * `complete`: if the entries are the whole log
+///
+/// returns: Option<i64>, the first entry which is not correct
+///
+pub fn verify_chain(entries: &[AuditEntry], complete: bool) -> Option<i64> {
+    let mut previous: Option<&AuditEntry> = None;
+    for entry in entries {
+        let linked = match previous {
+            None if complete => entry.prev_hash.as_deref() == Some(GENESIS_HASH),
+            Some(previous) if complete => entry.prev_hash == previous.hash,
+            //the ids of the filtered entries are not consecutive when the others are between them
+            Some(previous) if previous.audit_id.zip(entry.audit_id).is_some_and(|(prev_id, id)| prev_id + 1 == id) =>
+                entry.prev_hash == previous.hash,
+            _ => true,
+        };
+        if !linked || entry.hash.as_deref() != Some(entry.compute_hash().as_str()) {
+            return Some(entry.audit_id.unwrap_or_default());
+        }
+        previous = Some(entry);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::audit::*;
+
+    #[test]
+    fn audit_chain_test() {
+        let mut entries = vec![];
+        let mut prev_hash = None;
+        for (i, operation) in ["apply", "recover", "clearvault"].iter().enumerate() {
+            let mut entry = AuditEntry::new("web", operation, Some("19".into()));
+            entry.audit_id = Some(i as i64 + 1);
+            entry.set_disguises(&["a".to_string()]);
+            entry.set_outcome(true, "done".into());
+            entry.seal(prev_hash);
+            prev_hash = entry.hash.clone();
+            entries.push(entry);
+        }
+        assert_eq!(entries[0].disguise_ids.as_deref(), Some("[\"a\"]"));
+        assert_eq!(verify_chain(&entries, true), None);
+        //the entries of one vault are checked without the others
+        assert_eq!(verify_chain(&entries[1..], false), None);
+        assert_eq!(verify_chain(&entries[1..], true), Some(2));
+
+        //the changed entry does not match its hash
+        let mut changed = entries.clone();
+        changed[1].row_count = Some(100);
+        assert_eq!(verify_chain(&changed, false), Some(2));
+        //the rehashed entry breaks the link of the next one
+        changed[1].hash = Some(changed[1].compute_hash());
+        assert_eq!(verify_chain(&changed, false), Some(3));
+        //the deleted entry breaks the link of the next one in the whole log
+        let mut deleted = entries.clone();
+        deleted.remove(1);
+        assert_eq!(verify_chain(&deleted, true), Some(3));
+    }
+}
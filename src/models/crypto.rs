@@ -0,0 +1,439 @@
+use std::{env, fs, io};
+use std::path::PathBuf;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::digest::{digest, SHA256};
+use ring::hkdf::{Salt, HKDF_SHA256};
+use ring::pbkdf2::{derive, PBKDF2_HMAC_SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+use rsa::pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey};
+use rsa::{PaddingScheme, PublicKey, RsaPrivateKey, RsaPublicKey};
+use sha2::Sha256;
+use crate::error::MyError;
+use crate::models::vault::Vault;
+
+/// the prefix of the encrypted values stored in the vault
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+/// the salt to derive the keys of the vaults from the master key
+const VAULT_KEY_SALT: &[u8] = b"disguise-vault-key";
+/// the prefix of the data keys wrapped by the recovery keys
+const RECOVERY_PREFIX: &str = "rsa-oaep:";
+/// the size of the recovery keys
+const RECOVERY_KEY_BITS: usize = 2048;
+/// the iterations of pbkdf2 to derive the key from the recovery secret
+const SECRET_ITERATIONS: u32 = 100_000;
+/// the shortest recovery secret chosen by the user
+pub const MIN_SECRET_LENGTH: usize = 12;
+
+/// the server's master key which all the vaults' keys are derived from
+/// it is never written into the databases
+#[derive(Clone)]
+pub struct MasterKey {
+    key: [u8; 32]
+}
+/// the key of one vault, or the data key of one disguise,
+/// to encrypt by aes-256-gcm, so the changed ciphertext is refused
+/// the vault's key only wraps the data keys of its disguises,
+/// and the original rows are encrypted by the data keys
+#[derive(Clone)]
+pub struct VaultKey {
+    key: [u8; 32]
+}
+
+impl MasterKey {
+    /// read the master key from its base64 encoding
+    pub fn from_base64(encoded: &str) -> Result<MasterKey, MyError> {
+        let bytes = base64::decode(encoded.trim())
+            .map_err(|_err| MyError::InvalidInput("The master key should be encoded by base64.".into()))?;
+        let key: [u8; 32] = bytes.try_into()
+            .map_err(|_err| MyError::InvalidInput("The master key should have 32 bytes.".into()))?;
+        Ok(MasterKey { key })
+    }
+
+    ///
+    /// load the master key from the env variable "VAULT_MASTER_KEY",
+    /// or from the file of the env variable "VAULT_MASTER_KEY_FILE"
+    /// the key is 32 random bytes encoded by base64, like "openssl rand -base64 32"
+    ///
+    /// returns: Result<Option<MasterKey>, MyError>
+    ///
+    pub fn load() -> Result<Option<MasterKey>, MyError> {
+        if let Ok(encoded) = env::var("VAULT_MASTER_KEY") {
+            return MasterKey::from_base64(encoded.as_str()).map(Some);
+        }
+        if let Ok(path) = env::var("VAULT_MASTER_KEY_FILE") {
+            let encoded = fs::read_to_string(path.as_str())
+                .map_err(|err| MyError::OperationError(format!("The master key file could not be read: {}", err)))?;
+            return MasterKey::from_base64(encoded.as_str()).map(Some);
+        }
+        Ok(None)
+    }
+
+    /// derive the key of the vault by hkdf-sha256 with the vault id
+    pub fn vault_key(&self, vault_id: &str) -> VaultKey {
+        let mut key = [0u8; 32];
+        let info = [b"vault:".as_slice(), vault_id.as_bytes()];
+        Salt::new(HKDF_SHA256, VAULT_KEY_SALT)
+            .extract(&self.key)
+            .expand(&info, &AES_256_GCM)
+            .and_then(|okm| okm.fill(&mut key))
+            .unwrap();
+        VaultKey { key }
+    }
+}
+
+impl VaultKey {
+    /// get the key of aes-256-gcm
+    fn cipher(&self) -> LessSafeKey {
+        LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &self.key).unwrap())
+    }
+
+    /// encrypt the value with a random nonce,
+    /// which is stored as "enc:v1:" and the base64 of the nonce, the ciphertext and the tag
+    pub fn encrypt(&self, plaintext: &str) -> String {
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new().fill(&mut nonce).unwrap();
+        let mut sealed = plaintext.as_bytes().to_vec();
+        self.cipher()
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut sealed)
+            .unwrap();
+        let mut stored = nonce.to_vec();
+        stored.append(&mut sealed);
+        ENCRYPTED_PREFIX.to_string() + base64::encode(stored).as_str()
+    }
+
+    /// decrypt the value stored by "encrypt"
+    pub fn decrypt(&self, stored: &str) -> Result<String, MyError> {
+        let invalid = || MyError::OperationError("The encrypted value in the vault could not be decrypted.".into());
+        let encoded = stored.strip_prefix(ENCRYPTED_PREFIX).ok_or_else(invalid)?;
+        let mut bytes = base64::decode(encoded).map_err(|_err| invalid())?;
+        if bytes.len() < NONCE_LEN {
+            return Err(invalid());
+        }
+        let mut sealed = bytes.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&bytes).map_err(|_err| invalid())?;
+        let plaintext = self.cipher()
+            .open_in_place(nonce, Aad::empty(), &mut sealed)
+            .map_err(|_err| invalid())?;
+        String::from_utf8(plaintext.to_vec()).map_err(|_err| invalid())
+    }
+}
+
+/// the recovery key pair of the vault stored in the vault table
+/// the public key wraps the data keys of the vault's disguises,
+/// and the private key is encrypted by the key derived from the user's secret,
+/// so the disguises could not be decrypted without the user
+#[derive(Debug, Clone)]
+pub struct SealedRecoveryKey {
+    pub public_key: String,
+    pub private_key: String,
+    pub salt: String
+}
+/// the private recovery key opened by the user's secret
+pub struct RecoveryKey {
+    private_key: RsaPrivateKey
+}
+/// the keys to wrap or unwrap the data keys of one vault
+/// the data key is wrapped by the recovery key first, and then by the vault's key
+/// the wrapped data keys are kept in the key store if there is one
+pub struct VaultKeys {
+    pub vault_key: Option<VaultKey>,
+    pub recovery_public_key: Option<String>,
+    pub recovery_key: Option<RecoveryKey>,
+    pub key_store: Option<KeyStore>
+}
+/// the local directory of the wrapped data keys outside the vault database,
+/// so the data key destroyed from the store could not be found in the database's backups
+/// the directory should never be backed up with the database
+#[derive(Clone)]
+pub struct KeyStore {
+    dir: PathBuf
+}
+
+impl SealedRecoveryKey {
+    ///
+    /// generate the recovery key pair of the vault,
+    /// the private key is sealed by the key derived from the secret
+    /// it takes a while, so it should not run on the async workers
+    ///
+    /// # Arguments
+    ///
+    /// * `secret`: the user's recovery secret
+    ///
+    /// returns: Result<SealedRecoveryKey, MyError>
+    ///
+    pub fn generate(secret: &str) -> Result<SealedRecoveryKey, MyError> {
+        let failed = || MyError::OperationError("The recovery key could not be generated.".into());
+        let private_key = RsaPrivateKey::new(&mut rand::rngs::OsRng, RECOVERY_KEY_BITS).map_err(|_err| failed())?;
+        let public_key = RsaPublicKey::from(&private_key).to_public_key_der().map_err(|_err| failed())?;
+        let private_key = private_key.to_pkcs8_der().map_err(|_err| failed())?;
+        let mut salt = [0u8; 16];
+        SystemRandom::new().fill(&mut salt).unwrap();
+        Ok(SealedRecoveryKey {
+            public_key: base64::encode(public_key.as_ref()),
+            private_key: secret_key(secret, &salt).encrypt(base64::encode(private_key.as_ref()).as_str()),
+            salt: base64::encode(salt)
+        })
+    }
+
+    /// open the private recovery key by the user's secret
+    pub fn open(&self, secret: &str) -> Result<RecoveryKey, MyError> {
+        let salt = base64::decode(self.salt.as_str())
+            .map_err(|_err| MyError::OperationError("The recovery key in the vault is not correct.".into()))?;
+        let encoded = secret_key(secret, &salt).decrypt(self.private_key.as_str())
+            .map_err(|_err| MyError::InvalidInput("The recovery secret is not correct.".into()))?;
+        let private_key = base64::decode(encoded).ok()
+            .and_then(|der| RsaPrivateKey::from_pkcs8_der(&der).ok())
+            .ok_or_else(|| MyError::OperationError("The recovery key in the vault is not correct.".into()))?;
+        Ok(RecoveryKey { private_key })
+    }
+}
+
+impl VaultKeys {
+    /// get the keys to wrap the data keys of the vault's new disguises
+    pub fn sealing(master_key: Option<&MasterKey>, key_store: Option<&KeyStore>, vault: &Vault) -> VaultKeys {
+        VaultKeys {
+            vault_key: master_key.map(|master_key| master_key.vault_key(vault.vault_id.as_ref().unwrap())),
+            recovery_public_key: vault.recovery_key().map(|recovery_key| recovery_key.public_key),
+            recovery_key: None,
+            key_store: key_store.cloned()
+        }
+    }
+
+    /// get the keys to unwrap the data keys of the vault's disguises
+    /// the secret is required if the vault has the recovery key
+    pub fn opening(
+        master_key: Option<&MasterKey>,
+        key_store: Option<&KeyStore>,
+        vault: &Vault,
+        secret: Option<&str>,
+    ) -> Result<VaultKeys, MyError> {
+        let recovery_key = match vault.recovery_key() {
+            Some(recovery_key) => {
+                let secret = secret
+                    .ok_or_else(|| MyError::InvalidInput("The recovery secret of the vault is required.".into()))?;
+                Some(recovery_key.open(secret)?)
+            }
+            None => None,
+        };
+        Ok(VaultKeys {
+            vault_key: master_key.map(|master_key| master_key.vault_key(vault.vault_id.as_ref().unwrap())),
+            recovery_public_key: None,
+            recovery_key,
+            key_store: key_store.cloned()
+        })
+    }
+
+    /// generate a new random data key for one disguise,
+    /// which is returned with its wrapped form,
+    /// there is no data key if there is no key to wrap it
+    pub fn new_data_key(&self) -> Result<Option<(VaultKey, String)>, MyError> {
+        if self.vault_key.is_none() && self.recovery_public_key.is_none() {
+            return Ok(None);
+        }
+        let mut key = [0u8; 32];
+        SystemRandom::new().fill(&mut key).unwrap();
+        let mut wrapped = base64::encode(key);
+        if let Some(public_key) = &self.recovery_public_key {
+            let public_key = base64::decode(public_key).ok()
+                .and_then(|der| RsaPublicKey::from_public_key_der(&der).ok())
+                .ok_or_else(|| MyError::OperationError("The recovery key in the vault is not correct.".into()))?;
+            let sealed = public_key.encrypt(&mut rand::rngs::OsRng, PaddingScheme::new_oaep::<Sha256>(), &key)
+                .map_err(|_err| MyError::OperationError("The data key could not be wrapped.".into()))?;
+            wrapped = RECOVERY_PREFIX.to_string() + base64::encode(sealed).as_str();
+        }
+        if let Some(vault_key) = &self.vault_key {
+            wrapped = vault_key.encrypt(wrapped.as_str());
+        }
+        Ok(Some((VaultKey { key }, wrapped)))
+    }
+
+    /// unwrap the data key of one disguise
+    pub fn open_data_key(&self, wrapped: &str) -> Result<VaultKey, MyError> {
+        let invalid = || MyError::OperationError("The data key in the vault is not correct.".into());
+        let mut wrapped = open_stored(wrapped, self.vault_key.as_ref())?;
+        if let Some(sealed) = wrapped.strip_prefix(RECOVERY_PREFIX) {
+            let recovery_key = self.recovery_key.as_ref()
+                .ok_or_else(|| MyError::InvalidInput("The recovery secret of the vault is required.".into()))?;
+            let sealed = base64::decode(sealed).map_err(|_err| invalid())?;
+            let key = recovery_key.private_key.decrypt(PaddingScheme::new_oaep::<Sha256>(), &sealed)
+                .map_err(|_err| invalid())?;
+            wrapped = base64::encode(key);
+        }
+        let key: [u8; 32] = base64::decode(wrapped).map_err(|_err| invalid())?
+            .try_into()
+            .map_err(|_err| invalid())?;
+        Ok(VaultKey { key })
+    }
+}
+
+impl KeyStore {
+    ///
+    /// load the key store from the directory of the env variable "VAULT_KEY_STORE"
+    /// the data keys are kept in the vault database without it
+    ///
+    /// returns: Result<Option<KeyStore>, MyError>
+    ///
+    pub fn load() -> Result<Option<KeyStore>, MyError> {
+        match env::var("VAULT_KEY_STORE") {
+            Ok(dir) => KeyStore::open(dir).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// open the key store in the directory, which is created if it does not exist
+    pub fn open(dir: impl Into<PathBuf>) -> Result<KeyStore, MyError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .map_err(|err| MyError::OperationError(format!("The key store could not be opened: {}", err)))?;
+        Ok(KeyStore { dir })
+    }
+
+    /// get the file of the disguise's data key
+    fn path(&self, disguise_id: i32) -> PathBuf {
+        self.dir.join(format!("{}.key", disguise_id))
+    }
+
+    /// keep the wrapped data key of the disguise
+    pub fn put(&self, disguise_id: i32, wrapped: &str) -> Result<(), MyError> {
+        fs::write(self.path(disguise_id), wrapped)
+            .map_err(|err| MyError::OperationError(format!("The data key could not be stored: {}", err)))
+    }
+
+    /// get the wrapped data key of the disguise, there is none after it is destroyed
+    pub fn get(&self, disguise_id: i32) -> Result<Option<String>, MyError> {
+        match fs::read_to_string(self.path(disguise_id)) {
+            Ok(wrapped) => Ok(Some(wrapped)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(MyError::OperationError(format!("The data key could not be read: {}", err))),
+        }
+    }
+
+    /// destroy the wrapped data key of the disguise
+    pub fn destroy(&self, disguise_id: i32) -> Result<(), MyError> {
+        match fs::remove_file(self.path(disguise_id)) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(MyError::OperationError(format!("The data key could not be destroyed: {}", err))),
+        }
+    }
+}
+
+/// derive the key from the user's recovery secret by pbkdf2
+fn secret_key(secret: &str, salt: &[u8]) -> VaultKey {
+    let mut key = [0u8; 32];
+    let iterations = std::num::NonZeroU32::new(SECRET_ITERATIONS).unwrap();
+    derive(PBKDF2_HMAC_SHA256, iterations, salt, secret.as_bytes(), &mut key);
+    VaultKey { key }
+}
+
+/// generate a random recovery secret for the user
+pub fn new_secret() -> String {
+    let mut secret = [0u8; 24];
+    SystemRandom::new().fill(&mut secret).unwrap();
+    base64::encode_config(secret, base64::URL_SAFE_NO_PAD)
+}
+
+/// get the sha-256 of the wrapped data key in hex,
+/// which identifies the destroyed key without revealing it
+pub fn fingerprint(wrapped: &str) -> String {
+    digest(&SHA256, wrapped.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// check if the value stored in the vault is encrypted
+pub fn is_encrypted(stored: &str) -> bool {
+    stored.starts_with(ENCRYPTED_PREFIX)
+}
+
+///
+/// read the value stored in the vault,
+/// the encrypted one is decrypted by the disguise's data key
+/// and the one stored before the encryption is kept as it is
+///
+/// # Arguments
+///
+/// * `stored`: the value stored in the vault
+/// * `key`: the data key of the disguise
+///
+/// returns: Result<String, MyError>
+///
+pub fn open_stored(stored: &str, key: Option<&VaultKey>) -> Result<String, MyError> {
+    if !is_encrypted(stored) {
+        return Ok(stored.to_string());
+    }
+    key.ok_or_else(|| MyError::OperationError("The vault key is required to decrypt the disguise.".into()))?
+        .decrypt(stored)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::crypto::*;
+
+    #[test]
+    fn vault_key_test() {
+        let master = MasterKey::from_base64("MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=").unwrap();
+        let key = master.vault_key("1");
+        let stored = key.encrypt("{\"primary_key_index\":0}");
+        assert!(is_encrypted(stored.as_str()));
+        assert_ne!(stored, key.encrypt("{\"primary_key_index\":0}"));
+        assert_eq!(key.decrypt(stored.as_str()).unwrap(), "{\"primary_key_index\":0}");
+        //the key of another vault or the changed ciphertext is refused
+        assert!(master.vault_key("2").decrypt(stored.as_str()).is_err());
+        let mut changed = stored.clone().into_bytes();
+        let last = changed.len() - 3;
+        changed[last] = if changed[last] == b'A' { b'B' } else { b'A' };
+        assert!(key.decrypt(String::from_utf8(changed).unwrap().as_str()).is_err());
+        //the values stored before the encryption are still readable
+        assert_eq!(open_stored("19, \"Alice\"", None).unwrap(), "19, \"Alice\"");
+        assert!(open_stored(stored.as_str(), None).is_err());
+        assert!(MasterKey::from_base64("c2hvcnQ=").is_err());
+    }
+
+    #[test]
+    fn data_key_test() {
+        let master = MasterKey::from_base64("MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=").unwrap();
+        let keys = VaultKeys { vault_key: Some(master.vault_key("1")), recovery_public_key: None, recovery_key: None, key_store: None };
+        let (data_key, wrapped) = keys.new_data_key().unwrap().unwrap();
+        let stored = data_key.encrypt("19, \"Alice\"");
+        //the original is only readable with the data key
+        assert!(master.vault_key("1").decrypt(stored.as_str()).is_err());
+        let opened = keys.open_data_key(wrapped.as_str()).unwrap();
+        assert_eq!(opened.decrypt(stored.as_str()).unwrap(), "19, \"Alice\"");
+        let other = VaultKeys { vault_key: Some(master.vault_key("2")), recovery_public_key: None, recovery_key: None, key_store: None };
+        assert!(other.open_data_key(wrapped.as_str()).is_err());
+        assert_eq!(fingerprint(wrapped.as_str()).len(), 64);
+        assert_eq!(fingerprint(wrapped.as_str()), fingerprint(wrapped.as_str()));
+    }
+
+    #[test]
+    fn recovery_key_test() {
+        let secret = new_secret();
+        let sealed = SealedRecoveryKey::generate(secret.as_str()).unwrap();
+        let keys = VaultKeys { vault_key: None, recovery_public_key: Some(sealed.public_key.clone()), recovery_key: None, key_store: None };
+        let (data_key, wrapped) = keys.new_data_key().unwrap().unwrap();
+        let stored = data_key.encrypt("19, \"Alice\"");
+        //the data key could not be opened without the secret
+        assert!(keys.open_data_key(wrapped.as_str()).is_err());
+        assert!(sealed.open("not the secret").is_err());
+        let keys = VaultKeys { vault_key: None, recovery_public_key: None, recovery_key: Some(sealed.open(secret.as_str()).unwrap()), key_store: None };
+        let opened = keys.open_data_key(wrapped.as_str()).unwrap();
+        assert_eq!(opened.decrypt(stored.as_str()).unwrap(), "19, \"Alice\"");
+    }
+
+    #[test]
+    fn key_store_test() {
+        let dir = env::temp_dir().join(format!("key_store_test_{}", std::process::id()));
+        let store = KeyStore::open(&dir).unwrap();
+        assert_eq!(store.get(1).unwrap(), None);
+        store.put(1, "wrapped").unwrap();
+        assert_eq!(store.get(1).unwrap(), Some("wrapped".to_string()));
+        //the destroyed key could not be found again
+        store.destroy(1).unwrap();
+        assert_eq!(store.get(1).unwrap(), None);
+        assert!(store.destroy(1).is_ok());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
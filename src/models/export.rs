@@ -0,0 +1,189 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use crate::models::target::Target;
+use crate::models::vault::{DisguiseSummary, VaultInfo};
+
+/// the vault to export, found by its id or its owner's email
+#[derive(Deserialize, Debug, Clone)]
+pub struct ExportQuery {
+    pub vault_id: Option<String>,
+    pub email: Option<String>
+}
+/// all the original rows kept in the vault
+/// with the metadata of the disguises which changed them
+#[derive(Serialize, Debug, Clone)]
+pub struct VaultExport {
+    pub vault: Option<VaultInfo>,
+    pub time: Option<String>,
+    pub disguises: Option<Vec<DisguiseSummary>>,
+    pub tables: Option<Vec<ExportedTable>>,
+    /// the number of the functions whose original rows were not kept
+    pub unavailable: Option<u64>
+}
+/// the original rows of one table
+/// the columns are in the order they are first seen
+#[derive(Serialize, Debug, Clone)]
+pub struct ExportedTable {
+    pub table_name: Option<String>,
+    pub columns: Option<Vec<ExportedColumn>>,
+    pub rows: Option<Vec<ExportedRow>>
+}
+/// the column of the exported table with its mysql type
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ExportedColumn {
+    pub name: Option<String>,
+    pub column_type: Option<String>
+}
+/// one original row and the disguise which changed it
+#[derive(Serialize, Debug, Clone)]
+pub struct ExportedRow {
+    pub disguise_id: Option<String>,
+    pub function_type: Option<String>,
+    pub values: Option<Map<String, Value>>
+}
+
+impl VaultExport {
+    /// create the export of the vault without rows
+    pub fn new(vault: VaultInfo, time: String) -> Self {
+        VaultExport {
+            vault: Some(vault),
+            time: Some(time),
+            disguises: Some(vec![]),
+            tables: Some(vec![]),
+            unavailable: Some(0)
+        }
+    }
+
+    ///
+    /// add the original row into the table of the export
+    /// the table and its new columns are added when they are first seen
+    ///
+    /// # Arguments
+    ///
+    /// * `table_name`: the table of the row
+    /// * `disguise_id`: the public id of the disguise
+    /// * `function_type`: the type of the function which changed the row
+    /// * `original`: the original row
+    ///
+    /// returns: ()
+    ///
+    pub fn add_row(&mut self, table_name: &str, disguise_id: Option<String>, function_type: Option<String>, original: &Target) {
+        let tables = self.tables.get_or_insert_with(Vec::new);
+        let index = match tables.iter().position(|table| table.table_name.as_deref() == Some(table_name)) {
+            Some(index) => index,
+            None => {
+                tables.push(ExportedTable {
+                    table_name: Some(table_name.to_string()),
+                    columns: Some(vec![]),
+                    rows: Some(vec![])
+                });
+                tables.len() - 1
+            }
+        };
+        let table = &mut tables[index];
+        let columns = table.columns.get_or_insert_with(Vec::new);
+        for field in original.fields.as_ref().unwrap() {
+            if !columns.iter().any(|column| column.name == field.field_name) {
+                columns.push(ExportedColumn {
+                    name: field.field_name.clone(),
+                    column_type: field.field_type.clone()
+                });
+            }
+        }
+        table.rows.get_or_insert_with(Vec::new).push(ExportedRow {
+            disguise_id,
+            function_type,
+            values: Some(original.to_map())
+        });
+    }
+}
+
+impl ExportedTable {
+    /// get the rows as csv, with the disguise and the function before the columns
+    /// the null value is written as the empty field
+    pub fn to_csv(&self) -> String {
+        let columns = self.columns.as_ref().unwrap();
+        let mut header = vec!["disguise_id".to_string(), "function_type".to_string()];
+        header.extend(columns.iter().map(|column| column.name.clone().unwrap_or_default()));
+        let mut lines = vec![csv_line(&header)];
+        for row in self.rows.as_ref().unwrap() {
+            let values = row.values.as_ref().unwrap();
+            let mut line = vec![row.disguise_id.clone().unwrap_or_default(), row.function_type.clone().unwrap_or_default()];
+            for column in columns {
+                let value = column.name.as_ref().and_then(|name| values.get(name));
+                line.push(match value {
+                    None | Some(Value::Null) => String::new(),
+                    Some(Value::String(value)) => value.clone(),
+                    Some(value) => value.to_string(),
+                });
+            }
+            lines.push(csv_line(&line));
+        }
+        lines.join("\r\n") + "\r\n"
+    }
+}
+
+/// join the fields by commas, quoting the ones with the special characters
+fn csv_line(fields: &[String]) -> String {
+    fields.iter()
+        .map(|field| {
+            if field.contains([',', '"', '\r', '\n']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.clone()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::models::export::VaultExport;
+    use crate::models::target::{Field, FieldValue, Target};
+    use crate::models::vault::VaultInfo;
+
+    fn field(name: &str, field_type: &str, value: FieldValue) -> Field {
+        Field {
+            field_name: Some(name.into()),
+            field_type: Some(field_type.into()),
+            field_value: Some(value)
+        }
+    }
+
+    #[test]
+    fn export_csv_test() {
+        let vault = VaultInfo { vault_id: Some("1".into()), email: None, placeholder_info: None, secret_required: None };
+        let mut export = VaultExport::new(vault, "2022-07-14".into());
+        let review = Target {
+            primary_key_index: Some(0),
+            fields: Some(vec![
+                field("review_id", "int", FieldValue::Int(7)),
+                field("content", "text", FieldValue::Text("good, \"really\"".into())),
+            ])
+        };
+        let contact = Target {
+            primary_key_index: Some(0),
+            fields: Some(vec![
+                field("contact_id", "int", FieldValue::Int(19)),
+                field("phone", "varchar", FieldValue::Null),
+            ])
+        };
+        export.add_row("review", Some("a".into()), Some("decorrelation".into()), &review);
+        export.add_row("contact_info", Some("a".into()), Some("removal".into()), &contact);
+        export.add_row("review", Some("b".into()), Some("modification".into()), &review);
+
+        let tables = export.tables.as_ref().unwrap();
+        assert_eq!(tables.len(), 2);
+        assert_eq!(tables[0].rows.as_ref().unwrap().len(), 2);
+        assert_eq!(tables[0].rows.as_ref().unwrap()[0].values.as_ref().unwrap()["review_id"], json!(7));
+        assert_eq!(
+            tables[0].to_csv(),
+            "disguise_id,function_type,review_id,content\r\n\
+            a,decorrelation,7,\"good, \"\"really\"\"\"\r\n\
+            b,modification,7,\"good, \"\"really\"\"\"\r\n"
+        );
+        assert_eq!(tables[1].to_csv(), "disguise_id,function_type,contact_id,phone\r\na,removal,19,\r\n");
+    }
+}
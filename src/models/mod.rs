@@ -2,4 +2,12 @@ pub mod requirement;
 pub mod transformation;
 pub mod placeholder;
 pub mod vault;
-pub mod target;
\ No newline at end of file
+pub mod target;
+pub mod predicate;
+pub mod specification;
+pub mod schema;
+pub mod export;
+pub mod crypto;
+pub mod api_key;
+pub mod audit;
+pub mod recovery;
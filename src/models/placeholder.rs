@@ -1,27 +1,73 @@
 use actix_web::web::Json;
 use sqlx::FromRow;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use crate::error::MyError;
+use crate::models::predicate::quote_identifier;
 /// the location of the placeholder stored in database
-#[derive(Deserialize, Serialize, Debug, Clone, FromRow)]
+/// the template generates a new placeholder for every decorrelated row
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct PlaceholderInfo {
     pub pred: Option<String>,
     pub table: Option<String>,
+    pub template: Option<GeneratePlaceHolder>,
 }
 impl PlaceholderInfo {
     pub fn new(generate_placeholder: GeneratePlaceHolder, id: &str) -> PlaceholderInfo {
         PlaceholderInfo {
-            pred: (generate_placeholder.primary_key_name.unwrap() + "=" + id).into(),
-            table: generate_placeholder.table,
+            pred: (generate_placeholder.primary_key_name.clone().unwrap() + "=" + id).into(),
+            table: generate_placeholder.table.clone(),
+            template: Some(generate_placeholder),
         }
     }
 }
+/// the placeholder generated for one decorrelated row
+/// which is stored in the vault and removed on recovery
+#[derive(Deserialize, Serialize, Debug, Clone, FromRow)]
+pub struct PlaceholderMapping {
+    pub table_name: Option<String>,
+    pub predicate: Option<String>,
+    pub placeholder_table: Option<String>,
+    pub placeholder_predicate: Option<String>,
+}
+/// the placeholder owned by the vault
+/// the shared placeholder of the vault has no disguise id,
+/// the others are generated for the decorrelated rows of the disguise
+#[derive(Deserialize, Serialize, Debug, Clone, FromRow)]
+pub struct OwnedPlaceholder {
+    pub vault_id: Option<String>,
+    pub disguise_id: Option<i32>,
+    pub placeholder_table: Option<String>,
+    pub placeholder_predicate: Option<String>,
+    pub time: Option<String>,
+}
+/// the references to the owned placeholder
+/// the status is "in_use", "reserved" for the vault, "orphaned" or "missing"
+#[derive(Serialize, Debug, Clone)]
+pub struct PlaceholderReport {
+    pub placeholder: Option<OwnedPlaceholder>,
+    pub references: Option<i64>,
+    pub status: Option<String>,
+    pub removed: Option<bool>,
+}
+/// the report of the placeholders returned to the web app or the user
+#[derive(Serialize, Debug, Clone)]
+pub struct PlaceholderCollection {
+    pub placeholders: Option<Vec<PlaceholderReport>>,
+    pub removed: Option<usize>,
+    pub message: Option<String>,
+}
 /// the input placeholder information from user or web
-#[derive(Deserialize, Debug, Clone)]
+/// the values of the "columns" are generated for every placeholder,
+/// otherwise the literal "fields" and "field_values" are inserted
+/// with the bound values when the server allows the raw sql
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct GeneratePlaceHolder {
     pub table: Option<String>,
     pub primary_key_name: Option<String>,
     pub fields: Option<String>,
     pub field_values: Option<String>,
+    pub columns: Option<Vec<PlaceholderColumn>>,
 }
 impl From<Json<GeneratePlaceHolder>> for GeneratePlaceHolder {
     fn from(placeholder_json: Json<GeneratePlaceHolder>) -> Self {
@@ -30,6 +76,268 @@ impl From<Json<GeneratePlaceHolder>> for GeneratePlaceHolder {
             primary_key_name: placeholder_json.primary_key_name.clone(),
             fields: placeholder_json.fields.clone(),
             field_values: placeholder_json.field_values.clone(),
+            columns: placeholder_json.columns.clone(),
+        }
+    }
+}
+impl GeneratePlaceHolder {
+    /// if the placeholder copies the values of the original owner
+    pub fn copies_owner(&self) -> bool {
+        self.columns.as_ref().is_some_and(|columns| columns.iter().any(|column| {
+            matches!(column.generator, Some(Generator::Copy { .. }))
+        }))
+    }
+    ///
+    /// get the quoted names of the literal "fields"
+    /// and the values of the literal "field_values" to bind
+    ///
+    /// returns: Result<(Vec<String>, Vec<Value>), MyError>
+    ///
+    pub fn literal_values(&self) -> Result<(Vec<String>, Vec<Value>), MyError> {
+        let fields = self.fields.as_deref()
+            .ok_or_else(|| MyError::InvalidInput("The fields of the placeholder are required.".into()))?;
+        let field_values = self.field_values.as_deref()
+            .ok_or_else(|| MyError::InvalidInput("The field values of the placeholder are required.".into()))?;
+        let names = fields.split(',')
+            .map(|field| quote_identifier(field.trim()))
+            .collect::<Result<Vec<String>, MyError>>()?;
+        let values = parse_literals(field_values)?;
+        if names.len() != values.len() {
+            return Err(MyError::InvalidInput("The fields and the field values of the placeholder are not matched.".into()));
+        }
+        Ok((names, values))
+    }
+}
+
+///
+/// parse the sql literals seperated by ","
+/// which are the quoted texts, the numbers, the booleans and null
+///
+/// # Arguments
+///
+/// * `literals`: the literals of the placeholder's values
+///
+/// returns: Result<Vec<Value, Global>, MyError>
+///
+pub fn parse_literals(literals: &str) -> Result<Vec<Value>, MyError> {
+    let invalid = || MyError::InvalidInput(format!("The field values \"{}\" are not valid literals.", literals));
+    let mut values = vec![];
+    let mut chars = literals.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let value = match chars.peek() {
+            //the quoted text with the escaped or doubled quotes
+            Some(&quote) if quote == '\'' || quote == '"' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next().ok_or_else(invalid)? {
+                        '\\' => match chars.next().ok_or_else(invalid)? {
+                            'n' => text.push('\n'),
+                            'r' => text.push('\r'),
+                            't' => text.push('\t'),
+                            '0' => text.push('\0'),
+                            c => text.push(c),
+                        },
+                        c if c == quote => match chars.next_if_eq(&quote) {
+                            Some(c) => text.push(c),
+                            None => break,
+                        },
+                        c => text.push(c),
+                    }
+                }
+                Value::from(text)
+            }
+            Some(_) => {
+                let mut word = String::new();
+                while let Some(c) = chars.next_if(|c| *c != ',') {
+                    word.push(c);
+                }
+                let word = word.trim();
+                match word.to_lowercase().as_str() {
+                    "null" => Value::Null,
+                    "true" => Value::from(true),
+                    "false" => Value::from(false),
+                    _ => match (word.parse::<i64>(), word.parse::<f64>()) {
+                        (Ok(number), _) => Value::from(number),
+                        (_, Ok(number)) if number.is_finite() => Value::from(number),
+                        _ => return Err(invalid()),
+                    },
+                }
+            }
+            None => return Err(invalid()),
+        };
+        values.push(value);
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        match chars.next() {
+            Some(',') => continue,
+            None => break,
+            Some(_) => return Err(invalid()),
         }
     }
-}
\ No newline at end of file
+    Ok(values)
+}
+/// the column of the placeholder with the generator of its value
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PlaceholderColumn {
+    pub column: Option<String>,
+    pub generator: Option<Generator>,
+}
+/// the generator of the placeholder's value
+/// "copy" takes the column of the original owner of the decorrelated row,
+/// and "sequence" continues from the largest number in the column
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Generator {
+    RandomName,
+    RandomEmail { domain: Option<String> },
+    Uuid,
+    Constant { value: Value },
+    Null,
+    Copy { column: Option<String> },
+    Sequence { start: Option<i64> },
+}
+
+const FIRST_NAMES: [&str; 16] = [
+    "Alex", "Bea", "Chris", "Dana", "Eli", "Fay", "Gray", "Hana",
+    "Ivan", "Jo", "Kai", "Lee", "Mia", "Noa", "Omar", "Pat",
+];
+const LAST_NAMES: [&str; 16] = [
+    "Adams", "Brown", "Chen", "Diaz", "Evans", "Fischer", "Garcia", "Hill",
+    "Ito", "Jones", "Kim", "Lopez", "Miller", "Nguyen", "Okafor", "Smith",
+];
+
+impl Generator {
+    ///
+    /// generate the value which needs nothing from the database
+    /// the "sequence" is not generated here
+    ///
+    /// # Arguments
+    ///
+    /// * `column`: the name of the column
+    /// * `owner`: the values of the original owner
+    ///
+    /// returns: Value
+    ///
+    pub fn generate(&self, column: &str, owner: Option<&Map<String, Value>>) -> Value {
+        match self {
+            Generator::RandomName => {
+                let first = FIRST_NAMES[rand::random::<usize>() % FIRST_NAMES.len()];
+                let last = LAST_NAMES[rand::random::<usize>() % LAST_NAMES.len()];
+                Value::from(format!("{} {}", first, last))
+            }
+            Generator::RandomEmail { domain } => {
+                let first = FIRST_NAMES[rand::random::<usize>() % FIRST_NAMES.len()];
+                let last = LAST_NAMES[rand::random::<usize>() % LAST_NAMES.len()];
+                Value::from(format!(
+                    "{}.{}{:04}@{}",
+                    first.to_lowercase(),
+                    last.to_lowercase(),
+                    rand::random::<u16>() % 10000,
+                    domain.as_deref().unwrap_or("example.com")
+                ))
+            }
+            Generator::Uuid => Value::from(new_uuid()),
+            Generator::Constant { value } => value.clone(),
+            Generator::Null | Generator::Sequence { .. } => Value::Null,
+            Generator::Copy { column: from } => owner
+                .and_then(|owner| owner.get(from.as_deref().unwrap_or(column)))
+                .cloned()
+                .unwrap_or(Value::Null),
+        }
+    }
+
+    ///
+    /// get another value for the unique column
+    /// when the generated one has been used
+    ///
+    /// # Arguments
+    ///
+    /// * `column`: the name of the column
+    /// * `owner`: the values of the original owner
+    /// * `used`: the value which has been used
+    ///
+    /// returns: Option<Value>
+    ///
+    pub fn regenerate(&self, column: &str, owner: Option<&Map<String, Value>>, used: &Value) -> Option<Value> {
+        match self {
+            Generator::RandomName | Generator::RandomEmail { .. } | Generator::Uuid => {
+                let value = self.generate(column, owner);
+                //the random text is made unique by a random suffix
+                match (self, value.as_str()) {
+                    (Generator::RandomName, Some(name)) => Some(Value::from(format!("{} {:04x}", name, rand::random::<u16>()))),
+                    _ => Some(value),
+                }
+            }
+            //the fixed text is made unique by a random suffix
+            Generator::Constant { .. } | Generator::Copy { .. } => used.as_str()
+                .map(|text| Value::from(format!("{}_{:06x}", text, rand::random::<u32>() & 0xffffff))),
+            Generator::Null | Generator::Sequence { .. } => None,
+        }
+    }
+}
+
+/// generate a random uuid of version 4
+pub fn new_uuid() -> String {
+    let mut bytes: [u8; 16] = rand::random();
+    //version 4 and the variant of RFC 4122
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Map, Value};
+    use crate::models::placeholder::*;
+
+    #[test]
+    fn generator_test() {
+        let generators: Vec<Generator> = serde_json::from_value(json!([
+            {"kind": "random_email", "domain": "anon.org"},
+            {"kind": "constant", "value": "placeholder"},
+            {"kind": "copy", "column": "country"},
+            {"kind": "uuid"},
+            {"kind": "null"}
+        ])).unwrap();
+        let mut owner = Map::new();
+        owner.insert("country".to_string(), json!("NZ"));
+        assert!(generators[0].generate("email", None).as_str().unwrap().ends_with("@anon.org"));
+        assert_eq!(generators[1].generate("name", None), json!("placeholder"));
+        assert_eq!(generators[2].generate("region", Some(&owner)), json!("NZ"));
+        assert_eq!(generators[2].generate("region", None), Value::Null);
+        assert_eq!(generators[3].generate("token", None).as_str().unwrap().len(), 36);
+        assert_eq!(generators[4].generate("avatar", None), Value::Null);
+        //the used fixed text gets a suffix
+        let unique = generators[1].regenerate("name", None, &json!("placeholder")).unwrap();
+        assert!(unique.as_str().unwrap().starts_with("placeholder_"));
+        assert!(generators[4].regenerate("avatar", None, &Value::Null).is_none());
+    }
+
+    #[test]
+    fn literal_values_test() {
+        let placeholder = GeneratePlaceHolder {
+            table: Some("contact_info".into()),
+            primary_key_name: Some("contact_id".into()),
+            fields: Some("name, phone, is_anonymous, note".into()),
+            field_values: Some("'place''holder', \"777\", true, NULL".into()),
+            columns: None
+        };
+        let (names, values) = placeholder.literal_values().unwrap();
+        assert_eq!(names, vec!["`name`", "`phone`", "`is_anonymous`", "`note`"]);
+        assert_eq!(values, vec![json!("place'holder"), json!("777"), json!(true), Value::Null]);
+
+        assert_eq!(parse_literals("-3, 1.5,'a\\'b'").unwrap(), vec![json!(-3), json!(1.5), json!("a'b")]);
+        //the literals could not carry any other sql
+        assert!(parse_literals("1); DROP TABLE users; --").is_err());
+        assert!(parse_literals("'open").is_err());
+        assert!(parse_literals("'a' 'b'").is_err());
+        let injected = GeneratePlaceHolder {
+            fields: Some("name) VALUES ('x'); --".into()),
+            field_values: Some("'x'".into()),
+            ..placeholder
+        };
+        assert!(injected.literal_values().is_err());
+    }
+}
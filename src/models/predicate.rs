@@ -0,0 +1,310 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::error::MyError;
+
+/// the condition to select the targets in the web's database
+/// which could be a comparison on one column,
+/// or a group of predicates joined by "and" / "or",
+/// the raw sql string is only accepted when the transformation opts in
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Predicate {
+    And { and: Vec<Predicate> },
+    Or { or: Vec<Predicate> },
+    Condition {
+        column: String,
+        operator: String,
+        #[serde(default)]
+        value: Value,
+    },
+    Raw(String),
+}
+
+/// the changes of a modification
+/// which are the new values of the columns,
+/// the raw sql string is only accepted when the transformation opts in
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Changes {
+    Columns(Vec<Change>),
+    Raw(String),
+}
+
+/// the new value of one column
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Change {
+    pub column: String,
+    pub value: Value,
+}
+
+/// the rendered sql with "?" placeholders
+/// and the values to bind to them in order
+#[derive(Debug, Clone, PartialEq)]
+pub struct SqlFragment {
+    pub sql: String,
+    pub values: Vec<Value>,
+}
+
+impl Predicate {
+    /// get the predicate of "column = value"
+    pub fn eq(column: &str, value: Value) -> Predicate {
+        Predicate::Condition {
+            column: column.to_string(),
+            operator: "=".to_string(),
+            value,
+        }
+    }
+
+    /// get the predicate of "column IS NULL"
+    pub fn is_null(column: &str) -> Predicate {
+        Predicate::Condition {
+            column: column.to_string(),
+            operator: "IS NULL".to_string(),
+            value: Value::Null,
+        }
+    }
+
+    ///
+    /// render the predicate into the sql after "WHERE"
+    ///
+    /// # Arguments
+    ///
+    /// * `allow_raw`: if the raw sql string is accepted
+    ///
+    /// returns: Result<SqlFragment, MyError>
+    ///
+    pub fn render(&self, allow_raw: bool) -> Result<SqlFragment, MyError> {
+        match self {
+            Predicate::And { and } => render_group(and, " AND ", allow_raw),
+            Predicate::Or { or } => render_group(or, " OR ", allow_raw),
+            Predicate::Condition { column, operator, value } => {
+                let column = quote_identifier(column)?;
+                let operator = operator.trim().to_uppercase();
+                match operator.as_str() {
+                    "=" | "!=" | "<>" | "<" | "<=" | ">" | ">=" | "LIKE" | "NOT LIKE" => {
+                        let binary = value.is_object() && decode_binary(value).is_some();
+                        if value.is_null() || value.is_array() || (value.is_object() && !binary) {
+                            return Err(MyError::InvalidInput(
+                                format!("The value of \"{}\" should be a single value.", operator)
+                            ));
+                        }
+                        Ok(SqlFragment {
+                            sql: column + " " + operator.as_str() + " ?",
+                            values: vec![value.clone()],
+                        })
+                    }
+                    "IN" | "NOT IN" => {
+                        let values = match value.as_array() {
+                            Some(values) if !values.is_empty() => values.clone(),
+                            _ => return Err(MyError::InvalidInput(
+                                format!("The value of \"{}\" should be a non-empty list.", operator)
+                            )),
+                        };
+                        let placeholders = vec!["?"; values.len()].join(", ");
+                        Ok(SqlFragment {
+                            sql: column + " " + operator.as_str() + " (" + placeholders.as_str() + ")",
+                            values,
+                        })
+                    }
+                    "IS NULL" | "IS NOT NULL" => Ok(SqlFragment {
+                        sql: column + " " + operator.as_str(),
+                        values: vec![],
+                    }),
+                    _ => Err(MyError::InvalidInput(
+                        format!("The operator \"{}\" is not supported.", operator)
+                    )),
+                }
+            }
+            Predicate::Raw(sql) => {
+                if !allow_raw {
+                    return Err(MyError::InvalidInput(
+                        "The raw sql predicate is only accepted with \"raw_sql\".".to_string()
+                    ));
+                }
+                Ok(SqlFragment {
+                    sql: "(".to_string() + sql + ")",
+                    values: vec![],
+                })
+            }
+        }
+    }
+
+    ///
+    /// read the predicate stored in the vault
+    /// which is the json of the predicate,
+    /// or the old format of "column=value"
+    ///
+    /// # Arguments
+    ///
+    /// * `predicate`: the predicate stored in the vault
+    ///
+    /// returns: Result<Predicate, MyError>
+    ///
+    pub fn from_vault(predicate: &str) -> Result<Predicate, MyError> {
+        if let Ok(predicate @ (Predicate::And { .. } | Predicate::Or { .. } | Predicate::Condition { .. }))
+            = serde_json::from_str::<Predicate>(predicate) {
+            return Ok(predicate);
+        }
+        //the old format of "column=value"
+        let (column, value) = predicate.split_once('=').ok_or_else(|| MyError::OperationError(
+            "The predicate in the vault is not correct.".to_string()
+        ))?;
+        let column = column.trim();
+        quote_identifier(column)?;
+        let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+        let value = match value.parse::<i64>() {
+            Ok(number) => Value::from(number),
+            Err(_) => Value::from(value),
+        };
+        Ok(Predicate::eq(column, value))
+    }
+
+    /// get the json of the predicate to store in the vault
+    pub fn to_vault(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+impl Changes {
+    ///
+    /// render the changes into the sql after "SET"
+    ///
+    /// # Arguments
+    ///
+    /// * `allow_raw`: if the raw sql string is accepted
+    ///
+    /// returns: Result<SqlFragment, MyError>
+    ///
+    pub fn render(&self, allow_raw: bool) -> Result<SqlFragment, MyError> {
+        match self {
+            Changes::Columns(changes) => {
+                if changes.is_empty() {
+                    return Err(MyError::InvalidInput("The changes should not be empty.".to_string()));
+                }
+                let mut sql = vec![];
+                let mut values = vec![];
+                for change in changes {
+                    sql.push(quote_identifier(&change.column)? + " = ?");
+                    values.push(change.value.clone());
+                }
+                Ok(SqlFragment { sql: sql.join(", "), values })
+            }
+            Changes::Raw(sql) => {
+                if !allow_raw {
+                    return Err(MyError::InvalidInput(
+                        "The raw sql changes are only accepted with \"raw_sql\".".to_string()
+                    ));
+                }
+                Ok(SqlFragment { sql: sql.clone(), values: vec![] })
+            }
+        }
+    }
+}
+
+/// get the json of the binary value encoded by base64,
+/// which is bound as the bytes
+pub fn binary_value(base64: &str) -> Value {
+    let mut value = serde_json::Map::new();
+    value.insert("base64".to_string(), Value::from(base64));
+    Value::Object(value)
+}
+
+/// get the bytes of the json made by "binary_value"
+pub fn decode_binary(value: &Value) -> Option<Vec<u8>> {
+    match value.as_object() {
+        Some(object) if object.len() == 1 => base64::decode(object.get("base64")?.as_str()?).ok(),
+        _ => None,
+    }
+}
+
+///
+/// check the name of a table or a column
+/// and quote it with backticks
+///
+/// # Arguments
+///
+/// * `name`: the name of the table or column
+///
+/// returns: Result<String, MyError>
+///
+pub fn quote_identifier(name: &str) -> Result<String, MyError> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+        && !name.chars().all(|c| c.is_ascii_digit());
+    if !valid {
+        return Err(MyError::InvalidInput(format!("The name \"{}\" is not a valid identifier.", name)));
+    }
+    Ok("`".to_string() + name + "`")
+}
+
+/// render the predicates joined by "AND" or "OR"
+fn render_group(predicates: &[Predicate], separator: &str, allow_raw: bool) -> Result<SqlFragment, MyError> {
+    if predicates.is_empty() {
+        return Err(MyError::InvalidInput("The group of predicates should not be empty.".to_string()));
+    }
+    let mut sql = vec![];
+    let mut values = vec![];
+    for predicate in predicates {
+        let fragment = predicate.render(allow_raw)?;
+        sql.push(fragment.sql);
+        values.extend(fragment.values);
+    }
+    Ok(SqlFragment {
+        sql: "(".to_string() + sql.join(separator).as_str() + ")",
+        values,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::models::predicate::*;
+
+    #[test]
+    fn render_group_test() {
+        let predicate: Predicate = serde_json::from_value(json!({
+            "or": [
+                {"column": "contact_id", "operator": "=", "value": 19},
+                {"and": [
+                    {"column": "email", "operator": "like", "value": "%@mail.com"},
+                    {"column": "review_id", "operator": "in", "value": [1, 2]}
+                ]}
+            ]
+        })).unwrap();
+        let fragment = predicate.render(false).unwrap();
+        assert_eq!(fragment.sql, "(`contact_id` = ? OR (`email` LIKE ? AND `review_id` IN (?, ?)))");
+        assert_eq!(fragment.values, vec![json!(19), json!("%@mail.com"), json!(1), json!(2)]);
+    }
+
+    #[test]
+    fn reject_injection_test() {
+        let predicate = Predicate::eq("contact_id=1 OR 1", json!(19));
+        assert!(predicate.render(false).is_err());
+        let predicate = Predicate::Raw("contact_id=19".to_string());
+        assert!(predicate.render(false).is_err());
+        assert!(predicate.render(true).is_ok());
+        let changes = Changes::Columns(vec![Change { column: "name`".to_string(), value: json!("x") }]);
+        assert!(changes.render(false).is_err());
+    }
+
+    #[test]
+    fn from_vault_test() {
+        let predicate = Predicate::eq("contact_id", json!(19));
+        assert_eq!(Predicate::from_vault(&predicate.to_vault()).unwrap(), predicate);
+        assert_eq!(Predicate::from_vault("contact_id=19").unwrap(), predicate);
+        assert!(Predicate::from_vault("1=1 OR contact_id=19").is_err());
+    }
+
+    #[test]
+    fn binary_value_test() {
+        let predicate = Predicate::eq("user_id", binary_value("AAEC/w=="));
+        let fragment = Predicate::from_vault(&predicate.to_vault()).unwrap().render(false).unwrap();
+        assert_eq!(fragment.sql, "`user_id` = ?");
+        assert_eq!(decode_binary(&fragment.values[0]), Some(vec![0, 1, 2, 255]));
+        //the other objects are not accepted
+        assert!(Predicate::eq("user_id", json!({"base64": "not base64!"})).render(false).is_err());
+        assert!(Predicate::eq("user_id", json!({"sql": "1"})).render(false).is_err());
+        assert_eq!(Predicate::is_null("parent_id").render(false).unwrap().sql, "`parent_id` IS NULL");
+    }
+}
@@ -0,0 +1,535 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use crate::models::target::Target;
+use crate::models::vault::{Disguise, Function};
+
+/// how the recovery resolves the row changed by the application after the disguise
+/// "abort" recovers nothing, "skip" keeps the current row,
+/// "overwrite" restores the original row,
+/// and "merge" restores the original row but keeps the fields changed after the disguise
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    #[default]
+    Abort,
+    Skip,
+    Overwrite,
+    Merge,
+}
+/// how the recovery treats the rows changed again by the later disguises of the vault
+/// "refuse" recovers nothing until the later disguises are recovered,
+/// "reorder" recovers the later disguises first from the newest one,
+/// and "undo" only undoes this disguise by rewriting the state kept by the later disguises
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum StackPolicy {
+    #[default]
+    Refuse,
+    Reorder,
+    Undo,
+}
+/// the function of the disguise whose row has been changed again by a later disguise of the vault
+#[derive(Serialize, Debug, Clone, FromRow)]
+pub struct StackedFunction {
+    pub function_id: Option<i32>,
+    pub table_name: Option<String>,
+    pub predicate: Option<String>,
+    /// the public id of the later disguise
+    pub later_disguise_id: Option<String>,
+    pub later_function_id: Option<i32>
+}
+/// the disguise to recover with the steps of its selected functions,
+/// and the selected functions stacked under the later disguises
+#[derive(Debug, Clone)]
+pub struct StackedDisguise {
+    pub disguise: Disguise,
+    pub steps: Vec<Disguise>,
+    pub stacked: Vec<StackedFunction>
+}
+/// the rows of one disguise undone in the middle of the stack
+#[derive(Debug, Clone, PartialEq)]
+pub struct UndoPlan {
+    /// the original and the updated state of every later function without the disguise
+    pub later: Vec<(Option<Target>, Option<Target>)>,
+    /// the fields of the current row to restore, none if nothing is restored
+    pub current: Option<Target>
+}
+/// the policies of the recovery, which could be chosen for every table
+#[derive(Debug, Clone, Default)]
+pub struct RecoveryPolicies {
+    pub default: ConflictPolicy,
+    pub tables: HashMap<String, ConflictPolicy>
+}
+/// the row which has been changed after the disguise, and how it was resolved
+#[derive(Serialize, Debug, Clone)]
+pub struct RecoveryConflict {
+    pub disguise_id: Option<String>,
+    pub table_name: Option<String>,
+    pub function_type: Option<String>,
+    pub primary_key: Option<Value>,
+    /// "modified", "deleted" or "key_reused"
+    pub conflict: Option<String>,
+    /// the fields changed by the application after the disguise
+    pub changed_fields: Option<Vec<String>>,
+    /// "aborted", "skipped", "overwritten" or "merged"
+    pub resolution: Option<String>
+}
+/// the recovered disguises returned to the web app or the user
+/// with the conflicts resolved by the policies
+#[derive(Serialize, Debug, Clone)]
+pub struct RecoveredDisguises {
+    pub disguise_ids: Option<Vec<String>>,
+    pub conflicts: Option<Vec<RecoveryConflict>>,
+    pub message: Option<String>
+}
+/// what the recovery would do to the row of one function, which is not changed by the preview
+/// the action is "insert", "update", "unchanged", "skip", "abort", "undo" or "fail"
+#[derive(Serialize, Debug, Clone)]
+pub struct FunctionDiff {
+    pub disguise_id: Option<String>,
+    pub function_id: Option<i32>,
+    pub table_name: Option<String>,
+    pub function_type: Option<String>,
+    pub primary_key: Option<Value>,
+    pub action: Option<String>,
+    pub conflict: Option<String>,
+    /// the fields changed by the application after the disguise
+    pub changed_fields: Option<Vec<String>>,
+    pub resolution: Option<String>,
+    /// the fields the recovery would write with their current values
+    pub changes: Option<Vec<FieldChange>>,
+    /// why the function could not be recovered
+    pub error: Option<String>
+}
+/// the field which would change from its current value to its original value
+/// the current value is none when the row would be inserted
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    pub field_name: Option<String>,
+    pub current: Option<Value>,
+    pub original: Option<Value>
+}
+/// the preview of the recovery returned to the web app or the user
+#[derive(Serialize, Debug, Clone)]
+pub struct RecoveryPreview {
+    pub disguise_ids: Option<Vec<String>>,
+    pub functions: Option<Vec<FunctionDiff>>,
+    pub conflicts: Option<usize>,
+    pub failures: Option<usize>,
+    pub message: Option<String>
+}
+/// what the recovery does to one row
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecoveryAction {
+    /// insert the row, which does not exist now
+    Insert(Target),
+    /// update the existing row with the fields
+    Update(Target),
+    Skip,
+    Abort,
+}
+/// the action of one row with the conflict found before it
+#[derive(Debug, Clone)]
+pub struct RecoveryPlan {
+    pub action: RecoveryAction,
+    pub conflict: Option<&'static str>,
+    pub changed_fields: Vec<String>
+}
+
+impl RecoveryPolicies {
+    /// restore all the original rows whatever they are now,
+    /// which only compensates the disguise just applied by the server
+    pub fn overwrite() -> Self {
+        RecoveryPolicies {
+            default: ConflictPolicy::Overwrite,
+            tables: HashMap::new()
+        }
+    }
+
+    /// get the policy of the table
+    pub fn of(&self, table_name: &str) -> ConflictPolicy {
+        self.tables.get(table_name).copied().unwrap_or(self.default)
+    }
+}
+
+impl ConflictPolicy {
+    /// get the resolution of the conflict resolved by the policy
+    pub fn resolution(&self) -> &'static str {
+        match self {
+            ConflictPolicy::Abort => "aborted",
+            ConflictPolicy::Skip => "skipped",
+            ConflictPolicy::Overwrite => "overwritten",
+            ConflictPolicy::Merge => "merged",
+        }
+    }
+}
+
+impl FunctionDiff {
+    /// create the diff of the function before it is planned
+    pub fn new(disguise_id: Option<String>, function: &Function) -> Self {
+        FunctionDiff {
+            disguise_id,
+            function_id: function.function_id,
+            table_name: function.table_name.clone(),
+            function_type: function.function_type.clone(),
+            primary_key: None,
+            action: None,
+            conflict: None,
+            changed_fields: None,
+            resolution: None,
+            changes: None,
+            error: None
+        }
+    }
+
+    ///
+    /// set what the plan would do to the current row
+    ///
+    /// # Arguments
+    ///
+    /// * `original`: the original row before the disguise
+    /// * `current`: the row in the application now
+    /// * `plan`: the plan of the row
+    /// * `policy`: the policy of the row's table
+    ///
+    /// returns: ()
+    ///
+    pub fn set_plan(&mut self, original: &Target, current: Option<&Target>, plan: RecoveryPlan, policy: ConflictPolicy) {
+        self.primary_key = original.primary_key().and_then(|key| key.field_value).map(|value| value.to_value());
+        if let Some(conflict) = plan.conflict {
+            self.conflict = Some(conflict.to_string());
+            self.changed_fields = Some(plan.changed_fields);
+            self.resolution = Some(policy.resolution().to_string());
+        }
+        let (action, changes) = match &plan.action {
+            RecoveryAction::Insert(target) => ("insert", diff_fields(target, None)),
+            RecoveryAction::Update(target) => {
+                let changes = diff_fields(target, current);
+                (if changes.is_empty() { "unchanged" } else { "update" }, changes)
+            }
+            RecoveryAction::Skip => ("skip", vec![]),
+            RecoveryAction::Abort => ("abort", vec![]),
+        };
+        self.action = Some(action.to_string());
+        self.changes = Some(changes);
+    }
+
+    /// set the function stacked under the later disguises, which is undone or refused
+    /// the changes of the undone row are only known when the later disguises are read
+    pub fn set_stacked(&mut self, undone: bool) {
+        self.conflict = Some("stacked".to_string());
+        self.action = Some(if undone { "undo" } else { "abort" }.to_string());
+        self.resolution = Some(if undone { "undone" } else { "refused" }.to_string());
+        self.changes = None;
+    }
+
+    /// set the reason why the function could not be recovered
+    pub fn set_failure(&mut self, error: String) {
+        self.action = Some("fail".to_string());
+        self.error = Some(error);
+    }
+}
+
+///
+/// get the fields of the row the recovery would write
+/// whose values are different from the current row
+///
+/// # Arguments
+///
+/// * `target`: the row the recovery would write
+/// * `current`: the row in the application now, none if it does not exist
+///
+/// returns: Vec<FieldChange, Global>
+///
+pub fn diff_fields(target: &Target, current: Option<&Target>) -> Vec<FieldChange> {
+    let current = current.map(|current| current.to_map());
+    target.to_map()
+        .into_iter()
+        .filter_map(|(name, original)| {
+            let value = current.as_ref().map(|current| current.get(&name).cloned().unwrap_or(Value::Null));
+            (value.as_ref() != Some(&original)).then_some(FieldChange {
+                field_name: Some(name),
+                current: value,
+                original: Some(original)
+            })
+        })
+        .collect()
+}
+
+///
+/// get the stacked functions of the disguises to recover
+/// whose later functions are not recovered before them by the same recovery
+/// the disguises are ordered from the newest one, which is recovered first
+///
+/// # Arguments
+///
+/// * `disguises`: the disguises to recover
+///
+/// returns: Vec<StackedFunction, Global>
+///
+pub fn blocked_functions(disguises: &[StackedDisguise]) -> Vec<StackedFunction> {
+    disguises.iter()
+        .flat_map(|disguise| disguise.stacked.iter())
+        .filter(|stacked| !disguises.iter().any(|later| {
+            later.disguise.public_id == stacked.later_disguise_id
+                && later.steps.iter().any(|step| step.has_function(stacked.later_function_id))
+        }))
+        .cloned()
+        .collect()
+}
+
+///
+/// plan the undo of one row of the disguise which has been changed again by the later disguises
+/// only the fields changed by the disguise are restored in every later state and the current row,
+/// and the field which has been changed again after the disguise keeps its value
+///
+/// # Arguments
+///
+/// * `original`: the original row before the disguise
+/// * `updated`: the row right after the disguise
+/// * `later`: the original and the updated state of the later functions from the oldest one
+/// * `current`: the row in the application now
+///
+/// returns: UndoPlan
+///
+pub fn plan_undo(
+    original: &Target,
+    updated: &Target,
+    later: &[(Option<Target>, Option<Target>)],
+    current: Option<&Target>,
+) -> UndoPlan {
+    let (original_values, updated_values) = (original.to_map(), updated.to_map());
+    //the field changed by the disguise is restored where it still has the disguised value
+    let undo = |state: &Target| {
+        let mut state = state.clone();
+        for field in state.fields.as_mut().unwrap() {
+            let name = field.field_name.clone().unwrap();
+            let value = field.field_value.as_ref().map_or(Value::Null, |value| value.to_value());
+            if original_values.get(&name) != updated_values.get(&name) && updated_values.get(&name) == Some(&value) {
+                field.field_value = original.foreign_key(&name).and_then(|field| field.field_value);
+            }
+        }
+        state
+    };
+    let later = later.iter()
+        .map(|(original, updated)| (original.as_ref().map(undo), updated.as_ref().map(undo)))
+        .collect();
+    let current = current.and_then(|current| {
+        let mut restored = undo(current);
+        let changed_fields = current.changed_fields(&restored);
+        restored.fields.as_mut().unwrap()
+            .retain(|field| changed_fields.contains(field.field_name.as_ref().unwrap()));
+        (!changed_fields.is_empty()).then_some(restored)
+    });
+    UndoPlan {
+        later,
+        current
+    }
+}
+
+///
+/// plan the recovery of one row by comparing its current state
+/// with the state stored right after the disguise
+/// the removed row conflicts when its primary key has been used again,
+/// and the changed row conflicts when it has been deleted or changed again
+/// the row without the stored state after the disguise is restored as before
+///
+/// # Arguments
+///
+/// * `function_type`: the type of the function which changed the row
+/// * `original`: the original row before the disguise
+/// * `updated`: the row right after the disguise, none if it was removed
+/// * `current`: the row in the application now
+/// * `policy`: the policy of the row's table
+///
+/// returns: RecoveryPlan
+///
+pub fn plan_recovery(
+    function_type: &str,
+    original: &Target,
+    updated: Option<&Target>,
+    current: Option<&Target>,
+    policy: ConflictPolicy,
+) -> RecoveryPlan {
+    let (conflict, changed_fields) = match (function_type, updated, current) {
+        //the row has been restored by the recovery which stopped before the function was marked
+        (_, _, Some(current)) if original.changed_fields(current).is_empty() => (None, vec![]),
+        ("removal", _, None) => (None, vec![]),
+        ("removal", _, Some(current)) => (Some("key_reused"), original.changed_fields(current)),
+        (_, None, Some(_)) => (None, vec![]),
+        (_, _, None) => (Some("deleted"), vec![]),
+        (_, Some(updated), Some(current)) => {
+            let changed_fields = updated.changed_fields(current);
+            (if changed_fields.is_empty() { None } else { Some("modified") }, changed_fields)
+        }
+    };
+    let restore = || match current {
+        Some(_) => RecoveryAction::Update(original.clone()),
+        None => RecoveryAction::Insert(original.clone()),
+    };
+    let action = match (conflict, policy) {
+        (None, _) | (Some(_), ConflictPolicy::Overwrite) => restore(),
+        (Some(_), ConflictPolicy::Abort) => RecoveryAction::Abort,
+        //the merged row keeps the current fields, which are all of them for the other row
+        (Some("modified"), ConflictPolicy::Merge) => {
+            let mut merged = original.clone();
+            merged.fields.as_mut().unwrap()
+                .retain(|field| !changed_fields.contains(field.field_name.as_ref().unwrap()));
+            RecoveryAction::Update(merged)
+        }
+        (Some(_), _) => RecoveryAction::Skip,
+    };
+    RecoveryPlan {
+        action,
+        conflict,
+        changed_fields
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::recovery::*;
+    use crate::models::target::Target;
+    use crate::models::vault::{Disguise, Function};
+
+    fn review(contact_id: &str, content: &str) -> Target {
+        let names = vec!["review_id".to_string(), "contact_id".to_string(), "content".to_string()];
+        let types = vec!["int".to_string(), "int".to_string(), "text".to_string()];
+        Target::from_legacy(format!("7, {}, \"{}\"", contact_id, content).as_str(), &names, &types, 0).unwrap()
+    }
+
+    #[test]
+    fn plan_recovery_test() {
+        let original = review("19", "good");
+        let updated = review("100", "good");
+        //the row is not changed after the disguise
+        let plan = plan_recovery("decorrelation", &original, Some(&updated), Some(&updated), ConflictPolicy::Abort);
+        assert_eq!(plan.conflict, None);
+        assert_eq!(plan.action, RecoveryAction::Update(original.clone()));
+
+        //the moderator edited the decorrelated review
+        let edited = review("100", "edited");
+        let plan = plan_recovery("decorrelation", &original, Some(&updated), Some(&edited), ConflictPolicy::Abort);
+        assert_eq!(plan.conflict, Some("modified"));
+        assert_eq!(plan.changed_fields, vec!["content".to_string()]);
+        assert_eq!(plan.action, RecoveryAction::Abort);
+        let plan = plan_recovery("decorrelation", &original, Some(&updated), Some(&edited), ConflictPolicy::Merge);
+        match plan.action {
+            RecoveryAction::Update(merged) => assert_eq!(merged.field_names().unwrap(), "`review_id`, `contact_id`"),
+            action => panic!("{:?}", action),
+        }
+        let plan = plan_recovery("decorrelation", &original, Some(&updated), Some(&edited), ConflictPolicy::Overwrite);
+        assert_eq!(plan.action, RecoveryAction::Update(original.clone()));
+
+        //the decorrelated review was deleted by the application
+        let plan = plan_recovery("decorrelation", &original, Some(&updated), None, ConflictPolicy::Merge);
+        assert_eq!((plan.conflict, plan.action), (Some("deleted"), RecoveryAction::Skip));
+        let plan = plan_recovery("decorrelation", &original, Some(&updated), None, ConflictPolicy::Overwrite);
+        assert_eq!(plan.action, RecoveryAction::Insert(original.clone()));
+
+        //the primary key of the removed review was used again
+        let plan = plan_recovery("removal", &original, None, None, ConflictPolicy::Abort);
+        assert_eq!(plan.action, RecoveryAction::Insert(original.clone()));
+        let plan = plan_recovery("removal", &original, None, Some(&edited), ConflictPolicy::Skip);
+        assert_eq!((plan.conflict, plan.action), (Some("key_reused"), RecoveryAction::Skip));
+        //the row restored before the recovery was resumed is written again
+        let plan = plan_recovery("removal", &original, None, Some(&original), ConflictPolicy::Abort);
+        assert_eq!((plan.conflict, plan.action), (None, RecoveryAction::Update(original.clone())));
+
+        let policies = RecoveryPolicies {
+            default: ConflictPolicy::Skip,
+            tables: HashMap::from([("review".to_string(), ConflictPolicy::Merge)])
+        };
+        assert_eq!(policies.of("review"), ConflictPolicy::Merge);
+        assert_eq!(policies.of("contact_info"), ConflictPolicy::Skip);
+    }
+
+    #[test]
+    fn plan_undo_test() {
+        //the review was decorrelated, and its content was scrubbed by the later disguise
+        let original = review("19", "good");
+        let updated = review("100", "good");
+        let later = vec![(Some(review("100", "good")), Some(review("100", "[deleted]")))];
+        let plan = plan_undo(&original, &updated, &later, Some(&review("100", "[deleted]")));
+        assert_eq!(plan.later, vec![(Some(review("19", "good")), Some(review("19", "[deleted]")))]);
+        let current = plan.current.unwrap();
+        assert_eq!(current.field_names().unwrap(), "`contact_id`");
+        assert_eq!(current.to_map().get("contact_id"), Some(&19.into()));
+
+        //the field decorrelated again by the later disguise keeps its value
+        let later = vec![(Some(review("100", "good")), Some(review("200", "good")))];
+        let plan = plan_undo(&original, &updated, &later, Some(&review("200", "good")));
+        assert_eq!(plan.later, vec![(Some(review("19", "good")), Some(review("200", "good")))]);
+        assert_eq!(plan.current, None);
+        //the row removed by the later disguise is only restored by it
+        let later = vec![(Some(review("100", "good")), None)];
+        let plan = plan_undo(&original, &updated, &later, None);
+        assert_eq!(plan.later, vec![(Some(original.clone()), None)]);
+    }
+
+    #[test]
+    fn blocked_functions_test() {
+        let disguise = |public_id: &str, function_id: i32| Disguise {
+            disguise_id: None,
+            public_id: Some(public_id.into()),
+            time: None,
+            vault_id: Some("19".into()),
+            disguise_type: None,
+            status: None,
+            functions: Some(vec![Function {
+                function_id: Some(function_id),
+                disguise_id: None,
+                function_type: Some("decorrelation".into()),
+                table_name: Some("review".into()),
+                predicate: None,
+                original: None,
+                updated: None,
+                recovered_time: None
+            }]),
+            placeholders: None
+        };
+        let stacked = StackedFunction {
+            function_id: Some(1),
+            table_name: Some("review".into()),
+            predicate: None,
+            later_disguise_id: Some("scrub".into()),
+            later_function_id: Some(2)
+        };
+        let anonymize = StackedDisguise {
+            disguise: disguise("anonymize", 1),
+            steps: disguise("anonymize", 1).steps(),
+            stacked: vec![stacked]
+        };
+        //the later disguise is not recovered by the same recovery
+        assert_eq!(blocked_functions(std::slice::from_ref(&anonymize)).len(), 1);
+        //the later disguise is recovered first
+        let scrub = StackedDisguise {
+            disguise: disguise("scrub", 2),
+            steps: disguise("scrub", 2).steps(),
+            stacked: vec![]
+        };
+        assert!(blocked_functions(&[scrub.clone(), anonymize.clone()]).is_empty());
+        //the later function is not selected
+        let scrub = StackedDisguise {
+            steps: vec![],
+            ..scrub
+        };
+        assert_eq!(blocked_functions(&[scrub, anonymize]).len(), 1);
+    }
+
+    #[test]
+    fn diff_fields_test() {
+        let original = review("19", "good");
+        let edited = review("100", "edited");
+        let changes = diff_fields(&original, Some(&edited));
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].field_name.as_deref(), Some("contact_id"));
+        assert_eq!((changes[0].current.clone(), changes[0].original.clone()), (Some(100.into()), Some(19.into())));
+        assert!(diff_fields(&original, Some(&original)).is_empty());
+        //every field of the inserted row is written
+        assert!(diff_fields(&original, None).iter().all(|change| change.current.is_none()));
+        assert_eq!(diff_fields(&original, None).len(), 3);
+    }
+}
@@ -1,17 +1,46 @@
 use actix_web::web;
 use actix_web::web::Json;
-use serde::Deserialize;
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::models::recovery::{ConflictPolicy, RecoveryPolicies, StackPolicy};
+use crate::models::schema::DiscoverRoot;
 use crate::models::transformation::Transformation;
 
 /// which comes from the web app or the users
 /// to describe the disguise
-#[derive(Deserialize, Debug, Clone)]
+/// the applied disguise is found by its public "disguise_id",
+/// or by its type with the "selector" of "latest" or "all"
+/// the transformations could be taken from the stored "specification"
+/// with its "parameters" instead
+/// or discovered from the "root" row of the user's data
+/// the "preview" disguise only reports the changes without writing them
+/// the vault with the recovery key is only recovered with the user's "secret"
+/// the rows changed after the disguise are recovered by the "conflict_policy",
+/// or by the policy of their tables in "table_policies"
+/// only the functions on the "tables" or with the "function_ids" are recovered if they are given
+/// the rows changed again by the later disguises are recovered by the "stack_policy"
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Requirement {
     pub disguise_name: Option<String>,
     pub vault_id: Option<String>,
+    pub disguise_id: Option<String>,
+    pub selector: Option<String>,
     pub delete_age: Option<i64>,
     pub delete_name: Option<String>,
     pub transformations: Option<Vec<Transformation>>,
+    pub specification: Option<String>,
+    pub version: Option<i32>,
+    pub parameters: Option<HashMap<String, Value>>,
+    pub preview: Option<bool>,
+    pub root: Option<DiscoverRoot>,
+    #[serde(skip_serializing)]
+    pub secret: Option<String>,
+    pub conflict_policy: Option<ConflictPolicy>,
+    pub table_policies: Option<HashMap<String, ConflictPolicy>>,
+    pub tables: Option<Vec<String>>,
+    pub function_ids: Option<Vec<i32>>,
+    pub stack_policy: Option<StackPolicy>,
 }
 
 impl From<web::Json<Requirement>> for Requirement {
@@ -19,9 +48,58 @@ impl From<web::Json<Requirement>> for Requirement {
         Requirement {
             disguise_name: json_requirement.disguise_name.clone(),
             vault_id: json_requirement.vault_id.clone(),
-            delete_age: json_requirement.delete_age.clone(),
+            disguise_id: json_requirement.disguise_id.clone(),
+            selector: json_requirement.selector.clone(),
+            delete_age: json_requirement.delete_age,
             delete_name: json_requirement.delete_name.clone(),
-            transformations: json_requirement.transformations.clone()
+            transformations: json_requirement.transformations.clone(),
+            specification: json_requirement.specification.clone(),
+            version: json_requirement.version,
+            parameters: json_requirement.parameters.clone(),
+            preview: json_requirement.preview,
+            root: json_requirement.root.clone(),
+            secret: json_requirement.secret.clone(),
+            conflict_policy: json_requirement.conflict_policy,
+            table_policies: json_requirement.table_policies.clone(),
+            tables: json_requirement.tables.clone(),
+            function_ids: json_requirement.function_ids.clone(),
+            stack_policy: json_requirement.stack_policy
         }
     }
-}
\ No newline at end of file
+}
+impl Requirement {
+    /// get a new empty requirement
+    pub fn new_empty() -> Self {
+        Self {
+            disguise_name: None,
+            vault_id: None,
+            disguise_id: None,
+            selector: None,
+            delete_age: None,
+            delete_name: None,
+            transformations: None,
+            specification: None,
+            version: None,
+            parameters: None,
+            preview: None,
+            root: None,
+            secret: None,
+            conflict_policy: None,
+            table_policies: None,
+            tables: None,
+            function_ids: None,
+            stack_policy: None
+        }
+    }
+    /// if the disguise is only previewed
+    pub fn is_preview(&self) -> bool {
+        self.preview.unwrap_or(false)
+    }
+    /// get the policies of the recovery, which aborts on any conflict by default
+    pub fn recovery_policies(&self) -> RecoveryPolicies {
+        RecoveryPolicies {
+            default: self.conflict_policy.unwrap_or_default(),
+            tables: self.table_policies.clone().unwrap_or_default()
+        }
+    }
+}
@@ -0,0 +1,385 @@
+use std::collections::{HashSet, VecDeque};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use crate::error::MyError;
+use crate::models::placeholder::PlaceholderInfo;
+use crate::models::predicate::Predicate;
+use crate::models::requirement::Requirement;
+use crate::models::transformation::Transformation;
+
+/// the foreign key in the web's database
+/// which is read from "information_schema.KEY_COLUMN_USAGE",
+/// the composite foreign key has one row for every column of its constraint
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, FromRow)]
+pub struct ForeignKey {
+    pub table_name: Option<String>,
+    pub column_name: Option<String>,
+    pub referenced_table_name: Option<String>,
+    pub referenced_column_name: Option<String>,
+    pub constraint_name: Option<String>,
+}
+impl ForeignKey {
+    /// if the constraint of the foreign key has several columns
+    pub fn is_composite(&self, foreign_keys: &[ForeignKey]) -> bool {
+        foreign_keys.iter()
+            .filter(|foreign_key| foreign_key.table_name == self.table_name
+                && foreign_key.constraint_name == self.constraint_name)
+            .count() > 1
+    }
+}
+/// the row which owns the user's data, like "contact_info.contact_id=19"
+/// the primary key of the table is used if its name is not given
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct DiscoverRoot {
+    pub table_name: Option<String>,
+    pub primary_key_name: Option<String>,
+    pub primary_key_value: Option<Value>,
+}
+/// the table which refers to the root directly or through other tables
+/// the number of the rows is only counted for the direct references,
+/// and the composite foreign key is marked since it could not be decorrelated by one column
+#[derive(Serialize, Debug, Clone)]
+pub struct Dependent {
+    pub foreign_key: Option<ForeignKey>,
+    pub depth: Option<usize>,
+    pub rows: Option<i64>,
+    pub composite: Option<bool>,
+}
+/// the data from the web app or the user to discover the user's data
+#[derive(Deserialize, Debug, Clone)]
+pub struct Discover {
+    pub disguise_name: Option<String>,
+    pub vault_id: Option<String>,
+    pub root: Option<DiscoverRoot>,
+}
+/// the discovered dependents returned to the web app or the user
+/// with the proposed requirement which could be applied directly
+#[derive(Serialize, Debug, Clone)]
+pub struct Discovery {
+    pub requirement: Option<Requirement>,
+    pub dependents: Option<Vec<Dependent>>,
+}
+
+///
+/// find the tables which refer to the root column
+/// through the foreign keys level by level,
+/// the tables of the deeper levels are the ones referring to any column of the dependents
+/// every table is only visited once
+///
+/// # Arguments
+///
+/// * `foreign_keys`: all the foreign keys of the database
+/// * `table_name`: the table of the root
+/// * `column_name`: the primary key of the root
+///
+/// returns: Vec<Dependent, Global>
+///
+pub fn find_dependents(foreign_keys: &[ForeignKey], table_name: &str, column_name: &str) -> Vec<Dependent> {
+    let mut res = vec![];
+    let mut visited = HashSet::from([table_name.to_string()]);
+    let mut queue = VecDeque::new();
+    //the direct references to the root column
+    for foreign_key in foreign_keys {
+        if foreign_key.referenced_table_name.as_deref() == Some(table_name)
+            && foreign_key.referenced_column_name.as_deref() == Some(column_name) {
+            res.push(Dependent {
+                foreign_key: Some(foreign_key.clone()),
+                depth: Some(1),
+                rows: None,
+                composite: Some(foreign_key.is_composite(foreign_keys))
+            });
+            let dependent = foreign_key.table_name.clone().unwrap();
+            if visited.insert(dependent.clone()) {
+                queue.push_back((dependent, 1));
+            }
+        }
+    }
+    //the references to the dependents
+    while let Some((dependent, depth)) = queue.pop_front() {
+        for foreign_key in foreign_keys {
+            if foreign_key.referenced_table_name.as_deref() == Some(dependent.as_str()) {
+                res.push(Dependent {
+                    foreign_key: Some(foreign_key.clone()),
+                    depth: Some(depth + 1),
+                    rows: None,
+                    composite: Some(foreign_key.is_composite(foreign_keys))
+                });
+                let next = foreign_key.table_name.clone().unwrap();
+                if visited.insert(next.clone()) {
+                    queue.push_back((next, depth + 1));
+                }
+            }
+        }
+    }
+    res
+}
+
+///
+/// propose the transformations of the disguise for the root
+/// only the rows referring to the root directly (depth 1) are decorrelated
+/// to the placeholder of the vault, which should be a row of the root table,
+/// and the root is removed by "userscrub" at last,
+/// the deeper dependents are not decorrelated and keep referring to the decorrelated rows
+/// the rows referring to the root by a composite foreign key are rejected,
+/// since one column of the key could not be pointed to the placeholder
+///
+/// # Arguments
+///
+/// * `disguise_name`: "userscrub" or "anonymize"
+/// * `root`: the table and the primary key of the root
+/// * `dependents`: the dependents with the number of their rows
+/// * `placeholder`: the placeholder of the vault
+///
+/// returns: Result<Vec<Transformation, Global>, MyError>
+///
+pub fn propose_transformations(
+    disguise_name: &str,
+    root: &DiscoverRoot,
+    dependents: &[Dependent],
+    placeholder: Option<&PlaceholderInfo>,
+) -> Result<Vec<Transformation>, MyError> {
+    let remove_root = match disguise_name {
+        "userscrub" => true,
+        "anonymize" => false,
+        _ => return Err(MyError::InvalidInput(
+            "Only \"userscrub\" and \"anonymize\" could be discovered.".into()
+        )),
+    };
+    let value = root.primary_key_value.clone().unwrap();
+    let mut transformations = vec![];
+    //the tables without the referring rows are skipped
+    for dependent in dependents {
+        if dependent.depth != Some(1) || dependent.rows.unwrap_or(0) == 0 {
+            continue;
+        }
+        let foreign_key = dependent.foreign_key.as_ref().unwrap();
+        let column_name = foreign_key.column_name.as_ref().unwrap();
+        if dependent.composite == Some(true) {
+            return Err(MyError::InvalidInput(format!(
+                "The rows of \"{}\" refer to the root by the composite foreign key \"{}\", \
+                which could not be decorrelated.",
+                foreign_key.table_name.as_deref().unwrap_or_default(),
+                foreign_key.constraint_name.as_deref().unwrap_or_default()
+            )));
+        }
+        let mut decorrelation = Transformation::new_empty();
+        decorrelation.transform_type = Some("decorrelation".into());
+        decorrelation.table_name = foreign_key.table_name.clone();
+        decorrelation.predicate = Some(Predicate::eq(column_name, value.clone()));
+        decorrelation.foreign_key = Some(column_name.clone());
+        transformations.push(decorrelation);
+    }
+    //the decorrelated rows refer to the placeholder instead of the root
+    if let Some(placeholder) = placeholder.filter(|_| !transformations.is_empty()) {
+        let column = match Predicate::from_vault(placeholder.pred.as_deref().unwrap_or_default())? {
+            Predicate::Condition { column, .. } => Some(column),
+            _ => None,
+        };
+        if placeholder.table != root.table_name || column != root.primary_key_name {
+            return Err(MyError::InvalidInput(format!(
+                "The placeholder of the vault is not a row of the root table \"{}\".",
+                root.table_name.as_deref().unwrap_or_default()
+            )));
+        }
+    }
+    if remove_root {
+        let mut removal = Transformation::new_empty();
+        removal.transform_type = Some("removal".into());
+        removal.table_name = root.table_name.clone();
+        removal.predicate = Some(Predicate::eq(root.primary_key_name.as_ref().unwrap(), value));
+        transformations.push(removal);
+    }
+    Ok(transformations)
+}
+
+///
+/// order the transformations by the foreign keys between their tables
+/// the transformation of the referring table (child) is executed
+/// before the one of the referred table (parent),
+/// and the order of the request is kept for the others
+/// the functions are recovered in the reverse order, so the parents are recovered first
+///
+/// # Arguments
+///
+/// * `transformations`: the transformations in the order of the request
+/// * `foreign_keys`: all the foreign keys of the database
+///
+/// returns: Result<Vec<Transformation, Global>, MyError>
+///
+pub fn order_transformations(
+    transformations: Vec<Transformation>,
+    foreign_keys: &[ForeignKey],
+) -> Result<Vec<Transformation>, MyError> {
+    let count = transformations.len();
+    //the transformations which should be executed before every transformation
+    let mut before = vec![HashSet::new(); count];
+    for (i, child) in transformations.iter().enumerate() {
+        for (j, parent) in transformations.iter().enumerate() {
+            //the self-referring table keeps its order
+            if child.table_name == parent.table_name {
+                continue;
+            }
+            let refers = foreign_keys.iter().any(|foreign_key| {
+                foreign_key.table_name == child.table_name
+                    && foreign_key.referenced_table_name == parent.table_name
+            });
+            if refers {
+                before[j].insert(i);
+            }
+        }
+    }
+    let mut ordered = vec![];
+    let mut done = vec![false; count];
+    while ordered.len() < count {
+        //the first transformation whose children have been executed
+        let next = (0..count).find(|&i| !done[i] && before[i].iter().all(|&j| done[j]));
+        match next {
+            Some(i) => {
+                done[i] = true;
+                ordered.push(i);
+            }
+            None => {
+                let mut tables: Vec<&str> = (0..count)
+                    .filter(|&i| !done[i])
+                    .filter_map(|i| transformations[i].table_name.as_deref())
+                    .collect();
+                tables.dedup();
+                return Err(MyError::InvalidInput(format!(
+                    "The foreign keys between the tables \"{}\" are circular, the transformations could not be ordered.",
+                    tables.join(", ")
+                )));
+            }
+        }
+    }
+    let mut transformations: Vec<Option<Transformation>> = transformations.into_iter().map(Some).collect();
+    Ok(ordered.into_iter().map(|i| transformations[i].take().unwrap()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::models::predicate::Predicate;
+    use crate::models::schema::*;
+
+    fn foreign_key(table_name: &str, column_name: &str, referenced_table_name: &str, referenced_column_name: &str) -> ForeignKey {
+        ForeignKey {
+            table_name: Some(table_name.into()),
+            column_name: Some(column_name.into()),
+            referenced_table_name: Some(referenced_table_name.into()),
+            referenced_column_name: Some(referenced_column_name.into()),
+            constraint_name: Some(format!("{}_{}", table_name, column_name)),
+        }
+    }
+
+    #[test]
+    fn find_dependents_test() {
+        let foreign_keys = vec![
+            foreign_key("review", "contact_id", "contact_info", "contact_id"),
+            foreign_key("review_vote", "review_id", "review", "review_id"),
+            foreign_key("contact_info", "invited_by", "contact_info", "contact_id"),
+            foreign_key("order_item", "order_id", "orders", "order_id"),
+        ];
+        let dependents = find_dependents(&foreign_keys, "contact_info", "contact_id");
+        let tables: Vec<(&str, usize)> = dependents.iter()
+            .map(|dependent| (dependent.foreign_key.as_ref().unwrap().table_name.as_deref().unwrap(), dependent.depth.unwrap()))
+            .collect();
+        assert_eq!(tables, vec![("review", 1), ("contact_info", 1), ("review_vote", 2)]);
+    }
+
+    fn transformation(transform_type: &str, table_name: &str) -> Transformation {
+        let mut transformation = Transformation::new_empty();
+        transformation.transform_type = Some(transform_type.into());
+        transformation.table_name = Some(table_name.into());
+        transformation
+    }
+
+    #[test]
+    fn order_transformations_test() {
+        let foreign_keys = vec![
+            foreign_key("review", "contact_id", "contact_info", "contact_id"),
+            foreign_key("review_vote", "review_id", "review", "review_id"),
+            foreign_key("contact_info", "invited_by", "contact_info", "contact_id"),
+        ];
+        let transformations = vec![
+            transformation("removal", "contact_info"),
+            transformation("modification", "orders"),
+            transformation("removal", "review"),
+            transformation("decorrelation", "review_vote"),
+        ];
+        let ordered = order_transformations(transformations, &foreign_keys).unwrap();
+        let tables: Vec<&str> = ordered.iter().map(|transformation| transformation.table_name.as_deref().unwrap()).collect();
+        assert_eq!(tables, vec!["orders", "review_vote", "review", "contact_info"]);
+    }
+
+    #[test]
+    fn order_cycle_test() {
+        let foreign_keys = vec![
+            foreign_key("review", "contact_id", "contact_info", "contact_id"),
+            foreign_key("contact_info", "pinned_review_id", "review", "review_id"),
+        ];
+        let transformations = vec![
+            transformation("removal", "contact_info"),
+            transformation("decorrelation", "review"),
+        ];
+        assert!(order_transformations(transformations, &foreign_keys).is_err());
+    }
+
+    #[test]
+    fn propose_transformations_test() {
+        let root = DiscoverRoot {
+            table_name: Some("contact_info".into()),
+            primary_key_name: Some("contact_id".into()),
+            primary_key_value: Some(json!(19)),
+        };
+        let dependents = vec![
+            Dependent { foreign_key: Some(foreign_key("review", "contact_id", "contact_info", "contact_id")), depth: Some(1), rows: Some(3), composite: Some(false) },
+            Dependent { foreign_key: Some(foreign_key("address", "contact_id", "contact_info", "contact_id")), depth: Some(1), rows: Some(0), composite: Some(false) },
+            Dependent { foreign_key: Some(foreign_key("review_vote", "review_id", "review", "review_id")), depth: Some(2), rows: None, composite: Some(false) },
+        ];
+        let transformations = propose_transformations("userscrub", &root, &dependents, None).unwrap();
+        assert_eq!(transformations.len(), 2);
+        assert_eq!(transformations[0].table_name.as_deref(), Some("review"));
+        assert_eq!(transformations[0].foreign_key.as_deref(), Some("contact_id"));
+        assert_eq!(transformations[1].transform_type.as_deref(), Some("removal"));
+        assert_eq!(transformations[1].predicate, Some(Predicate::eq("contact_id", json!(19))));
+        assert_eq!(propose_transformations("anonymize", &root, &dependents, None).unwrap().len(), 1);
+        assert!(propose_transformations("expiration", &root, &dependents, None).is_err());
+    }
+
+    #[test]
+    fn propose_checks_test() {
+        let root = DiscoverRoot {
+            table_name: Some("contact_info".into()),
+            primary_key_name: Some("contact_id".into()),
+            primary_key_value: Some(json!(19)),
+        };
+        let dependents = vec![
+            Dependent { foreign_key: Some(foreign_key("review", "contact_id", "contact_info", "contact_id")), depth: Some(1), rows: Some(3), composite: Some(false) },
+        ];
+        //the placeholder should be a row of the root table
+        let mut placeholder = PlaceholderInfo {
+            pred: Some(Predicate::eq("contact_id", json!(1)).to_vault()),
+            table: Some("contact_info".into()),
+            template: None,
+        };
+        assert!(propose_transformations("userscrub", &root, &dependents, Some(&placeholder)).is_ok());
+        placeholder.table = Some("users".into());
+        assert!(propose_transformations("userscrub", &root, &dependents, Some(&placeholder)).is_err());
+        placeholder.table = Some("contact_info".into());
+        placeholder.pred = Some("email=0".into());
+        assert!(propose_transformations("userscrub", &root, &dependents, Some(&placeholder)).is_err());
+
+        //the composite foreign key is marked and rejected
+        let mut order_key = foreign_key("orders", "contact_id", "contact_info", "contact_id");
+        let mut region_key = foreign_key("orders", "region", "contact_info", "region");
+        order_key.constraint_name = Some("orders_contact".into());
+        region_key.constraint_name = Some("orders_contact".into());
+        let foreign_keys = vec![order_key, region_key];
+        let mut dependents = find_dependents(&foreign_keys, "contact_info", "contact_id");
+        assert_eq!(dependents.len(), 1);
+        assert_eq!(dependents[0].composite, Some(true));
+        dependents[0].rows = Some(2);
+        assert!(propose_transformations("anonymize", &root, &dependents, None).is_err());
+    }
+}
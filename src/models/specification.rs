@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use actix_web::web::Json;
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use sqlx::FromRow;
+use crate::error::MyError;
+use crate::models::transformation::Transformation;
+
+/// the named disguise kept in the vault database
+/// so that the web app only needs to give its name and parameters
+/// every change of the same name is stored as a new version
+/// the string "{name}" in the transformations is replaced by the parameter "name"
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Specification {
+    pub name: Option<String>,
+    pub version: Option<i32>,
+    pub transformations: Option<Vec<Transformation>>,
+    pub reversible: Option<bool>,
+    pub time: Option<String>,
+}
+impl From<Json<Specification>> for Specification {
+    fn from(specification_json: Json<Specification>) -> Self {
+        Specification {
+            name: specification_json.name.clone(),
+            version: specification_json.version,
+            transformations: specification_json.transformations.clone(),
+            reversible: specification_json.reversible,
+            time: specification_json.time.clone(),
+        }
+    }
+}
+/// which from or to the database
+/// the transformations are stored as json
+#[derive(Debug, Clone, FromRow)]
+pub struct SpecificationFromDB {
+    pub name: Option<String>,
+    pub version: Option<i32>,
+    pub transformations: Option<String>,
+    pub reversible: Option<bool>,
+    pub time: Option<String>,
+}
+impl TryFrom<SpecificationFromDB> for Specification {
+    type Error = MyError;
+
+    fn try_from(specification_from_db: SpecificationFromDB) -> Result<Self, Self::Error> {
+        let transformations = serde_json::from_str(specification_from_db.transformations.unwrap().as_str())
+            .map_err(|_err| MyError::OperationError("The specification in the vault is not correct.".into()))?;
+        Ok(Specification {
+            name: specification_from_db.name,
+            version: specification_from_db.version,
+            transformations: Some(transformations),
+            reversible: specification_from_db.reversible,
+            time: specification_from_db.time,
+        })
+    }
+}
+/// the version of the specification chosen by the web app or the user
+#[derive(Deserialize, Debug, Clone)]
+pub struct SpecificationVersion {
+    pub version: Option<i32>,
+}
+
+impl Specification {
+    /// if the disguises of this specification could be recovered
+    pub fn is_reversible(&self) -> bool {
+        self.reversible.unwrap_or(true)
+    }
+
+    ///
+    /// get the transformations with the parameters filled in
+    /// only the whole string "{name}" is replaced,
+    /// so that the value keeps its json type and is never spliced into sql,
+    /// and the raw sql predicate and changes are kept as they are
+    ///
+    /// # Arguments
+    ///
+    /// * `parameters`: the values of the parameters by their names
+    ///
+    /// returns: Result<Vec<Transformation, Global>, MyError>
+    ///
+    pub fn instantiate(&self, parameters: &HashMap<String, Value>) -> Result<Vec<Transformation>, MyError> {
+        let transformations = self.transformations.as_ref()
+            .ok_or_else(|| MyError::OperationError("The specification has no transformations.".into()))?;
+        let mut res = vec![];
+        for transformation in transformations {
+            let mut filled = transformation.clone();
+            filled.predicate = None;
+            filled.changes = None;
+            let mut document = serde_json::to_value(&filled).unwrap();
+            fill_parameters(&mut document, parameters)?;
+            filled = serde_json::from_value(document)
+                .map_err(|_err| MyError::InvalidInput("The parameters of the specification are not correct.".into()))?;
+            //the raw sql strings could not be filled
+            if transformation.allow_raw() {
+                filled.predicate = transformation.predicate.clone();
+                filled.changes = transformation.changes.clone();
+            } else {
+                filled.predicate = fill_typed(&transformation.predicate, parameters)?;
+                filled.changes = fill_typed(&transformation.changes, parameters)?;
+            }
+            res.push(filled);
+        }
+        Ok(res)
+    }
+}
+
+/// fill the parameters into the predicate or the changes
+fn fill_typed<T: Serialize + DeserializeOwned>(
+    typed: &Option<T>,
+    parameters: &HashMap<String, Value>
+) -> Result<Option<T>, MyError> {
+    let mut document = serde_json::to_value(typed).unwrap();
+    fill_parameters(&mut document, parameters)?;
+    serde_json::from_value(document)
+        .map_err(|_err| MyError::InvalidInput("The parameters of the specification are not correct.".into()))
+}
+
+/// replace the strings of "{name}" in the json by the parameters
+fn fill_parameters(document: &mut Value, parameters: &HashMap<String, Value>) -> Result<(), MyError> {
+    match document {
+        Value::String(text) => {
+            if let Some(name) = text.strip_prefix('{').and_then(|text| text.strip_suffix('}')) {
+                let value = parameters.get(name)
+                    .ok_or_else(|| MyError::InvalidInput(format!("The parameter \"{}\" is required.", name)))?;
+                //the parameter could not change the structure of the transformation
+                let scalar = |value: &Value| !value.is_array() && !value.is_object();
+                let valid = match value {
+                    Value::Array(values) => values.iter().all(scalar),
+                    value => scalar(value),
+                };
+                if !valid {
+                    return Err(MyError::InvalidInput(format!("The parameter \"{}\" should be a value or a list of values.", name)));
+                }
+                *document = value.clone();
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                fill_parameters(value, parameters)?;
+            }
+        }
+        Value::Object(map) => {
+            for value in map.values_mut() {
+                fill_parameters(value, parameters)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use serde_json::json;
+    use crate::models::predicate::Predicate;
+    use crate::models::specification::Specification;
+
+    #[test]
+    fn instantiate_test() {
+        let specification: Specification = serde_json::from_value(json!({
+            "name": "userscrub",
+            "transformations": [
+                {"transform_type": "removal", "table_name": "contact_info",
+                    "predicate": {"column": "contact_id", "operator": "=", "value": "{user_id}"}},
+                {"transform_type": "decorrelation", "table_name": "review", "foreign_key": "contact_id",
+                    "predicate": {"column": "contact_id", "operator": "=", "value": "{user_id}"}}
+            ]
+        })).unwrap();
+        let parameters = HashMap::from([("user_id".to_string(), json!(19))]);
+        let transformations = specification.instantiate(&parameters).unwrap();
+        assert_eq!(transformations.len(), 2);
+        assert_eq!(transformations[1].predicate, Some(Predicate::eq("contact_id", json!(19))));
+        assert_eq!(transformations[1].foreign_key.as_deref(), Some("contact_id"));
+        assert!(specification.is_reversible());
+        //the missing parameter or the nested one is refused
+        assert!(specification.instantiate(&HashMap::new()).is_err());
+        let parameters = HashMap::from([("user_id".to_string(), json!({"or": []}))]);
+        assert!(specification.instantiate(&parameters).is_err());
+    }
+}
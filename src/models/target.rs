@@ -1,7 +1,10 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use crate::error::MyError;
+use crate::models::predicate::quote_identifier;
 /// the object in the web's database
 /// which could be user's info or the publications
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Target {
     pub primary_key_index: Option<usize>,
     pub fields: Option<Vec<Field>>
@@ -28,50 +31,240 @@ impl Target {
         }
         res
     }
-    /// get all the fields' names seperated by ", "
-    pub fn field_names(&self) -> Option<String> {
-        let mut res = String::new();
-        match &self.fields {
-            None => {
-                None
-            }
-            Some(fields) => {
-                for field in fields {
-                    res = res + ", " + field.field_name.as_ref().unwrap().as_str();
-                }
-                Some(res[2..].to_string())
-            }
+    /// get all the fields' quoted names seperated by ", "
+    pub fn field_names(&self) -> Result<String, MyError> {
+        let mut names = vec![];
+        for field in self.fields.as_ref().unwrap() {
+            names.push(quote_identifier(field.field_name.as_ref().unwrap())?);
         }
+        Ok(names.join(", "))
     }
-    /// get all the fields' values seperated by ", "
-    pub fn field_values(&self) -> Option<String> {
-        let mut res = String::new();
-        match &self.fields {
-            None => {
-                None
-            }
-            Some(fields) => {
-                for field in fields {
-                    let mut temp = String::new();
-                    if field.field_type.as_ref().unwrap().contains("int") {
-                        temp.push_str(field.field_value.as_ref().unwrap().as_str());
-                    }else {
-                        temp.push_str("\"");
-                        temp.push_str(field.field_value.as_ref().unwrap().as_str());
-                        temp.push_str("\"");
-                    }
-                    res.push_str(", ");
-                    res.push_str(temp.as_str());
-                }
-                Some(res[2..].to_string())
-            }
+    /// get all the fields' values in order
+    pub fn field_values(&self) -> Vec<FieldValue> {
+        self.fields.as_ref().unwrap()
+            .iter()
+            .map(|field| field.field_value.clone().unwrap_or(FieldValue::Null))
+            .collect()
+    }
+    /// get the values of the fields by their names
+    pub fn to_map(&self) -> Map<String, Value> {
+        self.fields.as_ref().unwrap()
+            .iter()
+            .map(|field| (
+                field.field_name.clone().unwrap(),
+                field.field_value.as_ref().map_or(Value::Null, |value| value.to_value())
+            ))
+            .collect()
+    }
+    /// get the names of the fields whose values are different in the other state
+    pub fn changed_fields(&self, other: &Target) -> Vec<String> {
+        let other = other.to_map();
+        self.to_map()
+            .into_iter()
+            .filter(|(name, value)| other.get(name) != Some(value))
+            .map(|(name, _)| name)
+            .collect()
+    }
+    /// get the self-describing json document stored in the vault
+    pub fn to_vault(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+    /// read the json document stored in the vault
+    pub fn from_vault(document: &str) -> Option<Target> {
+        serde_json::from_str(document).ok()
+    }
+    ///
+    /// read the old format stored in the vault,
+    /// which is the values seperated by ", "
+    /// and the non-integer values are wrapped in double quotes
+    ///
+    /// # Arguments
+    ///
+    /// * `values`: the old format of the values
+    /// * `field_names`: the names of the table's fields in order
+    /// * `field_types`: the types of the table's fields in order
+    /// * `primary_key_index`: the index of the primary key
+    ///
+    /// returns: Result<Target, MyError>
+    ///
+    pub fn from_legacy(
+        values: &str,
+        field_names: &[String],
+        field_types: &[String],
+        primary_key_index: usize
+    ) -> Result<Target, MyError> {
+        let values = split_legacy_values(values);
+        if values.len() != field_names.len() {
+            return Err(MyError::OperationError(
+                "The original values in the vault do not match the table.".to_string()
+            ));
         }
+        let mut fields = vec![];
+        for (i, (value, quoted)) in values.into_iter().enumerate() {
+            let field_value = if quoted {
+                FieldValue::Text(value)
+            } else if value == "NULL" {
+                FieldValue::Null
+            } else if let Some(value) = value.strip_prefix("FROM_BASE64(\"").and_then(|value| value.strip_suffix("\")")) {
+                FieldValue::Binary(value.to_string())
+            } else if let Ok(value) = value.parse::<i64>() {
+                FieldValue::Int(value)
+            } else if let Ok(value) = value.parse::<u64>() {
+                FieldValue::UnsignedInt(value)
+            } else {
+                FieldValue::Decimal(value)
+            };
+            fields.push(Field {
+                field_name: Some(field_names[i].clone()),
+                field_type: Some(field_types[i].clone()),
+                field_value: Some(field_value)
+            });
+        }
+        Ok(Target {
+            primary_key_index: Some(primary_key_index),
+            fields: Some(fields)
+        })
     }
 }
 /// the field of the web' database table (object's field)
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Field {
     pub field_name: Option<String>,
     pub field_type: Option<String>,
-    pub field_value: Option<String>
-}
\ No newline at end of file
+    pub field_value: Option<FieldValue>
+}
+/// the typed value of a field
+/// which covers all the mysql column types,
+/// the binary value is encoded by base64
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum FieldValue {
+    Null,
+    Int(i64),
+    UnsignedInt(u64),
+    Float(f64),
+    Decimal(String),
+    Bool(bool),
+    Bit(u64),
+    Text(String),
+    Binary(String),
+    Date(String),
+    Time(String),
+    DateTime(String),
+    Json(String),
+}
+impl FieldValue {
+    /// get the json value to compare in the predicates
+    pub fn to_value(&self) -> Value {
+        match self {
+            FieldValue::Null => Value::Null,
+            FieldValue::Int(value) => Value::from(*value),
+            FieldValue::UnsignedInt(value) | FieldValue::Bit(value) => Value::from(*value),
+            FieldValue::Float(value) => Value::from(*value),
+            FieldValue::Bool(value) => Value::from(*value),
+            FieldValue::Decimal(value)
+            | FieldValue::Text(value)
+            | FieldValue::Binary(value)
+            | FieldValue::Date(value)
+            | FieldValue::Time(value)
+            | FieldValue::DateTime(value)
+            | FieldValue::Json(value) => Value::from(value.as_str()),
+        }
+    }
+}
+/// split the old format of the values stored in the vault
+/// the quoted values are unescaped and marked as quoted
+fn split_legacy_values(values: &str) -> Vec<(String, bool)> {
+    let mut res = vec![];
+    let mut chars = values.chars().peekable();
+    while chars.peek().is_some() {
+        let mut value = String::new();
+        let quoted = chars.peek() == Some(&'"');
+        if quoted {
+            chars.next();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => match chars.next() {
+                        Some('0') => value.push('\0'),
+                        Some('n') => value.push('\n'),
+                        Some('r') => value.push('\r'),
+                        Some('Z') => value.push('\x1a'),
+                        Some(c) => value.push(c),
+                        None => value.push('\\'),
+                    },
+                    //the closing quote is followed by ", " or the end
+                    '"' if chars.peek().is_none() || chars.clone().take(2).eq(", ".chars()) => break,
+                    _ => value.push(c),
+                }
+            }
+        } else {
+            while let Some(c) = chars.peek().copied() {
+                if c == ',' && chars.clone().take(2).eq(", ".chars()) {
+                    break;
+                }
+                value.push(c);
+                chars.next();
+            }
+        }
+        //skip the separator
+        chars.next();
+        chars.next();
+        res.push((value, quoted));
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::target::*;
+
+    #[test]
+    fn vault_document_test() {
+        let target = Target {
+            primary_key_index: Some(0),
+            fields: Some(vec![
+                Field {
+                    field_name: Some("contact_id".into()),
+                    field_type: Some("int".into()),
+                    field_value: Some(FieldValue::Int(19))
+                },
+                Field {
+                    field_name: Some("name".into()),
+                    field_type: Some("text".into()),
+                    field_value: Some(FieldValue::Text("Bea, \"the\" \\ one".into()))
+                },
+                Field {
+                    field_name: Some("avatar".into()),
+                    field_type: Some("blob".into()),
+                    field_value: Some(FieldValue::Null)
+                },
+            ])
+        };
+        let document = Target::from_vault(&target.to_vault()).unwrap();
+        assert_eq!(document.field_values(), target.field_values());
+        assert_eq!(document.field_names().unwrap(), "`contact_id`, `name`, `avatar`");
+    }
+
+    #[test]
+    fn changed_fields_test() {
+        let names = vec!["contact_id".to_string(), "name".to_string(), "email".to_string()];
+        let types = vec!["int".to_string(), "varchar(45)".to_string(), "varchar(45)".to_string()];
+        let before = Target::from_legacy("19, \"Bea\", \"bea@mail.com\"", &names, &types, 0).unwrap();
+        let after = Target::from_legacy("19, \"anonymous\", \"bea@mail.com\"", &names, &types, 0).unwrap();
+        assert_eq!(before.changed_fields(&after), vec!["name".to_string()]);
+        assert_eq!(after.to_map()["name"], "anonymous");
+    }
+
+    #[test]
+    fn from_legacy_test() {
+        let names = vec!["contact_id".to_string(), "name".to_string(), "email".to_string()];
+        let types = vec!["int".to_string(), "varchar(45)".to_string(), "varchar(45)".to_string()];
+        let target = Target::from_legacy("19, \"Bea, B\", \"bea@mail.com\"", &names, &types, 0).unwrap();
+        assert_eq!(target.field_values(), vec![
+            FieldValue::Int(19),
+            FieldValue::Text("Bea, B".into()),
+            FieldValue::Text("bea@mail.com".into()),
+        ]);
+        assert!(Target::from_legacy("19, \"Bea\"", &names, &types, 0).is_err());
+    }
+}
@@ -1,16 +1,23 @@
 use actix_web::web;
 use actix_web::web::Json;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use crate::models::predicate::{Changes, Predicate};
 
 /// which is the fundamental operation of the required disguise
 /// three types are "removal", "modification", "decorrelation"
-#[derive(Deserialize, Debug, Clone)]
+/// the predicate and changes could only be raw sql strings
+/// when "raw_sql" is true and the server allows it
+/// the decorrelated rows point to their own new placeholders
+/// when "unique_placeholder" is true
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Transformation {
     pub transform_type: Option<String>,
     pub table_name: Option<String>,
-    pub predicate: Option<String>,
+    pub predicate: Option<Predicate>,
     pub foreign_key: Option<String>,
-    pub changes: Option<String>
+    pub changes: Option<Changes>,
+    pub raw_sql: Option<bool>,
+    pub unique_placeholder: Option<bool>
 }
 impl Transformation {
     /// get a new empty transformation
@@ -20,9 +27,19 @@ impl Transformation {
             table_name: None,
             predicate: None,
             foreign_key: None,
-            changes: None
+            changes: None,
+            raw_sql: None,
+            unique_placeholder: None
         }
     }
+    /// if the raw sql strings are accepted in this transformation
+    pub fn allow_raw(&self) -> bool {
+        self.raw_sql.unwrap_or(false)
+    }
+    /// if every decorrelated row gets its own placeholder
+    pub fn is_unique_placeholder(&self) -> bool {
+        self.unique_placeholder.unwrap_or(false)
+    }
 }
 
 impl From<web::Json<Transformation>> for Transformation {
@@ -32,7 +49,9 @@ impl From<web::Json<Transformation>> for Transformation {
             table_name: json_transform.table_name.clone(),
             predicate: json_transform.predicate.clone(),
             foreign_key: json_transform.foreign_key.clone(),
-            changes: json_transform.changes.clone()
+            changes: json_transform.changes.clone(),
+            raw_sql: json_transform.raw_sql,
+            unique_placeholder: json_transform.unique_placeholder
         }
     }
-}
\ No newline at end of file
+}
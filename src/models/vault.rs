@@ -1,68 +1,508 @@
 use actix_web::web::Json;
 use sqlx::FromRow;
-use serde::Deserialize;
-use crate::models::placeholder::GeneratePlaceHolder;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use crate::models::crypto::{is_encrypted, SealedRecoveryKey};
+use crate::models::placeholder::{GeneratePlaceHolder, PlaceholderInfo, PlaceholderMapping};
+use crate::models::recovery::StackedFunction;
+use crate::models::target::Target;
 
 /// which from the web app or the user input
+/// the vault is only recovered with the user's secret
+/// if the user gives the secret or asks for one by "recovery"
 #[derive(Deserialize, Debug, Clone)]
 pub struct GenerateVault {
     pub vault_id: Option<String>,
     pub email: Option<String>,
-    pub generate_placeholder: Option<GeneratePlaceHolder>
+    pub generate_placeholder: Option<GeneratePlaceHolder>,
+    pub secret: Option<String>,
+    pub recovery: Option<bool>
 }
 impl From<Json<GenerateVault>> for GenerateVault {
     fn from(vault_json: Json<GenerateVault>) -> Self {
         GenerateVault {
             vault_id: vault_json.vault_id.clone(),
             email: vault_json.email.clone(),
-            generate_placeholder: vault_json.generate_placeholder.clone()
+            generate_placeholder: vault_json.generate_placeholder.clone(),
+            secret: vault_json.secret.clone(),
+            recovery: vault_json.recovery
         }
     }
 }
+/// the generated vault returned to the web app or the user
+/// the generated recovery secret is only returned once, and it is never stored
+#[derive(Serialize, Debug, Clone)]
+pub struct GeneratedVault {
+    pub vault_id: Option<String>,
+    pub recovery_secret: Option<String>,
+    pub message: Option<String>
+}
 /// which from or to the database
 #[derive(Debug, Clone, FromRow)]
 pub struct Vault {
     pub vault_id: Option<String>,
     pub email: Option<String>,
-    pub placeholder_info: Option<String>
+    pub placeholder_info: Option<String>,
+    pub recovery_public_key: Option<String>,
+    pub recovery_private_key: Option<String>,
+    pub recovery_salt: Option<String>
+}
+impl Vault {
+    /// get the recovery key if the vault is only recovered with the user's secret
+    pub fn recovery_key(&self) -> Option<SealedRecoveryKey> {
+        match (&self.recovery_public_key, &self.recovery_private_key, &self.recovery_salt) {
+            (Some(public_key), Some(private_key), Some(salt)) => Some(SealedRecoveryKey {
+                public_key: public_key.clone(),
+                private_key: private_key.clone(),
+                salt: salt.clone()
+            }),
+            _ => None,
+        }
+    }
 }
 /// which from or to the database
 /// stored in the vaults
 #[derive(Debug, Clone, FromRow)]
 pub struct DisguiseFromDB {
     pub disguise_id: Option<i32>,
+    pub public_id: Option<String>,
     pub time: Option<String>,
     pub vault_id: Option<String>,
-    pub disguise_type: Option<String>
+    pub disguise_type: Option<String>,
+    pub status: Option<String>
 }
 /// applied disguise stored in database
+/// with the placeholders generated for its decorrelated rows
 #[derive(Debug, Clone)]
 pub struct Disguise {
     pub disguise_id: Option<i32>,
+    pub public_id: Option<String>,
     pub time: Option<String>,
     pub vault_id: Option<String>,
     pub disguise_type: Option<String>,
-    pub functions: Option<Vec<Function>>
+    /// "applied", "recovering", "partially_recovered" when some of its functions have been recovered,
+    /// "recovered" or "cleared"
+    pub status: Option<String>,
+    pub functions: Option<Vec<Function>>,
+    pub placeholders: Option<Vec<PlaceholderMapping>>
 }
 impl From<DisguiseFromDB> for Disguise {
     fn from(disguise_from_db: DisguiseFromDB) -> Self {
         Disguise {
             disguise_id: disguise_from_db.disguise_id,
+            public_id: disguise_from_db.public_id,
             time: disguise_from_db.time,
             vault_id: disguise_from_db.vault_id,
             disguise_type: disguise_from_db.disguise_type,
-            functions: None
+            status: disguise_from_db.status,
+            functions: None,
+            placeholders: None
         }
     }
 }
+impl Disguise {
+    ///
+    /// get the part of the disguise with the functions on the tables or with the ids,
+    /// and the placeholders generated for the rows of these functions
+    /// all the functions are selected without the tables and the ids
+    ///
+    /// # Arguments
+    ///
+    /// * `tables`: the names of the selected tables
+    /// * `function_ids`: the ids of the selected functions
+    ///
+    /// returns: Disguise
+    ///
+    pub fn select(&self, tables: Option<&[String]>, function_ids: Option<&[i32]>) -> Disguise {
+        let selected = |function: &Function| {
+            let by_table = tables.is_some_and(|tables| function.table_name.as_ref().is_some_and(|name| tables.contains(name)));
+            let by_id = function_ids.is_some_and(|ids| function.function_id.is_some_and(|id| ids.contains(&id)));
+            (tables.is_none() && function_ids.is_none()) || by_table || by_id
+        };
+        let functions: Vec<Function> = self.functions.iter().flatten().filter(|function| selected(function)).cloned().collect();
+        let placeholders = self.placeholders.iter().flatten()
+            .filter(|placeholder| functions.iter().any(|function| {
+                function.table_name == placeholder.table_name && function.predicate == placeholder.predicate
+            }))
+            .cloned()
+            .collect();
+        Disguise {
+            functions: Some(functions),
+            placeholders: Some(placeholders),
+            ..self.clone()
+        }
+    }
+
+    ///
+    /// split the functions of the disguise which have not been recovered
+    /// into the steps of the recovery, from the last applied one,
+    /// every step has one function with its placeholders
+    ///
+    /// returns: Vec<Disguise, Global>
+    ///
+    pub fn steps(&self) -> Vec<Disguise> {
+        self.functions.iter().flatten().rev()
+            .filter(|function| function.recovered_time.is_none())
+            .map(|function| {
+                let placeholders = self.placeholders.iter().flatten()
+                    .filter(|placeholder| function.table_name == placeholder.table_name && function.predicate == placeholder.predicate)
+                    .cloned()
+                    .collect();
+                Disguise {
+                    functions: Some(vec![function.clone()]),
+                    placeholders: Some(placeholders),
+                    ..self.clone()
+                }
+            })
+            .collect()
+    }
+
+    /// if the function is one of the functions of the disguise
+    pub fn has_function(&self, function_id: Option<i32>) -> bool {
+        self.functions.iter().flatten().any(|function| function.function_id == function_id)
+    }
+}
+/// the disguise is "recovering" until all its selected functions are recovered
+/// the recovered and the cleared disguises are only kept as the history
+pub fn is_active(status: Option<&str>) -> bool {
+    !matches!(status, Some("recovered") | Some("cleared"))
+}
 /// which from or to the database
 /// make up the disguise
 #[derive(Debug, Clone, FromRow)]
 pub struct Function {
+    pub function_id: Option<i32>,
     pub disguise_id: Option<i32>,
     pub function_type: Option<String>,
     pub table_name: Option<String>,
     pub predicate: Option<String>,
     pub original: Option<String>,
-    pub updated: Option<String>
-}
\ No newline at end of file
+    pub updated: Option<String>,
+    /// the time the function was recovered, its original and updated state are erased then
+    pub recovered_time: Option<String>
+}
+/// the applied disguise returned to the web app or the user
+#[derive(Serialize, Debug, Clone)]
+pub struct AppliedDisguise {
+    pub disguise_id: Option<String>,
+    pub row_counts: Option<Vec<RowCount>>,
+    pub message: Option<String>
+}
+/// the number of rows changed by one transformation
+#[derive(Serialize, Debug, Clone)]
+pub struct RowCount {
+    pub transform_type: Option<String>,
+    pub table_name: Option<String>,
+    pub rows: Option<u64>
+}
+/// the preview of a disguise returned to the web app or the user
+/// nothing of it has been written into the databases
+#[derive(Serialize, Debug, Clone)]
+pub struct DisguisePreview {
+    pub disguise_name: Option<String>,
+    pub tables: Option<Vec<TablePreview>>,
+    pub message: Option<String>
+}
+/// the rows of one table which one transformation would change
+#[derive(Serialize, Debug, Clone)]
+pub struct TablePreview {
+    pub transform_type: Option<String>,
+    pub table_name: Option<String>,
+    pub primary_key_name: Option<String>,
+    pub rows: Option<u64>,
+    pub targets: Option<Vec<TargetPreview>>
+}
+/// the state of one row before and after the disguise
+/// the removed row has no state after the disguise,
+/// and neither has the row of the raw changes which are not rendered
+#[derive(Serialize, Debug, Clone)]
+pub struct TargetPreview {
+    pub primary_key: Option<Value>,
+    pub before: Option<Map<String, Value>>,
+    pub after: Option<Map<String, Value>>,
+    pub changed_fields: Option<Vec<String>>
+}
+impl DisguisePreview {
+    /// count the rows of every table in the preview without their values,
+    /// which is recorded in the audit log
+    pub fn row_counts(&self) -> Vec<RowCount> {
+        self.tables.as_deref().unwrap_or_default().iter().map(|table| RowCount {
+            transform_type: table.transform_type.clone(),
+            table_name: table.table_name.clone(),
+            rows: table.rows
+        }).collect()
+    }
+}
+/// the vault returned to the web app or the user
+#[derive(Serialize, Debug, Clone)]
+pub struct VaultInfo {
+    pub vault_id: Option<String>,
+    pub email: Option<String>,
+    pub placeholder_info: Option<PlaceholderInfo>,
+    pub secret_required: Option<bool>
+}
+impl From<Vault> for VaultInfo {
+    fn from(vault: Vault) -> Self {
+        VaultInfo {
+            secret_required: Some(vault.recovery_key().is_some()),
+            vault_id: vault.vault_id,
+            email: vault.email,
+            placeholder_info: vault.placeholder_info
+                .and_then(|placeholder_info| serde_json::from_str(placeholder_info.as_str()).ok())
+        }
+    }
+}
+/// the query to find the vault by its owner's email
+#[derive(Deserialize, Debug, Clone)]
+pub struct VaultQuery {
+    pub email: Option<String>
+}
+/// the applied disguise in the list of the vault's disguises
+#[derive(Serialize, Debug, Clone)]
+pub struct DisguiseSummary {
+    pub disguise_id: Option<String>,
+    pub disguise_type: Option<String>,
+    pub time: Option<String>,
+    pub status: Option<String>,
+    pub functions: Option<Vec<FunctionCount>>
+}
+/// the number of the functions of one type on one table
+#[derive(Serialize, Debug, Clone, FromRow)]
+pub struct FunctionCount {
+    pub table_name: Option<String>,
+    pub function_type: Option<String>,
+    pub count: Option<i64>
+}
+/// the applied disguise with all its functions
+#[derive(Serialize, Debug, Clone)]
+pub struct DisguiseDetail {
+    pub disguise_id: Option<String>,
+    pub disguise_type: Option<String>,
+    pub time: Option<String>,
+    pub status: Option<String>,
+    pub history: Option<Vec<DisguiseState>>,
+    /// the functions whose rows have been changed again by the later disguises
+    pub stacked: Option<Vec<StackedFunction>>,
+    pub functions: Option<Vec<FunctionRecord>>
+}
+impl From<Disguise> for DisguiseDetail {
+    fn from(disguise: Disguise) -> Self {
+        DisguiseDetail {
+            disguise_id: disguise.public_id,
+            disguise_type: disguise.disguise_type,
+            time: disguise.time,
+            status: disguise.status,
+            history: None,
+            stacked: None,
+            functions: disguise.functions
+                .map(|functions| functions.into_iter().map(FunctionRecord::from).collect())
+        }
+    }
+}
+/// the state the disguise entered at the time
+#[derive(Serialize, Debug, Clone, FromRow)]
+pub struct DisguiseState {
+    pub status: Option<String>,
+    pub time: Option<String>
+}
+/// the function of the disguise returned to the web app or the user
+/// the json documents are parsed, and the old formats are kept as strings
+/// the encrypted states are not returned
+/// the function could be recovered alone by its id
+#[derive(Serialize, Debug, Clone)]
+pub struct FunctionRecord {
+    pub function_id: Option<i32>,
+    pub function_type: Option<String>,
+    pub table_name: Option<String>,
+    pub predicate: Option<Value>,
+    pub original: Option<Value>,
+    pub updated: Option<Value>,
+    pub recovered_time: Option<String>
+}
+impl From<Function> for FunctionRecord {
+    fn from(function: Function) -> Self {
+        let parse = |document: String| serde_json::from_str(document.as_str()).unwrap_or(Value::String(document));
+        let plain = |document: &String| !is_encrypted(document);
+        FunctionRecord {
+            function_id: function.function_id,
+            function_type: function.function_type,
+            table_name: function.table_name,
+            predicate: function.predicate.map(parse),
+            original: function.original.filter(plain).map(parse),
+            updated: function.updated.filter(plain).map(parse),
+            recovered_time: function.recovered_time
+        }
+    }
+}
+/// the proof that a cleared disguise has been erased
+/// the fingerprint identifies its destroyed data key,
+/// and the disguise stored as plaintext or by the vault's key before the data keys has no fingerprint
+/// the method is "crypto_shred" only if the data key is destroyed from the key store,
+/// or "key_deleted" if the data key could still be in the backups of the vault database
+#[derive(Serialize, Debug, Clone, FromRow)]
+pub struct Tombstone {
+    pub public_id: Option<String>,
+    pub vault_id: Option<String>,
+    pub disguise_type: Option<String>,
+    pub functions: Option<i32>,
+    pub key_fingerprint: Option<String>,
+    pub method: Option<String>,
+    pub time: Option<String>
+}
+/// the disguises cleared in the vault transaction
+/// the data keys of the shredded disguises are destroyed from the key store
+/// only after the vault transaction is committed,
+/// and the rows deleted from the application are inserted again if it fails to commit
+#[derive(Debug, Clone, Default)]
+pub struct ClearedDisguises {
+    pub public_ids: Vec<String>,
+    pub shredded_ids: Vec<i32>,
+    /// the table and the row deleted from the application, in the order of the deletion
+    pub deleted_rows: Vec<(String, Target)>
+}
+/// the recovered or cleared disguises returned to the web app or the user
+#[derive(Serialize, Debug, Clone)]
+pub struct HandledDisguises {
+    pub disguise_ids: Option<Vec<String>>,
+    pub message: Option<String>
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use crate::models::crypto::MasterKey;
+    use crate::models::placeholder::PlaceholderMapping;
+    use crate::models::vault::{is_active, Disguise, DisguisePreview, Function, FunctionRecord, TablePreview, TargetPreview};
+
+    #[test]
+    fn function_record_test() {
+        let function = Function {
+            function_id: Some(1),
+            disguise_id: Some(1),
+            function_type: Some("removal".into()),
+            table_name: Some("contact_info".into()),
+            predicate: Some(r#"{"column":"contact_id","operator":"=","value":19}"#.into()),
+            original: Some("contact_id=19".into()),
+            updated: None,
+            recovered_time: None
+        };
+        let record = FunctionRecord::from(function);
+        assert_eq!(record.predicate, Some(json!({"column": "contact_id", "operator": "=", "value": 19})));
+        //the old format is kept as the string
+        assert_eq!(record.original, Some(Value::String("contact_id=19".into())));
+        assert_eq!(record.updated, None);
+        //the encrypted states are not returned
+        let key = MasterKey::from_base64("MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=").unwrap().vault_key("1");
+        let function = Function {
+            function_id: Some(2),
+            disguise_id: Some(1),
+            function_type: Some("modification".into()),
+            table_name: Some("contact_info".into()),
+            predicate: Some(r#"{"column":"contact_id","operator":"=","value":19}"#.into()),
+            original: Some(key.encrypt(r#"{"primary_key_index":0}"#)),
+            updated: Some(key.encrypt(r#"{"primary_key_index":0}"#)),
+            recovered_time: None
+        };
+        let record = FunctionRecord::from(function);
+        assert_eq!(record.original, None);
+        assert_eq!(record.updated, None);
+    }
+
+    #[test]
+    fn preview_row_counts_test() {
+        let preview = DisguisePreview {
+            disguise_name: Some("gdpr_removal".into()),
+            tables: Some(vec![TablePreview {
+                transform_type: Some("removal".into()),
+                table_name: Some("contact_info".into()),
+                primary_key_name: Some("contact_id".into()),
+                rows: Some(1),
+                targets: Some(vec![TargetPreview {
+                    primary_key: Some(json!(19)),
+                    before: json!({"contact_id": 19, "email": "bea@mail.com"}).as_object().cloned(),
+                    after: None,
+                    changed_fields: None
+                }])
+            }]),
+            message: None
+        };
+        //only the counts are kept without the rows' values
+        let summary = serde_json::to_string(&preview.row_counts()).unwrap();
+        assert_eq!(summary, r#"[{"transform_type":"removal","table_name":"contact_info","rows":1}]"#);
+    }
+
+    #[test]
+    fn select_disguise_test() {
+        let function = |function_id: i32, table_name: &str| Function {
+            function_id: Some(function_id),
+            disguise_id: Some(1),
+            function_type: Some("decorrelation".into()),
+            table_name: Some(table_name.into()),
+            predicate: Some(format!(r#"{{"column":"id","operator":"=","value":{}}}"#, function_id)),
+            original: None,
+            updated: None,
+            recovered_time: None
+        };
+        let disguise = Disguise {
+            disguise_id: Some(1),
+            public_id: Some("a".into()),
+            time: None,
+            vault_id: Some("19".into()),
+            disguise_type: Some("anonymize".into()),
+            status: None,
+            functions: Some(vec![function(1, "review"), function(2, "review"), function(3, "contact_info")]),
+            placeholders: Some(vec![PlaceholderMapping {
+                table_name: Some("review".into()),
+                predicate: function(2, "review").predicate,
+                placeholder_table: Some("contact_info".into()),
+                placeholder_predicate: Some(r#"{"column":"contact_id","operator":"=","value":100}"#.into())
+            }])
+        };
+        let part = disguise.select(Some(&["contact_info".to_string()]), None);
+        assert_eq!(part.functions.as_ref().unwrap().len(), 1);
+        assert!(part.placeholders.as_ref().unwrap().is_empty());
+        //the placeholder is recovered with the row it was generated for
+        let part = disguise.select(None, Some(&[2]));
+        assert_eq!(part.functions.as_ref().unwrap()[0].function_id, Some(2));
+        assert_eq!(part.placeholders.as_ref().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn disguise_steps_test() {
+        let function = |function_id: i32| Function {
+            function_id: Some(function_id),
+            disguise_id: Some(1),
+            function_type: Some("decorrelation".into()),
+            table_name: Some("review".into()),
+            predicate: Some(format!(r#"{{"column":"id","operator":"=","value":{}}}"#, function_id)),
+            original: None,
+            updated: None,
+            recovered_time: None
+        };
+        let mut recovered = function(3);
+        recovered.recovered_time = Some("2022-07-19 00:00:00".into());
+        let disguise = Disguise {
+            disguise_id: Some(1),
+            public_id: Some("a".into()),
+            time: None,
+            vault_id: Some("19".into()),
+            disguise_type: Some("anonymize".into()),
+            status: Some("recovering".into()),
+            functions: Some(vec![function(1), function(2), recovered]),
+            placeholders: Some(vec![PlaceholderMapping {
+                table_name: Some("review".into()),
+                predicate: function(1).predicate,
+                placeholder_table: Some("contact_info".into()),
+                placeholder_predicate: Some(r#"{"column":"contact_id","operator":"=","value":100}"#.into())
+            }])
+        };
+        //the recovery is resumed from the last function which has not been recovered
+        let steps = disguise.steps();
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[0].functions.as_ref().unwrap()[0].function_id, Some(2));
+        assert!(steps[0].placeholders.as_ref().unwrap().is_empty());
+        assert_eq!(steps[1].placeholders.as_ref().unwrap().len(), 1);
+        assert!(is_active(disguise.status.as_deref()));
+        assert!(!is_active(Some("recovered")));
+    }
+}
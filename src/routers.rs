@@ -1,19 +1,70 @@
 use actix_web::*;
+use crate::handlers::audit::{get_audit_entries, verify_audit_log};
 use crate::handlers::disguise::*;
-use crate::handlers::vault::generate_vault;
+use crate::handlers::export::{export_table, export_vault};
+use crate::handlers::placeholder::{collect_placeholders, placeholder_report};
+use crate::handlers::schema::discover;
+use crate::handlers::specification::*;
+use crate::handlers::vault::*;
+use crate::middleware::RequireRole;
+use crate::models::api_key::Role;
 
 /// all the vault interfaces
+/// the support role only inspects the vaults, and the app role generates them
 pub fn vault_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(web::scope("/vault")
-        .route("/generate", web::post().to(generate_vault)));
+        .route("", web::get().to(find_vault).wrap(RequireRole(Role::Support)))
+        .route("/generate", web::post().to(generate_vault).wrap(RequireRole(Role::App)))
+        .route("/export", web::get().to(export_vault).wrap(RequireRole(Role::Support)))
+        .route("/export/{table_name}", web::get().to(export_table).wrap(RequireRole(Role::Support)))
+        .route("/{vault_id}", web::get().to(get_vault).wrap(RequireRole(Role::Support)))
+        .route("/{vault_id}/disguises", web::get().to(list_disguises).wrap(RequireRole(Role::Support)))
+        .route("/{vault_id}/tombstones", web::get().to(get_tombstones).wrap(RequireRole(Role::Support)))
+        .route("/{vault_id}/disguises/{disguise_id}", web::get().to(get_disguise).wrap(RequireRole(Role::Support))));
 }
 
 /// all the disguise interfaces
+/// only the admin role clears the vaults, and the support role could preview the recoveries
 pub fn disguise_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(web::scope("/disguise")
-        .route("/userscrub", web::post().to(scrub_user))
-        .route("/anonymize", web::post().to(anonymize))
-        .route("/expiration", web::post().to(expiration))
-        .route("/clearvault", web::post().to(clear_vault))
-        .route("/recover", web::post().to(recover_disguise)));
+        .route("/apply", web::post().to(apply_disguise).wrap(RequireRole(Role::App)))
+        .route("/userscrub", web::post().to(scrub_user).wrap(RequireRole(Role::App)))
+        .route("/anonymize", web::post().to(anonymize).wrap(RequireRole(Role::App)))
+        .route("/expiration", web::post().to(expiration).wrap(RequireRole(Role::App)))
+        .route("/clearvault", web::post().to(clear_vault).wrap(RequireRole(Role::Admin)))
+        .route("/recover", web::post().to(recover_disguise).wrap(RequireRole(Role::App)))
+        .route("/recover/preview", web::post().to(preview_recovery).wrap(RequireRole(Role::Support))));
+}
+
+/// all the specification interfaces
+/// only the admin role changes the specifications
+pub fn specification_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope("/specification")
+        .route("", web::post().to(create_specification).wrap(RequireRole(Role::Admin)))
+        .route("", web::get().to(get_specifications).wrap(RequireRole(Role::Support)))
+        .route("/{name}", web::get().to(get_specification).wrap(RequireRole(Role::Support)))
+        .route("/{name}", web::put().to(update_specification).wrap(RequireRole(Role::Admin)))
+        .route("/{name}", web::delete().to(delete_specification).wrap(RequireRole(Role::Admin))));
+}
+
+/// all the schema interfaces
+pub fn schema_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope("/schema")
+        .route("/discover", web::post().to(discover).wrap(RequireRole(Role::App))));
+}
+
+/// all the placeholder interfaces
+/// only the admin role collects the placeholders
+pub fn placeholder_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope("/placeholder")
+        .route("", web::get().to(placeholder_report).wrap(RequireRole(Role::Support)))
+        .route("/collect", web::post().to(collect_placeholders).wrap(RequireRole(Role::Admin))));
+}
+
+/// all the audit interfaces
+/// only the admin role reads the audit log
+pub fn audit_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope("/audit")
+        .route("", web::get().to(get_audit_entries).wrap(RequireRole(Role::Admin)))
+        .route("/verify", web::get().to(verify_audit_log).wrap(RequireRole(Role::Admin))));
 }
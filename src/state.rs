@@ -1,7 +1,18 @@
 use sqlx::mysql::MySqlPool;
+use crate::models::crypto::{KeyStore, MasterKey};
 
 /// the state of the server
 pub struct AppState {
     pub vault_db: MySqlPool,
     pub target_db: MySqlPool,
-}
\ No newline at end of file
+    /// if the transformations could opt in to the raw sql strings
+    pub allow_raw_sql: bool,
+    /// the key to encrypt the original rows in the vaults,
+    /// they are stored as plaintext without it
+    pub master_key: Option<MasterKey>,
+    /// the local store of the data keys outside the vault database,
+    /// they are kept in the vault database without it
+    pub key_store: Option<KeyStore>,
+    /// if the requests require the api keys of their roles
+    pub api_auth: bool,
+}